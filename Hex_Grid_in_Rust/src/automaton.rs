@@ -0,0 +1,202 @@
+use crate::hex_grid::HexGrid;
+use crate::hex_map::HexMap;
+use crate::layers::HexLayer;
+
+/// How the hexes of an `Automaton` change from one generation to the next.
+///
+/// Implemented by `LifeRule`, and by any closure taking the current state of a
+/// hex and the states of its neighbors.
+pub trait Rule<T> {
+    /// Returns the next state of a hex in state `cell`. `neighbors` are the
+    /// states of its neighbors, in `Direction::ALL` order, skipping those out
+    /// of the map and those cut off by walls (so there may be less than six).
+    fn next(&mut self, cell: &T, neighbors: &[&T]) -> T;
+}
+
+impl<T, F> Rule<T> for F
+where
+    F: FnMut(&T, &[&T]) -> T,
+{
+    fn next(&mut self, cell: &T, neighbors: &[&T]) -> T {
+        self(cell, neighbors)
+    }
+}
+
+/// A rule for two-state automata, like Conway's Game of Life but on hexes:
+/// dead hexes (`false`) come alive with some numbers of live neighbors, and
+/// live ones survive with some others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LifeRule {
+    birth: [bool; 7],
+    survival: [bool; 7],
+}
+
+impl LifeRule {
+    /// Creates a rule where dead hexes with a number of live neighbors in
+    /// `birth` come alive, and live hexes with a number of live neighbors in
+    /// `survival` stay alive. Numbers above six are ignored.
+    ///
+    /// For example, `LifeRule::new(&[2], &[3, 4])` is the usual "B2/S34".
+    pub fn new(birth: &[usize], survival: &[usize]) -> Self {
+        let mut rule = Self {
+            birth: [false; 7],
+            survival: [false; 7],
+        };
+        for &n in birth.iter().filter(|&&n| n <= 6) {
+            rule.birth[n] = true;
+        }
+        for &n in survival.iter().filter(|&&n| n <= 6) {
+            rule.survival[n] = true;
+        }
+        rule
+    }
+}
+
+impl Rule<bool> for LifeRule {
+    fn next(&mut self, cell: &bool, neighbors: &[&bool]) -> bool {
+        let alive = neighbors.iter().filter(|&&&n| n).count();
+        if *cell {
+            self.survival[alive]
+        } else {
+            self.birth[alive]
+        }
+    }
+}
+
+/// A cellular automaton over the hexes of a grid: one value of type `T` per
+/// hex, all updated at once by a `Rule`.
+///
+/// The next generation is written into a second layer, which is then swapped
+/// with the first, so that every hex sees the previous generation of its
+/// neighbors whatever order the hexes are updated in.
+pub struct Automaton<T> {
+    cells: HexLayer<T>,
+    next: HexLayer<T>,
+    cut_by_walls: bool,
+}
+
+impl<T: Clone> Automaton<T> {
+    /// Creates an automaton starting from the given states, for grids with the
+    /// same layout as `cells`. Walls don't matter until `set_cut_by_walls()`.
+    pub fn new(cells: HexLayer<T>) -> Self {
+        Self {
+            next: cells.clone(),
+            cells,
+            cut_by_walls: false,
+        }
+    }
+
+    /// Sets whether walls cut neighbors off: when set, a neighbor only counts
+    /// if one could move from it into the hex (so open doors don't cut, and
+    /// one-way passages only let things through their way).
+    pub fn set_cut_by_walls(&mut self, cut_by_walls: bool) {
+        self.cut_by_walls = cut_by_walls;
+    }
+
+    /// Returns the current states.
+    pub fn cells(&self) -> &HexLayer<T> {
+        &self.cells
+    }
+
+    /// Computes the next generation with `rule`, using `hex_grid` for the
+    /// neighbors and walls of each hex.
+    ///
+    /// Hexes are updated in the order `HexGrid::axial_coords()` yields them,
+    /// so stateful rules (say, closures drawing random numbers) are
+    /// deterministic too.
+    pub fn step<R: Rule<T>>(&mut self, hex_grid: &HexGrid, rule: &mut R) {
+        let mut neighbors = Vec::with_capacity(6);
+        for ((q, r), next) in hex_grid.axial_coords().zip(self.next.as_mut_slice()) {
+            neighbors.clear();
+            for (dir, (nq, nr)) in hex_grid.neighbors(q, r) {
+                if !self.cut_by_walls || hex_grid.can_cross(nq, nr, dir.opposite()) {
+                    neighbors.push(self.cells.get(nq, nr).unwrap());
+                }
+            }
+            *next = rule.next(self.cells.get(q, r).unwrap(), &neighbors);
+        }
+        std::mem::swap(&mut self.cells, &mut self.next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex_grid::*;
+    use raylib::prelude::Color;
+
+    fn alive(automaton: &Automaton<bool>, hex_grid: &HexGrid) -> Vec<(i32, i32)> {
+        hex_grid
+            .axial_coords()
+            .filter(|&(q, r)| *automaton.cells().get(q, r).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_life_rule() {
+        let mut rule = LifeRule::new(&[2], &[3, 4, 9]);
+        assert!(rule.next(&false, &[&true, &true, &false]));
+        assert!(!rule.next(&false, &[&true, &false]));
+        assert!(rule.next(&true, &[&true, &true, &true]));
+        assert!(!rule.next(&true, &[&true, &true]));
+        assert!(!rule.next(&true, &[&true; 6]));
+    }
+
+    #[test]
+    fn test_step_is_simultaneous() {
+        // Two live hexes side by side give birth to the two hexes touching
+        // both, and die of loneliness; updating in place would see the births
+        // and keep them alive.
+        let hex_grid = HexGrid::new(6, 6);
+        let mut cells = HexLayer::new(hex_grid.layout(), false);
+        cells.set(1, 2, true);
+        cells.set(2, 2, true);
+        let mut automaton = Automaton::new(cells);
+        let mut rule = LifeRule::new(&[2], &[3, 4]);
+
+        automaton.step(&hex_grid, &mut rule);
+        assert_eq!(alive(&automaton, &hex_grid), vec![(2, 1), (1, 3)]);
+
+        // Same thing the other way around.
+        automaton.step(&hex_grid, &mut rule);
+        assert_eq!(alive(&automaton, &hex_grid), vec![(1, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn test_walls_cut_neighbors() {
+        let mut hex_grid = HexGrid::new(6, 6);
+        hex_grid.set_wall(1, 2, Direction::East, Some(Color::BLACK));
+        let mut cells = HexLayer::new(hex_grid.layout(), false);
+        cells.set(1, 2, true);
+
+        // Fire spreads to every neighbor, but not through walls once they cut.
+        let mut spread = |cell: &bool, neighbors: &[&bool]| *cell || neighbors.iter().any(|&&n| n);
+        let mut automaton = Automaton::new(cells.clone());
+        automaton.step(&hex_grid, &mut spread);
+        assert_eq!(alive(&automaton, &hex_grid).len(), 7);
+
+        let mut automaton = Automaton::new(cells);
+        automaton.set_cut_by_walls(true);
+        automaton.step(&hex_grid, &mut spread);
+        assert_eq!(alive(&automaton, &hex_grid).len(), 6);
+        assert_eq!(automaton.cells().get(2, 2), Some(&false));
+    }
+
+    #[test]
+    fn test_stateful_rule_is_deterministic() {
+        let hex_grid = HexGrid::new_wrapping(8, 5);
+        let run = || {
+            let mut automaton = Automaton::new(HexLayer::new(hex_grid.layout(), 0u64));
+            let mut counter = 0;
+            let mut rule = |cell: &u64, neighbors: &[&u64]| {
+                counter += 1;
+                cell + neighbors.iter().copied().sum::<u64>() % 7 + counter % 3
+            };
+            for _ in 0..5 {
+                automaton.step(&hex_grid, &mut rule);
+            }
+            automaton.cells().clone()
+        };
+        assert_eq!(run(), run());
+    }
+}
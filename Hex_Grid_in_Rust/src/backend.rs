@@ -0,0 +1,233 @@
+use raylib::prelude::*;
+
+/// The few drawing primitives `HexGridRenderer` needs. Implement this to draw
+/// grids with anything other than raylib.
+///
+/// Positions are in pixels, with y growing downwards. Polygons are convex, and
+/// their points may come in either winding order.
+pub trait DrawBackend {
+    /// Draws a filled polygon.
+    fn fill_polygon(&mut self, points: &[Vector2], color: Color);
+
+    /// Draws the outline of a (closed) polygon.
+    fn stroke_polygon(&mut self, points: &[Vector2], thickness: f32, color: Color);
+
+    /// Draws a line.
+    fn draw_line(&mut self, start: Vector2, end: Vector2, thickness: f32, color: Color);
+
+    /// Draws a filled circle.
+    fn fill_circle(&mut self, center: Vector2, radius: f32, color: Color);
+
+    /// Draws text with raylib's default font (or something close enough), with
+    /// its top-left corner at `position`.
+    fn draw_text(&mut self, text: &str, position: Vector2, font_size: i32, color: Color);
+}
+
+/// Draws with raylib, through any of its drawing handles.
+pub struct RaylibBackend<'a, D: RaylibDraw>(pub &'a mut D);
+
+impl<'a, D: RaylibDraw> DrawBackend for RaylibBackend<'a, D> {
+    fn fill_polygon(&mut self, points: &[Vector2], color: Color) {
+        if points.len() < 3 {
+            return;
+        }
+
+        // raylib wants triangles counter-clockwise on screen, and culls the
+        // others. With y growing downwards, that's a negative signed area.
+        let area: f32 = (0..points.len())
+            .map(|i| {
+                let (a, b) = (points[i], points[(i + 1) % points.len()]);
+                a.x * b.y - b.x * a.y
+            })
+            .sum();
+
+        for i in 1..points.len() - 1 {
+            let (b, c) = if area < 0.0 {
+                (points[i], points[i + 1])
+            } else {
+                (points[i + 1], points[i])
+            };
+            self.0.draw_triangle(points[0], b, c, color);
+        }
+    }
+
+    fn stroke_polygon(&mut self, points: &[Vector2], thickness: f32, color: Color) {
+        for i in 0..points.len() {
+            let (start, end) = (points[i], points[(i + 1) % points.len()]);
+            self.0.draw_line_ex(start, end, thickness, color);
+        }
+    }
+
+    fn draw_line(&mut self, start: Vector2, end: Vector2, thickness: f32, color: Color) {
+        self.0.draw_line_ex(start, end, thickness, color);
+    }
+
+    fn fill_circle(&mut self, center: Vector2, radius: f32, color: Color) {
+        self.0.draw_circle_v(center, radius, color);
+    }
+
+    fn draw_text(&mut self, text: &str, position: Vector2, font_size: i32, color: Color) {
+        let (x, y) = (position.x as i32, position.y as i32);
+        self.0.draw_text(text, x, y, font_size, color);
+    }
+}
+
+/// A single call to a `DrawBackend` method, as captured by `RecordingBackend`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DrawCall {
+    FillPolygon {
+        points: Vec<Vector2>,
+        color: Color,
+    },
+    StrokePolygon {
+        points: Vec<Vector2>,
+        thickness: f32,
+        color: Color,
+    },
+    Line {
+        start: Vector2,
+        end: Vector2,
+        thickness: f32,
+        color: Color,
+    },
+    Circle {
+        center: Vector2,
+        radius: f32,
+        color: Color,
+    },
+    Text {
+        text: String,
+        position: Vector2,
+        font_size: i32,
+        color: Color,
+    },
+}
+
+/// Doesn't draw anything, just remembers the draw calls, in order. Handy for
+/// tests, or to replay the calls somewhere else later.
+#[derive(Debug, Default)]
+pub struct RecordingBackend {
+    pub calls: Vec<DrawCall>,
+}
+
+impl RecordingBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl DrawBackend for RecordingBackend {
+    fn fill_polygon(&mut self, points: &[Vector2], color: Color) {
+        self.calls.push(DrawCall::FillPolygon {
+            points: points.to_vec(),
+            color,
+        });
+    }
+
+    fn stroke_polygon(&mut self, points: &[Vector2], thickness: f32, color: Color) {
+        self.calls.push(DrawCall::StrokePolygon {
+            points: points.to_vec(),
+            thickness,
+            color,
+        });
+    }
+
+    fn draw_line(&mut self, start: Vector2, end: Vector2, thickness: f32, color: Color) {
+        self.calls.push(DrawCall::Line {
+            start,
+            end,
+            thickness,
+            color,
+        });
+    }
+
+    fn fill_circle(&mut self, center: Vector2, radius: f32, color: Color) {
+        self.calls.push(DrawCall::Circle {
+            center,
+            radius,
+            color,
+        });
+    }
+
+    fn draw_text(&mut self, text: &str, position: Vector2, font_size: i32, color: Color) {
+        self.calls.push(DrawCall::Text {
+            text: text.to_string(),
+            position,
+            font_size,
+            color,
+        });
+    }
+}
+
+impl DrawCall {
+    /// Makes this same call on `backend`.
+    pub fn replay<B: DrawBackend>(&self, backend: &mut B) {
+        match self {
+            DrawCall::FillPolygon { points, color } => backend.fill_polygon(points, *color),
+            DrawCall::StrokePolygon {
+                points,
+                thickness,
+                color,
+            } => backend.stroke_polygon(points, *thickness, *color),
+            DrawCall::Line {
+                start,
+                end,
+                thickness,
+                color,
+            } => backend.draw_line(*start, *end, *thickness, *color),
+            DrawCall::Circle {
+                center,
+                radius,
+                color,
+            } => backend.fill_circle(*center, *radius, *color),
+            DrawCall::Text {
+                text,
+                position,
+                font_size,
+                color,
+            } => backend.draw_text(text, *position, *font_size, *color),
+        }
+    }
+}
+
+/// Returns the top-left and bottom-right corners of a box containing
+/// everything drawn by `calls`, or `None` if there are no calls. (Text only
+/// counts by its position, as we don't know how big it gets.)
+pub fn bounds(calls: &[DrawCall]) -> Option<(Vector2, Vector2)> {
+    let mut min = Vector2::new(f32::MAX, f32::MAX);
+    let mut max = Vector2::new(f32::MIN, f32::MIN);
+    let mut include = |p: Vector2, extra: f32| {
+        min = Vector2::new(min.x.min(p.x - extra), min.y.min(p.y - extra));
+        max = Vector2::new(max.x.max(p.x + extra), max.y.max(p.y + extra));
+    };
+
+    for call in calls {
+        match call {
+            DrawCall::FillPolygon { points, .. } => {
+                points.iter().for_each(|&p| include(p, 0.0));
+            }
+            DrawCall::StrokePolygon {
+                points, thickness, ..
+            } => {
+                points.iter().for_each(|&p| include(p, thickness / 2.0));
+            }
+            DrawCall::Line {
+                start,
+                end,
+                thickness,
+                ..
+            } => {
+                include(*start, thickness / 2.0);
+                include(*end, thickness / 2.0);
+            }
+            DrawCall::Circle { center, radius, .. } => include(*center, *radius),
+            DrawCall::Text { position, .. } => include(*position, 0.0),
+        }
+    }
+
+    if calls.is_empty() {
+        None
+    } else {
+        Some((min, max))
+    }
+}
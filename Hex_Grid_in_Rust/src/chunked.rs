@@ -0,0 +1,555 @@
+use crate::hex_grid::*;
+use crate::hex_map::HexMap;
+use crate::layers::{GridLayout, HexLayer, MapShape, Orientation};
+use raylib::prelude::*;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// The number of hexes along each side of a chunk.
+pub const CHUNK_SIZE: i32 = 16;
+
+const CHUNK_MAGIC: &[u8; 8] = b"HEXCHUNK";
+const CHUNK_VERSION: u32 = 1;
+
+/// The directions of the walls each hex owns, in the order they are stored in
+/// a chunk. The other three walls of a hex are owned by its neighbors in those
+/// directions.
+const OWNED: [Direction; 3] = [Direction::West, Direction::NorthWest, Direction::NorthEast];
+
+/// A `CHUNK_SIZE` by `CHUNK_SIZE` rhombus of hexes, and the walls they own, in
+/// coordinates local to the chunk.
+struct Chunk {
+    colors: HexLayer<Color>,
+    walls: HexLayer<[Option<Color>; 3]>,
+    wall_kinds: HexLayer<[WallKind; 3]>,
+}
+
+impl Chunk {
+    fn new(fill_color: Color) -> Self {
+        let layout = GridLayout::with_shape(MapShape::Rhombus {
+            width: CHUNK_SIZE,
+            height: CHUNK_SIZE,
+        });
+        Self {
+            colors: HexLayer::new(layout, fill_color),
+            walls: HexLayer::new(layout, [None; 3]),
+            wall_kinds: HexLayer::new(layout, [WallKind::Solid; 3]),
+        }
+    }
+
+    // Same format as `HexGrid::save()`, minus the shape: every chunk is the
+    // same size.
+    fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(CHUNK_MAGIC)?;
+        writer.write_all(&CHUNK_VERSION.to_le_bytes())?;
+
+        for color in self.colors.as_slice() {
+            write_color(writer, *color)?;
+        }
+
+        for walls in self.walls.as_slice() {
+            for wall in walls {
+                write_wall(writer, *wall)?;
+            }
+        }
+
+        for kinds in self.wall_kinds.as_slice() {
+            for kind in kinds {
+                writer.write_all(&[wall_kind_to_u8(*kind)])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != CHUNK_MAGIC {
+            return Err(invalid_data("not a hex chunk file"));
+        }
+        let version = read_u32(reader)?;
+        if version != CHUNK_VERSION {
+            return Err(invalid_data(&format!(
+                "unsupported hex chunk file version {}",
+                version
+            )));
+        }
+
+        let mut chunk = Self::new(Color::MAGENTA);
+
+        for color in chunk.colors.as_mut_slice() {
+            *color = read_color(reader)?;
+        }
+
+        for walls in chunk.walls.as_mut_slice() {
+            for wall in walls {
+                *wall = read_wall(reader)?;
+            }
+        }
+
+        for kinds in chunk.wall_kinds.as_mut_slice() {
+            for kind in kinds {
+                *kind = wall_kind_from_u8(read_u8(reader)?)
+                    .ok_or_else(|| invalid_data("invalid wall kind"))?;
+            }
+        }
+
+        Ok(chunk)
+    }
+}
+
+/// A hex grid with no fixed bounds, for open worlds: hexes are allocated in
+/// chunks of `CHUNK_SIZE` by `CHUNK_SIZE` (in axial coordinates) the first time
+/// anything in them is set, and coordinates can go as far as they like in any
+/// direction, negative ones included.
+///
+/// The hexes in the map are those of the chunks in memory: until a chunk is
+/// touched, its hexes are out of the map, just like the hexes past the edges of
+/// a `HexGrid`. This keeps flood fills, pathfinding and so on from wandering
+/// off forever.
+///
+/// Chunks can be evicted to files in a directory, to keep memory in check as
+/// the world grows, and loaded back later: see `evict_chunk()` and
+/// `load_chunk()`. While evicted, the hexes of a chunk are out of the map, and
+/// attempts to set them are ignored (they would be lost when loading it back).
+pub struct ChunkedHexGrid {
+    /// Which way the hexes are turned. Unlike with `HexGrid`, this only
+    /// matters for drawing: chunks are stored the same way either way.
+    orientation: Orientation,
+
+    /// The color of the hexes of new chunks.
+    fill_color: Color,
+
+    /// The chunks in memory, by chunk coordinates (see `chunk_of()`).
+    chunks: BTreeMap<(i32, i32), Chunk>,
+
+    /// The chunks evicted to `store_dir`.
+    evicted: BTreeSet<(i32, i32)>,
+
+    /// Where evicted chunks are stored.
+    store_dir: PathBuf,
+}
+
+impl ChunkedHexGrid {
+    /// Creates a new, empty chunked grid, painting new chunks with
+    /// `fill_color`, and evicting chunks to files in `store_dir` (created as
+    /// needed).
+    pub fn new<P: AsRef<Path>>(orientation: Orientation, fill_color: Color, store_dir: P) -> Self {
+        Self {
+            orientation,
+            fill_color,
+            chunks: BTreeMap::new(),
+            evicted: BTreeSet::new(),
+            store_dir: store_dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Returns the coordinates of the chunk containing the hex at the given
+    /// axial coordinates: the chunk (`cq`, `cr`) has the hexes with `q` in
+    /// `cq * CHUNK_SIZE..(cq + 1) * CHUNK_SIZE`, and the same for `r`.
+    pub fn chunk_of(q: i32, r: i32) -> (i32, i32) {
+        (q.div_euclid(CHUNK_SIZE), r.div_euclid(CHUNK_SIZE))
+    }
+
+    /// Iterates over the coordinates of the chunks in memory.
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    /// Iterates over the coordinates of the chunks evicted to disk.
+    pub fn evicted_chunks(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.evicted.iter().copied()
+    }
+
+    /// Writes the chunk at chunk coordinates `chunk` to its file and drops it
+    /// from memory. Does nothing if the chunk is not in memory.
+    pub fn evict_chunk(&mut self, chunk: (i32, i32)) -> io::Result<()> {
+        let Some(data) = self.chunks.get(&chunk) else {
+            return Ok(());
+        };
+
+        fs::create_dir_all(&self.store_dir)?;
+        let mut writer = BufWriter::new(File::create(self.chunk_path(chunk))?);
+        data.save(&mut writer)?;
+        writer.flush()?;
+
+        self.chunks.remove(&chunk);
+        self.evicted.insert(chunk);
+        Ok(())
+    }
+
+    /// Reads the chunk at chunk coordinates `chunk` back from its file. Does
+    /// nothing if the chunk was not evicted.
+    pub fn load_chunk(&mut self, chunk: (i32, i32)) -> io::Result<()> {
+        if !self.evicted.contains(&chunk) {
+            return Ok(());
+        }
+
+        let mut reader = BufReader::new(File::open(self.chunk_path(chunk))?);
+        let data = Chunk::load(&mut reader)?;
+
+        self.evicted.remove(&chunk);
+        self.chunks.insert(chunk, data);
+        Ok(())
+    }
+
+    fn chunk_path(&self, (cq, cr): (i32, i32)) -> PathBuf {
+        self.store_dir.join(format!("{}_{}.chunk", cq, cr))
+    }
+
+    /// Returns the chunk with the hex at the given axial coordinates, and the
+    /// coordinates of the hex within the chunk, if the chunk is in memory.
+    fn chunk(&self, q: i32, r: i32) -> Option<(&Chunk, i32, i32)> {
+        let (cq, cr) = Self::chunk_of(q, r);
+        let chunk = self.chunks.get(&(cq, cr))?;
+        Some((chunk, q - cq * CHUNK_SIZE, r - cr * CHUNK_SIZE))
+    }
+
+    /// Mutable version of `chunk()`, allocating the chunk if it was never
+    /// touched. Returns `None` only for evicted chunks.
+    fn chunk_mut(&mut self, q: i32, r: i32) -> Option<(&mut Chunk, i32, i32)> {
+        let (cq, cr) = Self::chunk_of(q, r);
+        if self.evicted.contains(&(cq, cr)) {
+            return None;
+        }
+        let fill_color = self.fill_color;
+        let chunk = self
+            .chunks
+            .entry((cq, cr))
+            .or_insert_with(|| Chunk::new(fill_color));
+        Some((chunk, q - cq * CHUNK_SIZE, r - cr * CHUNK_SIZE))
+    }
+
+    /// The bounding box of the chunks in memory, in axial coordinates, as
+    /// ranges of `q` and `r`. Empty if there are no chunks.
+    fn bounds(&self) -> (Range<i32>, Range<i32>) {
+        let mut keys = self.chunks.keys();
+        let Some(&(q, r)) = keys.next() else {
+            return (0..0, 0..0);
+        };
+        let (mut q0, mut q1, mut r0, mut r1) = (q, q, r, r);
+        for &(q, r) in keys {
+            (q0, q1) = (q0.min(q), q1.max(q));
+            (r0, r1) = (r0.min(r), r1.max(r));
+        }
+        (
+            q0 * CHUNK_SIZE..(q1 + 1) * CHUNK_SIZE,
+            r0 * CHUNK_SIZE..(r1 + 1) * CHUNK_SIZE,
+        )
+    }
+}
+
+/// Returns the hex owning the wall of the hex at (`q`, `r`) in the given
+/// direction, and the index of the wall among those it owns (see `OWNED`).
+fn wall_owner(q: i32, r: i32, dir: Direction) -> (i32, i32, usize) {
+    if let Some(i) = OWNED.iter().position(|&d| d == dir) {
+        return (q, r, i);
+    }
+    let (dq, dr) = dir.offset();
+    let i = OWNED.iter().position(|&d| d == dir.opposite()).unwrap();
+    (q + dq, r + dr, i)
+}
+
+impl HexMap for ChunkedHexGrid {
+    fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    fn normalize(&self, q: i32, r: i32) -> Option<(i32, i32)> {
+        self.chunk(q, r).map(|_| (q, r))
+    }
+
+    /// Every vertex is either the top corner (5) of a hex or the bottom corner
+    /// (2) of another, whichever way the hexes are turned, and that's the
+    /// canonical name.
+    fn normalize_vertex(&self, q: i32, r: i32, corner: u8) -> Option<Vertex> {
+        let (dq, dr, corner) = match corner % 6 {
+            0 => (1, -1, 2),
+            1 => (0, 1, 5),
+            3 => (-1, 1, 5),
+            4 => (0, -1, 2),
+            corner => (0, 0, corner),
+        };
+        let (q, r) = (q + dq, r + dr);
+
+        vertex_names(q, r, corner)
+            .iter()
+            .any(|&(q, r, _)| self.are_coords_valid(q, r))
+            .then_some((q, r, corner))
+    }
+
+    fn hex_color(&self, q: i32, r: i32) -> Option<Color> {
+        let (chunk, q, r) = self.chunk(q, r)?;
+        chunk.colors.get(q, r).copied()
+    }
+
+    /// Allocates the chunk of the hex if needed. Ignored for hexes in evicted
+    /// chunks.
+    fn set_hex_color(&mut self, q: i32, r: i32, color: Color) {
+        if let Some((chunk, q, r)) = self.chunk_mut(q, r) {
+            chunk.colors.set(q, r, color);
+        }
+    }
+
+    /// Walls are stored with one of the two hexes sharing them, so this returns
+    /// `None` whenever that hex is out of the map, even if the other one is in.
+    fn wall(&self, q: i32, r: i32, dir: Direction) -> Option<Color> {
+        let (q, r, i) = wall_owner(q, r, dir);
+        let (chunk, q, r) = self.chunk(q, r)?;
+        chunk.walls.get(q, r).and_then(|walls| walls[i])
+    }
+
+    /// Adding a wall allocates the chunk of the hex storing it, if needed.
+    /// Ignored for walls stored in evicted chunks.
+    fn set_wall(&mut self, q: i32, r: i32, dir: Direction, color: Option<Color>) {
+        let (q, r, i) = wall_owner(q, r, dir);
+        if color.is_none() && self.chunk(q, r).is_none() {
+            return;
+        }
+        if let Some((chunk, q, r)) = self.chunk_mut(q, r) {
+            if let Some(walls) = chunk.walls.get_mut(q, r) {
+                walls[i] = color;
+            }
+            if color.is_none() {
+                if let Some(kinds) = chunk.wall_kinds.get_mut(q, r) {
+                    kinds[i] = WallKind::Solid;
+                }
+            }
+        }
+    }
+
+    fn wall_kind(&self, q: i32, r: i32, dir: Direction) -> Option<WallKind> {
+        self.wall(q, r, dir)?;
+        let (q, r, i) = wall_owner(q, r, dir);
+        let (chunk, q, r) = self.chunk(q, r)?;
+        chunk.wall_kinds.get(q, r).map(|kinds| kinds[i])
+    }
+
+    fn set_wall_kind(&mut self, q: i32, r: i32, dir: Direction, kind: WallKind) {
+        if self.wall(q, r, dir).is_none() {
+            return;
+        }
+        let (q, r, i) = wall_owner(q, r, dir);
+        if let Some((chunk, q, r)) = self.chunk_mut(q, r) {
+            if let Some(kinds) = chunk.wall_kinds.get_mut(q, r) {
+                kinds[i] = kind;
+            }
+        }
+    }
+
+    fn owned_directions(&self) -> [Direction; 3] {
+        OWNED
+    }
+
+    /// Goes chunk by chunk, and row by row within each chunk.
+    fn axial_coords(&self) -> Box<dyn Iterator<Item = (i32, i32)> + '_> {
+        Box::new(self.chunks.keys().flat_map(|&(cq, cr)| {
+            (0..CHUNK_SIZE).flat_map(move |r| {
+                (0..CHUNK_SIZE).map(move |q| (cq * CHUNK_SIZE + q, cr * CHUNK_SIZE + r))
+            })
+        }))
+    }
+
+    /// The same as `axial_coords()`: all walls are owned by hexes in the map.
+    fn axial_coords_ext(&self) -> Box<dyn Iterator<Item = (i32, i32)> + '_> {
+        self.axial_coords()
+    }
+
+    fn rows(&self, _ext: bool) -> Range<i32> {
+        let (qs, rs) = self.bounds();
+        match self.orientation {
+            Orientation::PointyTop => rs,
+            Orientation::FlatTop => qs,
+        }
+    }
+
+    fn row_span(&self, _row: i32, _ext: bool) -> Range<i32> {
+        let (qs, rs) = self.bounds();
+        match self.orientation {
+            Orientation::PointyTop => qs,
+            Orientation::FlatTop => rs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::RecordingBackend;
+    use crate::pathfinding;
+    use crate::render::HexGridRenderer;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hex_chunks_{}_{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_sparse_allocation() {
+        let mut world =
+            ChunkedHexGrid::new(Orientation::PointyTop, Color::BLUE, temp_dir("sparse"));
+        assert_eq!(world.axial_coords().count(), 0);
+        assert_eq!(world.hex_color(0, 0), None);
+        assert!(!world.are_coords_valid(0, 0));
+
+        world.set_hex_color(-1, -1, Color::RED);
+        world.set_hex_color(1000, -3000, Color::GREEN);
+        assert_eq!(
+            world.loaded_chunks().collect::<Vec<_>>(),
+            vec![(-1, -1), (62, -188)]
+        );
+        assert_eq!(world.axial_coords().count(), 2 * 256);
+        assert_eq!(world.hex_color(-1, -1), Some(Color::RED));
+        assert_eq!(world.hex_color(-16, -16), Some(Color::BLUE));
+        assert_eq!(world.hex_color(0, 0), None);
+        assert_eq!(world.hex_color(1000, -3000), Some(Color::GREEN));
+
+        assert_eq!(ChunkedHexGrid::chunk_of(15, 16), (0, 1));
+        assert_eq!(ChunkedHexGrid::chunk_of(-16, -17), (-1, -2));
+
+        // Neighbors stop at the edges of the chunks in memory.
+        assert_eq!(world.neighbors(-1, -1).count(), 2);
+        assert_eq!(world.neighbors(-8, -8).count(), 6);
+    }
+
+    #[test]
+    fn test_walls() {
+        let mut world = ChunkedHexGrid::new(Orientation::PointyTop, Color::BLUE, temp_dir("walls"));
+        world.set_hex_color(0, 0, Color::BLUE);
+
+        // Both hexes sharing a wall see it, even across chunks.
+        for (q, r) in [(5, 5), (0, 0), (15, 15)] {
+            for dir in Direction::ALL {
+                let (dq, dr) = dir.offset();
+                world.set_wall(q, r, dir, Some(Color::BLACK));
+                assert_eq!(
+                    world.wall(q + dq, r + dr, dir.opposite()),
+                    Some(Color::BLACK)
+                );
+                world.set_wall_kind(q + dq, r + dr, dir.opposite(), WallKind::Window);
+                assert_eq!(world.wall_kind(q, r, dir), Some(WallKind::Window));
+                world.set_wall(q + dq, r + dr, dir.opposite(), None);
+                assert_eq!(world.wall(q, r, dir), None);
+                assert_eq!(world.wall_kind(q, r, dir), None);
+            }
+        }
+
+        // Walls past the chunk edges allocate the chunks storing them, but
+        // removing them doesn't.
+        assert_eq!(
+            world.loaded_chunks().collect::<Vec<_>>(),
+            vec![(-1, 0), (0, 0), (0, 1), (1, 0)]
+        );
+        world.set_wall(-40, -40, Direction::NorthWest, None);
+        assert_eq!(world.loaded_chunks().count(), 4);
+
+        // Vertices are named the same as on a pointy-top `HexGrid`, and are in
+        // the map if any of the hexes around them are.
+        let hex_grid = HexGrid::new(3, 3);
+        for (q, r) in [(0, 0), (15, 15), (-1, -1), (-17, 0)] {
+            for corner in 0..6 {
+                let around = vertex_names(q, r, corner);
+                assert_eq!(
+                    world.normalize_vertex(q, r, corner).is_some(),
+                    around.iter().any(|&(q, r, _)| world.are_coords_valid(q, r))
+                );
+            }
+        }
+        assert_eq!(
+            world.normalize_vertex(1, 1, 0),
+            hex_grid.normalize_vertex(1, 1, 0)
+        );
+        assert_eq!(world.vertex_edges(5, 5, 5).len(), 3);
+    }
+
+    #[test]
+    fn test_evict_and_load() {
+        let dir = temp_dir("evict");
+        let mut world = ChunkedHexGrid::new(Orientation::FlatTop, Color::BLUE, &dir);
+        world.set_hex_color(-20, 3, Color::RED);
+        world.set_wall(-20, 3, Direction::East, Some(Color::BLACK));
+        world.set_wall_kind(-20, 3, Direction::East, WallKind::OneWay(Direction::East));
+        world.set_hex_color(40, 40, Color::GREEN);
+
+        world.evict_chunk((-2, 0)).unwrap();
+        assert_eq!(world.loaded_chunks().collect::<Vec<_>>(), vec![(2, 2)]);
+        assert_eq!(world.evicted_chunks().collect::<Vec<_>>(), vec![(-2, 0)]);
+        assert_eq!(world.hex_color(-20, 3), None);
+        assert_eq!(world.wall(-20, 3, Direction::East), None);
+
+        // Evicted chunks are off limits until loaded back.
+        world.set_hex_color(-20, 3, Color::YELLOW);
+        assert_eq!(world.hex_color(-20, 3), None);
+
+        world.load_chunk((-2, 0)).unwrap();
+        world.load_chunk((7, 7)).unwrap();
+        assert_eq!(world.evicted_chunks().count(), 0);
+        assert_eq!(world.hex_color(-20, 3), Some(Color::RED));
+        assert_eq!(world.hex_color(-32, 0), Some(Color::BLUE));
+        assert_eq!(
+            world.wall_kind(-19, 3, Direction::West),
+            Some(WallKind::OneWay(Direction::East))
+        );
+
+        // Garbage is rejected.
+        world.evict_chunk((-2, 0)).unwrap();
+        world.evict_chunk((2, 2)).unwrap();
+        fs::write(dir.join("-2_0.chunk"), b"HEXCHUNK\x01\x00\x00\x00").unwrap();
+        let err = world.load_chunk((-2, 0)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(world.evicted_chunks().any(|chunk| chunk == (-2, 0)));
+        world.load_chunk((2, 2)).unwrap();
+        assert_eq!(world.hex_color(40, 40), Some(Color::GREEN));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_algorithms() {
+        let mut world = ChunkedHexGrid::new(Orientation::PointyTop, Color::BLUE, temp_dir("algos"));
+        world.set_hex_color(-10, -10, Color::BLUE);
+        world.set_hex_color(10, 10, Color::BLUE);
+        world.set_hex_color(10, -10, Color::BLUE);
+
+        // Wall off chunk (-1, -1) from chunk (0, -1), the only way out of it.
+        for r in -16..0 {
+            world.set_wall(-1, r, Direction::East, Some(Color::BLACK));
+            world.set_wall(-1, r, Direction::NorthEast, Some(Color::BLACK));
+        }
+        assert_eq!(world.loaded_chunks().count(), 3);
+        let uniform = |_, _| Some(1);
+        assert_eq!(
+            pathfinding::find_path(&world, (-10, -10), (10, 10), uniform),
+            Err(pathfinding::PathError::Unreachable)
+        );
+        assert_eq!(
+            crate::tools::flood_fill(&world, (-10, -10), true).len(),
+            (CHUNK_SIZE * CHUNK_SIZE) as usize
+        );
+
+        world.set_wall(-1, -5, Direction::East, None);
+        let path = pathfinding::find_path(&world, (-10, -10), (10, 10), uniform).unwrap();
+        assert!(path.hexes.contains(&(-1, -5)) && path.hexes.contains(&(0, -5)));
+        assert_eq!(
+            pathfinding::find_path(&world, (-10, -10), (-10, 10), uniform),
+            Err(pathfinding::PathError::OutOfBounds(-10, 10))
+        );
+
+        // Drawing only looks at the hexes in view, which may well be out of
+        // the map.
+        let mut renderer = HexGridRenderer::new(10.0);
+        renderer.set_view(Some(Rectangle::new(-50.0, -50.0, 100.0, 100.0)));
+        let mut recording = RecordingBackend::new();
+        renderer.draw(&mut recording, &world);
+        assert!(!recording.calls.is_empty());
+        assert!(renderer
+            .visible_hexes(&world)
+            .all(|(q, r)| world.are_coords_valid(q, r)));
+        assert!(renderer.visible_hexes(&world).any(|hex| hex == (-1, -1)));
+        assert!(!renderer.visible_hexes(&world).any(|hex| hex == (-1, 1)));
+    }
+}
@@ -1,269 +1,1417 @@
+use crate::layers::*;
 use raylib::prelude::*;
 
-/// A hexagonal grid, rectangular in shape, with hexes arranged in a pointy-top
-/// orientation, using axial coordinates.
+use std::any::Any;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// The magic bytes at the start of every saved hex grid file.
+const FILE_MAGIC: &[u8; 8] = b"HEXGRID\0";
+
+/// The version of the file format written by `HexGrid::save()`. Bump this
+/// whenever the format changes, and keep `HexGrid::load()` able to read the
+/// older versions.
+const FILE_VERSION: u32 = 5;
+
+/// The six directions from a hex to its neighbors (or, equivalently, the six
+/// walls of a hex).
 ///
-/// Also, going a bit old-style here, with a vector for each property of each
-/// hex, instead of a `Hex` struct. (Or is this cool again? With all the drive
-/// to ECS, cache locality, etc...)
-pub struct HexGrid {
-    /// The number of hexes, horizontally.
-    width: i32,
+/// Sorted clockwise starting from the east, in the same order the renderer
+/// numbers the hex corners: the wall in direction `d` goes from corner
+/// `d as u8` to the next one.
+///
+/// The names are those of pointy-top grids. On flat-top grids, each direction
+/// points 30 degrees clockwise of its name: see `Orientation`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    East,
+    SouthEast,
+    SouthWest,
+    West,
+    NorthWest,
+    NorthEast,
+}
 
-    /// The number of hexes, vertically.
-    height: i32,
+impl Direction {
+    /// All directions, in clockwise order, starting from the east.
+    pub const ALL: [Direction; 6] = [
+        Direction::East,
+        Direction::SouthEast,
+        Direction::SouthWest,
+        Direction::West,
+        Direction::NorthWest,
+        Direction::NorthEast,
+    ];
+
+    /// Returns the offset, in axial coordinates, to the neighbor in this
+    /// direction.
+    pub fn offset(self) -> (i32, i32) {
+        match self {
+            Direction::East => (1, 0),
+            Direction::SouthEast => (0, 1),
+            Direction::SouthWest => (-1, 1),
+            Direction::West => (-1, 0),
+            Direction::NorthWest => (0, -1),
+            Direction::NorthEast => (1, -1),
+        }
+    }
+
+    /// Returns the opposite direction.
+    pub fn opposite(self) -> Direction {
+        Self::ALL[(self as usize + 3) % 6]
+    }
+
+    /// Returns the indices of the two hex corners connected by the wall in this
+    /// direction, in clockwise order.
+    pub fn corners(self) -> (u8, u8) {
+        let i = self as u8;
+        (i, (i + 1) % 6)
+    }
+}
+
+/// What kind of wall stands on an edge, which decides what can get through it.
+/// Every wall is `Solid` unless told otherwise (see
+/// `HexGrid::set_wall_kind()`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum WallKind {
+    /// Nothing gets through.
+    #[default]
+    Solid,
+    /// Anything gets through.
+    OpenDoor,
+    /// Nothing gets through, until someone finds the key.
+    LockedDoor,
+    /// Blocks movement, but not sight.
+    Window,
+    /// Can only be crossed going in the given direction (which is the same
+    /// seen from both sides of the wall). Doesn't block sight.
+    OneWay(Direction),
+}
+
+impl WallKind {
+    /// All the kinds, with one-way passages going east. Handy for cycling
+    /// through them.
+    pub const ALL: [WallKind; 5] = [
+        WallKind::Solid,
+        WallKind::OpenDoor,
+        WallKind::LockedDoor,
+        WallKind::Window,
+        WallKind::OneWay(Direction::East),
+    ];
+
+    /// Checks if this kind of wall stops whatever crosses it going in
+    /// direction `dir`.
+    pub fn blocks_movement(self, dir: Direction) -> bool {
+        match self {
+            WallKind::OpenDoor => false,
+            WallKind::OneWay(way) => way != dir,
+            WallKind::Solid | WallKind::LockedDoor | WallKind::Window => true,
+        }
+    }
+
+    /// Checks if this kind of wall can't be seen through.
+    pub fn blocks_sight(self) -> bool {
+        matches!(self, WallKind::Solid | WallKind::LockedDoor)
+    }
+
+    /// Returns a short, human-readable name for this kind.
+    pub fn name(self) -> &'static str {
+        match self {
+            WallKind::Solid => "solid",
+            WallKind::OpenDoor => "open door",
+            WallKind::LockedDoor => "locked door",
+            WallKind::Window => "window",
+            WallKind::OneWay(_) => "one-way",
+        }
+    }
+}
+
+/// A vertex of the grid (a corner of a hex), as (`q`, `r`, `corner`): the axial
+/// coordinates of a hex, and the index of the corner, numbered like in
+/// `Direction::corners()`.
+///
+/// Each vertex is shared by three hexes, so it has three names;
+/// `HexGrid::normalize_vertex()` picks one.
+pub type Vertex = (i32, i32, u8);
+
+/// A hexagonal grid, with hexes arranged in a pointy-top orientation (unless
+/// created with `with_orientation()`, see `Orientation`), using axial
+/// coordinates. Grids are rectangular, unless created with `with_shape()`:
+/// see `MapShape` for the other shapes.
+///
+/// Optionally, pointy-top rectangular grids can wrap around horizontally, like
+/// a cylinder (or the typical world map in a strategy game): going past the
+/// east edge of the grid takes us back to its west edge, and vice-versa.
+///
+/// Also, going a bit old-style here, with a layer (a vector, really) for each
+/// property of each hex, instead of a `Hex` struct. (Or is this cool again?
+/// With all the drive to ECS, cache locality, etc...) Users of the grid can
+/// register layers of their own, for whatever data they need: see
+/// `add_layer()`.
+pub struct HexGrid {
+    /// The size and shape of the grid, shared by all layers.
+    layout: GridLayout,
 
-    //
-    // Properties of each hex. Each of these contains width * height elements.
-    //
     /// The color of each hex.
-    hex_colors: Vec<Color>,
+    hex_colors: HexLayer<Color>,
 
     /// An int associated with each hex. This is just to let me differentiate
     /// between hexes, for debugging purposes, and seeing what I am doing.
-    hex_ints: Vec<i32>,
+    hex_ints: HexLayer<i32>,
 
-    //
-    // Properties of each hex's birder. Each of these contains
-    // (width + 1) * (height + 1) elements, with the extra row and column
-    // storing data for hexes near the edges of the grid.
-    //
-    // Conveniently, we treat the grid as wrapping around horizontally, so the
-    // extra hexes can be interpreted as being either to the east or the west of
-    // the grid, as needed. Different properties of fhe same "out-of-bounds hex"
-    // can even be used as both west and east of the grid, at the same time
-    // (this happens on odd rows).
-    //
-    // Storing those walls in an array of 3 elements instead of 3 separate
-    // members? Would this help with some algorithm?
-    //
-    /// The color of each hex's west wall.
-    w_wall: Vec<Option<Color>>,
+    /// The color of each wall, if there is a wall there.
+    walls: EdgeLayer<Option<Color>>,
 
-    /// The color of each hex's north-west wall.
-    nw_wall: Vec<Option<Color>>,
+    /// The kind of each wall. Kept at `WallKind::Solid` where there is no wall.
+    wall_kinds: EdgeLayer<WallKind>,
 
-    /// The color of each hex's north-east wall.
-    ne_wall: Vec<Option<Color>>,
+    /// Extra layers registered with `add_layer()`, by name. Each of these is a
+    /// `HexLayer<T>` or `EdgeLayer<T>` for some `T`.
+    layers: HashMap<String, Box<dyn Any>>,
 }
 
 impl HexGrid {
     /// Creates a new hex grid.
     pub fn new(width: i32, height: i32) -> Self {
-        assert!(width > 0, "width must be greater than 0, got {}", width);
-        assert!(height > 0, "height must be greater than 0, got {}", height);
+        Self::with_layout(GridLayout::new(width, height, false))
+    }
 
-        // Per hex properties
-        let size = width * height;
-        let mut hex_colors = Vec::with_capacity(size as usize);
-        let mut hex_ints = Vec::with_capacity(size as usize);
+    /// Creates a new hex grid that wraps around horizontally.
+    pub fn new_wrapping(width: i32, height: i32) -> Self {
+        Self::with_layout(GridLayout::new(width, height, true))
+    }
 
-        for i in 0..size {
-            hex_colors.push(Color::MAGENTA);
-            hex_ints.push(i);
-        }
+    /// Creates a new hex grid of any shape. See `MapShape`.
+    pub fn with_shape(shape: MapShape) -> Self {
+        Self::with_layout(GridLayout::with_shape(shape))
+    }
 
-        // Per border properties
-        let size_ext = (width + 1) * (height + 1);
-        let mut w_wall = Vec::with_capacity(size_ext as usize);
-        let mut nw_wall = Vec::with_capacity(size_ext as usize);
-        let mut ne_wall = Vec::with_capacity(size_ext as usize);
+    /// Creates a new hex grid of any shape, with its hexes turned either way.
+    /// See `Orientation`.
+    pub fn with_orientation(shape: MapShape, orientation: Orientation) -> Self {
+        Self::with_layout(GridLayout::with_orientation(shape, orientation))
+    }
 
-        for _ in 0..size_ext {
-            w_wall.push(None);
-            nw_wall.push(None);
-            ne_wall.push(None);
+    fn with_layout(layout: GridLayout) -> Self {
+        let mut hex_ints = HexLayer::new(layout, 0);
+        for (i, int) in hex_ints.as_mut_slice().iter_mut().enumerate() {
+            *int = i as i32;
         }
 
         Self {
-            width,
-            height,
-            hex_colors,
+            layout,
+            hex_colors: HexLayer::new(layout, Color::MAGENTA),
             hex_ints,
-            w_wall,
-            nw_wall,
-            ne_wall,
+            walls: EdgeLayer::new(layout, None),
+            wall_kinds: EdgeLayer::new(layout, WallKind::Solid),
+            layers: HashMap::new(),
         }
     }
 
-    /// Returns the number of hexes, horizontally.
+    /// Returns the layout of the grid, to create layers for it.
+    pub fn layout(&self) -> GridLayout {
+        self.layout
+    }
+
+    /// Returns the shape of the grid.
+    pub fn shape(&self) -> MapShape {
+        self.layout.shape()
+    }
+
+    /// Returns which way the hexes of the grid are turned.
+    pub fn orientation(&self) -> Orientation {
+        self.layout.orientation()
+    }
+
+    /// Returns the number of hexes, horizontally (in the widest row, for grids
+    /// that aren't rectangles; the number of columns, for flat-top grids).
     pub fn width(&self) -> i32 {
-        self.width
+        self.layout.width()
     }
 
-    /// Returns the number of hexes, vertically.
+    /// Returns the number of hexes, vertically (in the tallest column, for
+    /// flat-top grids that aren't rectangles).
+    ///
+    /// The editor only walks grids by rows (see `HexMap::rows()`), so this one
+    /// is just for users of the grid.
+    #[allow(dead_code)]
     pub fn height(&self) -> i32 {
-        self.height
+        self.layout.height()
+    }
+
+    /// Checks if the grid wraps around horizontally.
+    pub fn wraps_horizontally(&self) -> bool {
+        self.layout.wraps_horizontally()
+    }
+
+    /// Returns the canonical axial coordinates of the hex at the given axial
+    /// coordinates, or `None` if they are not valid.
+    ///
+    /// This is the identity for valid coordinates, except on grids that wrap
+    /// around horizontally, where coordinates past the east or west edges
+    /// resolve to the hex on the other side of the grid.
+    pub fn normalize(&self, q: i32, r: i32) -> Option<(i32, i32)> {
+        self.layout.normalize(q, r)
     }
 
     /// Returns the hex color at the given axial coordinates. If the coordinates
     /// are valid, will always return `Some(Color)`.
     ///
     /// Top-left hex is at (0, 0). The *q* axis grows east, and the *r* axis
-    /// grows south-east.
+    /// grows south-east (on flat-top grids, south-east and south).
     pub fn hex_color(&self, q: i32, r: i32) -> Option<Color> {
-        if !self.are_coords_valid(q, r) {
-            None
-        } else {
-            let index = self.hex_array_index(q, r);
-            Some(self.hex_colors[index])
-        }
+        self.hex_colors.get(q, r).copied()
     }
 
     pub fn set_hex_color(&mut self, q: i32, r: i32, color: Color) {
-        if !self.are_coords_valid(q, r) {
-            return;
+        self.hex_colors.set(q, r, color);
+    }
+
+    pub fn hex_int(&self, q: i32, r: i32) -> Option<i32> {
+        self.hex_ints.get(q, r).copied()
+    }
+
+    /// Returns the wall of the hex at (`q`, `r`) in the given direction.
+    ///
+    /// Works for every wall touching at least one hex in the grid, including
+    /// the walls along the edges of the grid. Returns `None` for other walls.
+    pub fn wall(&self, q: i32, r: i32, dir: Direction) -> Option<Color> {
+        self.walls.get(q, r, dir).copied().flatten()
+    }
+
+    /// Sets the wall of the hex at (`q`, `r`) in the given direction. Same
+    /// rules as in `wall()`; attempts to set other walls are ignored.
+    ///
+    /// New walls are `WallKind::Solid`, and removing a wall forgets its kind.
+    pub fn set_wall(&mut self, q: i32, r: i32, dir: Direction, color: Option<Color>) {
+        self.walls.set(q, r, dir, color);
+        if color.is_none() {
+            self.wall_kinds.set(q, r, dir, WallKind::Solid);
+        }
+    }
+
+    /// Returns the kind of the wall of the hex at (`q`, `r`) in the given
+    /// direction, or `None` if there is no wall there.
+    pub fn wall_kind(&self, q: i32, r: i32, dir: Direction) -> Option<WallKind> {
+        self.wall(q, r, dir)?;
+        self.wall_kinds.get(q, r, dir).copied()
+    }
+
+    /// Sets the kind of the wall of the hex at (`q`, `r`) in the given
+    /// direction. Ignored if there is no wall there: add it with `set_wall()`
+    /// first.
+    pub fn set_wall_kind(&mut self, q: i32, r: i32, dir: Direction, kind: WallKind) {
+        if self.wall(q, r, dir).is_some() {
+            self.wall_kinds.set(q, r, dir, kind);
+        }
+    }
+
+    //
+    // Vertices
+    //
+
+    /// Returns the canonical name of the vertex at corner `corner` of the hex
+    /// at (`q`, `r`), or `None` if none of the hexes around it are in the grid.
+    ///
+    /// Every vertex is either the top corner (5) of a hex or the bottom corner
+    /// (2) of another (on flat-top grids, the west corner (3) or the east one
+    /// (0)), and that's the canonical name. That hex may be just out
+    /// of the grid, though, like walls along the edges.
+    pub fn normalize_vertex(&self, q: i32, r: i32, corner: u8) -> Option<Vertex> {
+        self.layout.normalize_vertex(q, r, corner)
+    }
+
+    /// Returns the canonical axial coordinates of the (up to three) hexes in
+    /// the grid around the given vertex.
+    pub fn vertex_hexes(&self, q: i32, r: i32, corner: u8) -> Vec<(i32, i32)> {
+        let mut hexes = Vec::new();
+        let Some((q, r, corner)) = self.normalize_vertex(q, r, corner) else {
+            return hexes;
+        };
+
+        for (q, r, _) in vertex_names(q, r, corner) {
+            if let Some(hex) = self.normalize(q, r) {
+                if !hexes.contains(&hex) {
+                    hexes.push(hex);
+                }
+            }
+        }
+
+        hexes
+    }
+
+    /// Returns the canonical names of the six vertices of the hex at (`q`,
+    /// `r`), in corner order, or `None` if the hex is not in the grid.
+    pub fn hex_vertices(&self, q: i32, r: i32) -> Option<[Vertex; 6]> {
+        self.normalize(q, r)?;
+        let vertices =
+            [0, 1, 2, 3, 4, 5].map(|corner| self.normalize_vertex(q, r, corner).unwrap());
+        Some(vertices)
+    }
+
+    /// Returns the canonical names of the vertices at the ends of the wall of
+    /// the hex at (`q`, `r`) in the given direction, in clockwise order around
+    /// that hex. Same rules as in `wall()`: returns `None` for walls that don't
+    /// touch the grid.
+    pub fn wall_vertices(&self, q: i32, r: i32, dir: Direction) -> Option<(Vertex, Vertex)> {
+        self.walls.get(q, r, dir)?;
+        let (c1, c2) = dir.corners();
+        Some((
+            self.normalize_vertex(q, r, c1)?,
+            self.normalize_vertex(q, r, c2)?,
+        ))
+    }
+
+    //
+    // Extra layers
+    //
+
+    /// Registers `layer` under `name`, replacing any layer previously
+    /// registered with the same name.
+    ///
+    /// The layer must have been created with this grid's `layout()`. Extra
+    /// layers are not saved by `save()`, keeping them around is up to whoever
+    /// registered them.
+    pub fn add_layer<L: GridLayer>(&mut self, name: &str, layer: L) {
+        assert_eq!(
+            layer.layout(),
+            self.layout,
+            "layer {:?} doesn't match the grid layout",
+            name
+        );
+        self.layers.insert(name.to_string(), Box::new(layer));
+    }
+
+    /// Returns the layer registered under `name`, or `None` if there is no
+    /// such layer, or if it is not of type `L`.
+    ///
+    /// For example, `hex_grid.layer::<HexLayer<u8>>("elevation")`.
+    pub fn layer<L: GridLayer>(&self, name: &str) -> Option<&L> {
+        self.layers.get(name)?.downcast_ref()
+    }
+
+    /// Mutable version of `layer()`.
+    pub fn layer_mut<L: GridLayer>(&mut self, name: &str) -> Option<&mut L> {
+        self.layers.get_mut(name)?.downcast_mut()
+    }
+
+    /// Iterates over all valid axial coordinates in the grid, row by row, from
+    /// west to east (on flat-top grids, column by column, from north to south).
+    pub fn axial_coords(&self) -> impl Iterator<Item = (i32, i32)> {
+        let layout = self.layout;
+        layout
+            .rows()
+            .flat_map(move |row| layout.row_span(row).map(move |x| layout.row_hex(row, x)))
+    }
+
+    /// Iterates over all "extra" axial coordinates in the grid. (Actually, over
+    /// all normal hex plus the extra ones!) These are the hexes owning edges:
+    /// see `EdgeLayer`.
+    pub fn axial_coords_ext(&self) -> impl Iterator<Item = (i32, i32)> {
+        // On rectangles, odd rows start with an extra hex to the west (and
+        // the same extra hex appears on the east side, too).
+        let layout = self.layout;
+        layout.ext_rows().flat_map(move |row| {
+            layout
+                .ext_row_span(row)
+                .map(move |x| layout.row_hex(row, x))
+        })
+    }
+
+    //
+    // Saving and loading
+    //
+
+    /// Saves the grid to the file at `path`. See `save()` for details.
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.save(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Loads a grid from the file at `path`. See `load()` for details.
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Self::load(&mut reader)
+    }
+
+    /// Saves the grid to `writer`.
+    ///
+    /// The format is a simple binary one, with all numbers stored in
+    /// little-endian order: the magic bytes, the format version, a byte for the
+    /// shape (0 for rectangles, 1 for hexagons, 2 for rhombi and 3 for
+    /// triangles) followed by its size (width and height, radius, width and
+    /// height, or size), a byte telling if the grid wraps around horizontally,
+    /// a byte for the orientation (0 for pointy-top, 1 for flat-top), and then
+    /// each of the per-hex and per-border vectors, in the same order they are
+    /// stored in memory (so the extra row and column of the wall layers are
+    /// saved, too).
+    ///
+    /// Version 4 was the same, minus the orientation byte: all grids were
+    /// pointy-top. Version 3 was version 4 minus the shape byte: all grids were
+    /// rectangles, so the size was always the width and height. Version 2 was
+    /// version 3 minus the wall kinds at the end, and version 1 was version 2
+    /// minus the wrapping byte.
+    pub fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(FILE_MAGIC)?;
+        writer.write_all(&FILE_VERSION.to_le_bytes())?;
+        let (shape, size) = match self.shape() {
+            MapShape::Rectangle { width, height } => (0, vec![width, height]),
+            MapShape::Hexagon { radius } => (1, vec![radius]),
+            MapShape::Rhombus { width, height } => (2, vec![width, height]),
+            MapShape::Triangle { size } => (3, vec![size]),
+        };
+        writer.write_all(&[shape])?;
+        for n in size {
+            writer.write_all(&n.to_le_bytes())?;
+        }
+        writer.write_all(&[self.wraps_horizontally() as u8])?;
+        let orientation = match self.orientation() {
+            Orientation::PointyTop => 0,
+            Orientation::FlatTop => 1,
+        };
+        writer.write_all(&[orientation])?;
+
+        for color in self.hex_colors.as_slice() {
+            write_color(writer, *color)?;
         }
 
-        let index = self.hex_array_index(q, r);
-        self.hex_colors[index] = color;
+        for int in self.hex_ints.as_slice() {
+            writer.write_all(&int.to_le_bytes())?;
+        }
+
+        for wall in self.walls.as_slice() {
+            write_wall(writer, *wall)?;
+        }
+
+        for kind in self.wall_kinds.as_slice() {
+            writer.write_all(&[wall_kind_to_u8(*kind)])?;
+        }
+
+        Ok(())
+    }
+
+    /// Loads a grid previously saved with `save()` from `reader`.
+    ///
+    /// Fails with `io::ErrorKind::InvalidData` if the data doesn't look like a
+    /// saved hex grid, or if it was saved in a format version we don't know
+    /// about.
+    pub fn load<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != FILE_MAGIC {
+            return Err(invalid_data("not a hex grid file"));
+        }
+
+        match read_u32(reader)? {
+            version @ 1..=5 => Self::load_v1_to_v5(reader, version),
+            version => Err(invalid_data(&format!(
+                "unsupported hex grid file version {}",
+                version
+            ))),
+        }
+    }
+
+    fn load_v1_to_v5<R: Read>(reader: &mut R, version: u32) -> io::Result<Self> {
+        let shape = if version >= 4 { read_u8(reader)? } else { 0 };
+
+        // Don't trust the sizes blindly: `new()` would panic on these, and huge
+        // values would make us try to allocate absurd amounts of memory.
+        const MAX_SIZE: i32 = 1 << 14;
+        let mut read_size = |min: i32| -> io::Result<i32> {
+            let size = read_i32(reader)?;
+            if size < min || size > MAX_SIZE {
+                return Err(invalid_data(&format!("invalid hex grid size {}", size)));
+            }
+            Ok(size)
+        };
+        let shape = match shape {
+            0 => MapShape::Rectangle {
+                width: read_size(1)?,
+                height: read_size(1)?,
+            },
+            1 => MapShape::Hexagon {
+                radius: read_size(0)?,
+            },
+            2 => MapShape::Rhombus {
+                width: read_size(1)?,
+                height: read_size(1)?,
+            },
+            3 => MapShape::Triangle {
+                size: read_size(1)?,
+            },
+            _ => return Err(invalid_data("invalid hex grid shape")),
+        };
+
+        let wraps = if version >= 2 {
+            match read_u8(reader)? {
+                0 => false,
+                1 => true,
+                _ => return Err(invalid_data("invalid wrapping flag")),
+            }
+        } else {
+            false
+        };
+        let orientation = if version >= 5 {
+            match read_u8(reader)? {
+                0 => Orientation::PointyTop,
+                1 => Orientation::FlatTop,
+                _ => return Err(invalid_data("invalid orientation")),
+            }
+        } else {
+            Orientation::PointyTop
+        };
+        let layout = match (shape, orientation) {
+            (MapShape::Rectangle { width, height }, Orientation::PointyTop) => {
+                GridLayout::new(width, height, wraps)
+            }
+            _ if wraps => return Err(invalid_data("only pointy-top rectangles can wrap around")),
+            _ => GridLayout::with_orientation(shape, orientation),
+        };
+
+        let mut hex_grid = Self::with_layout(layout);
+
+        for color in hex_grid.hex_colors.as_mut_slice() {
+            *color = read_color(reader)?;
+        }
+
+        for int in hex_grid.hex_ints.as_mut_slice() {
+            *int = read_i32(reader)?;
+        }
+
+        for wall in hex_grid.walls.as_mut_slice() {
+            *wall = read_wall(reader)?;
+        }
+
+        if version >= 3 {
+            for kind in hex_grid.wall_kinds.as_mut_slice() {
+                *kind = wall_kind_from_u8(read_u8(reader)?)
+                    .ok_or_else(|| invalid_data("invalid wall kind"))?;
+            }
+        }
+
+        Ok(hex_grid)
+    }
+}
+
+/// Shorthands for `wall()` and `set_wall()` in each direction.
+///
+/// Deprecated: use `wall()` and `set_wall()` with a `Direction` instead.
+#[allow(dead_code)]
+impl HexGrid {
+    pub fn w_wall(&self, q: i32, r: i32) -> Option<Color> {
+        self.wall(q, r, Direction::West)
+    }
+
+    pub fn nw_wall(&self, q: i32, r: i32) -> Option<Color> {
+        self.wall(q, r, Direction::NorthWest)
+    }
+
+    pub fn ne_wall(&self, q: i32, r: i32) -> Option<Color> {
+        self.wall(q, r, Direction::NorthEast)
     }
 
     pub fn set_w_wall(&mut self, q: i32, r: i32, color: Option<Color>) {
-        let index = self.hex_array_index_ext(q, r);
-        self.w_wall[index] = color;
+        self.set_wall(q, r, Direction::West, color);
     }
 
     pub fn set_nw_wall(&mut self, q: i32, r: i32, color: Option<Color>) {
-        let index = self.hex_array_index_ext(q, r);
-        self.nw_wall[index] = color;
+        self.set_wall(q, r, Direction::NorthWest, color);
     }
 
     pub fn set_ne_wall(&mut self, q: i32, r: i32, color: Option<Color>) {
-        let index = self.hex_array_index_ext(q, r);
-        self.ne_wall[index] = color;
+        self.set_wall(q, r, Direction::NorthEast, color);
     }
 
-    // East wall is the same as the west wall of the hex to the east. Similar to
-    // the cases below.
     pub fn set_e_wall(&mut self, q: i32, r: i32, color: Option<Color>) {
-        let index = self.hex_array_index_ext(q + 1, r);
-        self.w_wall[index] = color;
+        self.set_wall(q, r, Direction::East, color);
     }
 
     pub fn set_se_wall(&mut self, q: i32, r: i32, color: Option<Color>) {
-        let index = self.hex_array_index_ext(q, r + 1);
-        self.nw_wall[index] = color;
+        self.set_wall(q, r, Direction::SouthEast, color);
     }
 
     pub fn set_sw_wall(&mut self, q: i32, r: i32, color: Option<Color>) {
-        let index = self.hex_array_index_ext(q - 1, r + 1);
-        self.ne_wall[index] = color;
+        self.set_wall(q, r, Direction::SouthWest, color);
     }
+}
 
-    pub fn hex_int(&self, q: i32, r: i32) -> Option<i32> {
-        if !self.are_coords_valid(q, r) {
-            None
-        } else {
-            let index = self.hex_array_index(q, r);
-            Some(self.hex_ints[index])
+/// Returns the distance, in number of hexes, between two hexes given in axial
+/// coordinates.
+pub fn axial_distance(a: (i32, i32), b: (i32, i32)) -> u32 {
+    let dq = a.0 - b.0;
+    let dr = a.1 - b.1;
+    ((dq.abs() + dr.abs() + (dq + dr).abs()) / 2) as u32
+}
+
+/// Returns the three names of the vertex at corner `corner` of the hex at
+/// (`q`, `r`), one for each hex around it: that same one, and the neighbors
+/// across the walls on each side of the corner. See
+/// `HexGrid::normalize_vertex()`.
+pub(crate) fn vertex_names(q: i32, r: i32, corner: u8) -> [Vertex; 3] {
+    let after = Direction::ALL[corner as usize % 6].offset();
+    let before = Direction::ALL[(corner as usize + 5) % 6].offset();
+    [
+        (q, r, corner % 6),
+        (q + after.0, r + after.1, (corner + 4) % 6),
+        (q + before.0, r + before.1, (corner + 2) % 6),
+    ]
+}
+
+/// The position of the center of the hex at the given axial coordinates, in a
+/// coordinate system in which the hex size is 1 (and the center of the hex at
+/// (0, 0) is the origin). Same as `HexGridRenderer::hex_center()`, just scaled.
+///
+/// Always pointy-top: flat-top grids are the same, only turned 30 degrees, so
+/// anything that doesn't care which way is up can use it for them, too.
+pub(crate) fn hex_center((q, r): (i32, i32)) -> (f64, f64) {
+    let x = 3.0f64.sqrt() * (q as f64 + r as f64 / 2.0);
+    let y = 1.5 * r as f64;
+    (x, y)
+}
+
+/// Rounds fractional axial coordinates to the axial coordinates of the hex
+/// containing them.
+pub fn axial_round(q: f64, r: f64) -> (i32, i32) {
+    let s = -q - r;
+    let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+    let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+    if dq > dr && dq > ds {
+        rq = -rr - rs;
+    } else if dr > ds {
+        rr = -rq - rs;
+    }
+    (rq as i32, rr as i32)
+}
+
+//
+// File format helpers
+//
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut bytes = [0u8; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+pub(crate) fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_i32<R: Read>(reader: &mut R) -> io::Result<i32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+pub(crate) fn write_color<W: Write>(writer: &mut W, color: Color) -> io::Result<()> {
+    writer.write_all(&[color.r, color.g, color.b, color.a])
+}
+
+pub(crate) fn read_color<R: Read>(reader: &mut R) -> io::Result<Color> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(Color::new(bytes[0], bytes[1], bytes[2], bytes[3]))
+}
+
+// A wall is stored as a presence flag followed by its color (which is all
+// zeros when there is no wall).
+pub(crate) fn write_wall<W: Write>(writer: &mut W, wall: Option<Color>) -> io::Result<()> {
+    match wall {
+        Some(color) => {
+            writer.write_all(&[1])?;
+            write_color(writer, color)
         }
+        None => writer.write_all(&[0, 0, 0, 0, 0]),
     }
+}
 
-    pub fn w_wall(&self, q: i32, r: i32) -> Option<Color> {
-        let index = self.hex_array_index_ext(q, r);
-        self.w_wall[index]
+pub(crate) fn read_wall<R: Read>(reader: &mut R) -> io::Result<Option<Color>> {
+    let flag = read_u8(reader)?;
+    let color = read_color(reader)?;
+    match flag {
+        0 => Ok(None),
+        1 => Ok(Some(color)),
+        _ => Err(invalid_data("invalid wall flag")),
     }
+}
 
-    pub fn nw_wall(&self, q: i32, r: i32) -> Option<Color> {
-        let index = self.hex_array_index_ext(q, r);
-        self.nw_wall[index]
+// A wall kind is stored as a single byte: the index of the kind in
+// `WallKind::ALL`, except for one-way passages, which take 4 plus their
+// direction.
+pub(crate) fn wall_kind_to_u8(kind: WallKind) -> u8 {
+    match kind {
+        WallKind::Solid => 0,
+        WallKind::OpenDoor => 1,
+        WallKind::LockedDoor => 2,
+        WallKind::Window => 3,
+        WallKind::OneWay(dir) => 4 + dir as u8,
     }
+}
 
-    pub fn ne_wall(&self, q: i32, r: i32) -> Option<Color> {
-        let index = self.hex_array_index_ext(q, r);
-        self.ne_wall[index]
+pub(crate) fn wall_kind_from_u8(byte: u8) -> Option<WallKind> {
+    match byte {
+        0 => Some(WallKind::Solid),
+        1 => Some(WallKind::OpenDoor),
+        2 => Some(WallKind::LockedDoor),
+        3 => Some(WallKind::Window),
+        4..=9 => Some(WallKind::OneWay(Direction::ALL[(byte - 4) as usize])),
+        _ => None,
     }
+}
 
-    /// Iterates over all valid axial coordinates in the grid.
-    pub fn axial_coords(&self) -> impl Iterator<Item = (i32, i32)> {
-        let w = self.width;
-        let h = self.height;
-        let mut i = 0;
-        let mut j = -1;
-        std::iter::from_fn(move || {
-            j += 1;
-            if j >= w {
-                i += 1;
-                j = 0;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex_map::HexMap;
+
+    // A grid with a bit of everything, including walls stored in the extra row
+    // and column.
+    fn sample_grid() -> HexGrid {
+        let mut hex_grid = HexGrid::new(5, 4);
+        for (i, (q, r)) in hex_grid.axial_coords().enumerate() {
+            let i = i as u8;
+            hex_grid.set_hex_color(q, r, Color::new(i, 255 - i, i / 2, 255));
+        }
+
+        hex_grid.set_wall(1, 1, Direction::West, Some(Color::RED));
+        hex_grid.set_wall(2, 0, Direction::NorthWest, Some(Color::BLUE));
+        hex_grid.set_wall(0, 2, Direction::NorthEast, Some(Color::new(1, 2, 3, 4)));
+
+        // Walls in the extra column, row, and in the extra hex to the west of
+        // odd rows.
+        hex_grid.set_wall(4, 0, Direction::East, Some(Color::YELLOW));
+        hex_grid.set_wall(4, 1, Direction::East, Some(Color::ORANGE));
+        hex_grid.set_wall(1, 3, Direction::SouthEast, Some(Color::GRAY));
+        hex_grid.set_wall(-1, 3, Direction::SouthWest, Some(Color::BROWN));
+        hex_grid.set_wall(0, 0, Direction::SouthWest, Some(Color::DARKGREEN));
+
+        hex_grid.set_wall_kind(1, 1, Direction::West, WallKind::Window);
+        hex_grid.set_wall_kind(4, 1, Direction::East, WallKind::OpenDoor);
+        hex_grid.set_wall_kind(
+            0,
+            2,
+            Direction::NorthEast,
+            WallKind::OneWay(Direction::SouthWest),
+        );
+
+        hex_grid
+    }
+
+    fn assert_grids_equal(a: &HexGrid, b: &HexGrid) {
+        assert_eq!(a.layout, b.layout);
+        assert_eq!(a.hex_colors, b.hex_colors);
+        assert_eq!(a.hex_ints, b.hex_ints);
+        assert_eq!(a.walls, b.walls);
+        assert_eq!(a.wall_kinds, b.wall_kinds);
+    }
+
+    #[test]
+    fn test_direction() {
+        for dir in Direction::ALL {
+            let (dq, dr) = dir.offset();
+            let (oq, or) = dir.opposite().offset();
+            assert_eq!((dq + oq, dr + or), (0, 0));
+            assert_eq!(dir.opposite().opposite(), dir);
+        }
+        assert_eq!(Direction::West.corners(), (3, 4));
+        assert_eq!(Direction::NorthEast.corners(), (5, 0));
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let hex_grid = HexGrid::new(4, 3);
+
+        let all: Vec<_> = hex_grid.neighbors(1, 1).collect();
+        assert_eq!(all.len(), 6);
+        for (dir, (q, r)) in all {
+            assert_eq!(hex_grid.neighbor(1, 1, dir), Some((q, r)));
+            assert_eq!(hex_grid.neighbor(q, r, dir.opposite()), Some((1, 1)));
+        }
+
+        // Corners of the grid have fewer neighbors.
+        let top_left: Vec<_> = hex_grid.neighbors(0, 0).collect();
+        assert_eq!(
+            top_left,
+            vec![(Direction::East, (1, 0)), (Direction::SouthEast, (0, 1)),]
+        );
+        assert_eq!(hex_grid.neighbor(0, 0, Direction::West), None);
+        assert_eq!(hex_grid.neighbors(3, 1).count(), 3);
+        assert_eq!(hex_grid.neighbors(-1, 2).count(), 2);
+    }
+
+    #[test]
+    fn test_walls_are_shared() {
+        let mut hex_grid = HexGrid::new(4, 3);
+        for (q, r) in hex_grid.axial_coords() {
+            for dir in Direction::ALL {
+                let color = Some(Color::new(q as u8, r as u8, dir as u8, 255));
+                hex_grid.set_wall(q, r, dir, color);
+                assert_eq!(hex_grid.wall(q, r, dir), color);
+
+                // The same wall, seen from the other side.
+                let (dq, dr) = dir.offset();
+                assert_eq!(hex_grid.wall(q + dq, r + dr, dir.opposite()), color);
             }
-            if i >= h {
-                None
-            } else {
-                let r = i;
-                let q = (-i / 2) + j;
-                Some((q, r))
+        }
+    }
+
+    #[test]
+    fn test_wall_shorthands() {
+        let mut hex_grid = HexGrid::new(4, 3);
+        hex_grid.set_w_wall(1, 1, Some(Color::RED));
+        hex_grid.set_nw_wall(1, 1, Some(Color::GREEN));
+        hex_grid.set_ne_wall(1, 1, Some(Color::BLUE));
+        hex_grid.set_e_wall(1, 1, Some(Color::YELLOW));
+        hex_grid.set_se_wall(1, 1, Some(Color::ORANGE));
+        hex_grid.set_sw_wall(1, 1, Some(Color::GRAY));
+
+        assert_eq!(hex_grid.wall(1, 1, Direction::West), Some(Color::RED));
+        assert_eq!(
+            hex_grid.wall(1, 1, Direction::NorthWest),
+            Some(Color::GREEN)
+        );
+        assert_eq!(hex_grid.wall(1, 1, Direction::NorthEast), Some(Color::BLUE));
+        assert_eq!(hex_grid.wall(2, 1, Direction::West), Some(Color::YELLOW));
+        assert_eq!(
+            hex_grid.wall(1, 2, Direction::NorthWest),
+            Some(Color::ORANGE)
+        );
+        assert_eq!(hex_grid.wall(0, 2, Direction::NorthEast), Some(Color::GRAY));
+
+        assert_eq!(hex_grid.w_wall(2, 1), Some(Color::YELLOW));
+        assert_eq!(hex_grid.nw_wall(1, 2), Some(Color::ORANGE));
+        assert_eq!(hex_grid.ne_wall(0, 2), Some(Color::GRAY));
+    }
+
+    #[test]
+    fn test_wall_shorthands_wrap() {
+        let mut hex_grid = HexGrid::new_wrapping(4, 3);
+        hex_grid.set_e_wall(3, 0, Some(Color::RED));
+        assert_eq!(hex_grid.w_wall(0, 0), Some(Color::RED));
+        assert_eq!(hex_grid.w_wall(4, 0), Some(Color::RED));
+    }
+
+    #[test]
+    fn test_walls_along_edges() {
+        // The walls along the edges of the grid are stored in the extra row and
+        // column, and must not step on each other.
+        let mut hex_grid = HexGrid::new(3, 4);
+        let mut expected = Vec::new();
+        for (i, (q, r)) in hex_grid.axial_coords().enumerate() {
+            for dir in Direction::ALL {
+                if hex_grid.neighbor(q, r, dir).is_none() {
+                    let color = Some(Color::new(i as u8, dir as u8, 0, 255));
+                    hex_grid.set_wall(q, r, dir, color);
+                    expected.push((q, r, dir, color));
+                }
             }
-        })
+        }
+
+        for (q, r, dir, color) in expected {
+            assert_eq!(hex_grid.wall(q, r, dir), color, "({}, {}) {:?}", q, r, dir);
+        }
     }
 
-    /// Iterates over all "extra" axial coordinates in the grid. (Actually, over
-    /// all normal hex plus the extra ones!)
-    pub fn axial_coords_ext(&self) -> impl Iterator<Item = (i32, i32)> {
-        let w = self.width + 1;
-        let h = self.height + 1;
-        let mut i = 0;
-        let mut j = -1;
-        std::iter::from_fn(move || {
-            j += 1;
-
-            // We don't need the extra column on the extra row.
-            if i == h - 1 && j == w - 1 {
-                return None;
+    #[test]
+    fn test_vertices() {
+        let hex_grid = HexGrid::new(4, 4);
+
+        // All three names of each corner are the same vertex.
+        for corner in 0..6 {
+            let expected = hex_grid.normalize_vertex(1, 1, corner);
+            assert!(matches!(expected, Some((_, _, 2 | 5))));
+            for (q, r, c) in vertex_names(1, 1, corner) {
+                assert_eq!(hex_grid.normalize_vertex(q, r, c), expected);
             }
+        }
+        assert_eq!(hex_grid.normalize_vertex(0, 0, 0), Some((1, -1, 2)));
+        assert_eq!(hex_grid.normalize_vertex(1, 1, 8), Some((1, 1, 2)));
 
-            if j >= w {
-                i += 1;
+        // Vertices of the hexes along the edges are fine, others aren't.
+        assert_eq!(hex_grid.normalize_vertex(0, 0, 4), Some((0, -1, 2)));
+        assert_eq!(hex_grid.normalize_vertex(10, 10, 0), None);
+        assert_eq!(hex_grid.normalize_vertex(0, -1, 5), None);
 
-                // Odd rows start with an extra hex to the west (and the same
-                // extra hex appears on the wast side, too).
-                j = if i & 1 == 1 { -1 } else { 0 };
+        let hex_grid = HexGrid::new_wrapping(4, 4);
+        assert_eq!(hex_grid.normalize_vertex(4, 0, 5), Some((0, 0, 5)));
+        assert_eq!(hex_grid.normalize_vertex(7, 3, 1), Some((-1, 4, 5)));
+    }
+
+    #[test]
+    fn test_vertex_edges() {
+        let mut hex_grid = HexGrid::new(4, 4);
+
+        // Inside the grid, every vertex has three edges, and each leads to a
+        // vertex with an edge back along the same wall.
+        for corner in 0..6 {
+            let vertex = hex_grid.normalize_vertex(1, 2, corner).unwrap();
+            let edges = hex_grid.vertex_edges(vertex.0, vertex.1, vertex.2);
+            assert_eq!(edges.len(), 3);
+            for (end, (q, r, dir)) in edges {
+                hex_grid.set_wall(q, r, dir, Some(Color::RED));
+                let back = hex_grid.vertex_edges(end.0, end.1, end.2);
+                let (_, (q, r, dir)) = back.iter().find(|(v, _)| *v == vertex).unwrap();
+                assert_eq!(hex_grid.wall(*q, *r, *dir), Some(Color::RED));
             }
-            let r = i;
-            let q = (-i / 2) + j;
-            return Some((q, r));
-        })
+        }
+
+        // The edge going up from the top of (0, 0) is out of the grid.
+        assert_eq!(hex_grid.vertex_edges(0, 0, 5).len(), 2);
+        assert!(hex_grid.vertex_edges(0, -1, 5).is_empty());
     }
 
-    //
-    // Internal helpers
-    //
+    #[test]
+    fn test_vertex_conversions() {
+        let hex_grid = HexGrid::new(4, 4);
+        let vertices = hex_grid.hex_vertices(1, 2).unwrap();
+        for (i, &(q, r, corner)) in vertices.iter().enumerate() {
+            assert!(!vertices[..i].contains(&(q, r, corner)));
+
+            let hexes = hex_grid.vertex_hexes(q, r, corner);
+            assert_eq!(hexes.len(), 3);
+            assert!(hexes.contains(&(1, 2)));
+
+            // The walls leaving a vertex go between it and its neighbors.
+            for (end, (q, r, dir)) in hex_grid.vertex_edges(q, r, corner) {
+                let (a, b) = hex_grid.wall_vertices(q, r, dir).unwrap();
+                assert!((a, b) == (vertices[i], end) || (b, a) == (vertices[i], end));
+            }
+        }
+        assert_eq!(
+            hex_grid.wall_vertices(1, 2, Direction::East),
+            Some((vertices[0], vertices[1]))
+        );
 
-    /// Checks if the given axial coordinates are valid.
-    fn are_coords_valid(&self, q: i32, r: i32) -> bool {
-        let r2 = r / 2;
-        r >= 0 && r < self.height && q >= -r2 && q < self.width - r2
+        // Along the borders.
+        assert_eq!(hex_grid.vertex_hexes(0, 0, 5), vec![(0, 0)]);
+        assert!(hex_grid.vertex_hexes(10, 0, 5).is_empty());
+        assert_eq!(hex_grid.hex_vertices(4, 0), None);
+        assert_eq!(hex_grid.wall_vertices(10, 0, Direction::East), None);
     }
 
-    /// Returns the index where we store the hex located at the given axial
-    /// coordinates.
-    ///
-    /// Validity of the coordinates is not checked by design (because this is
-    /// also used for the "extra hexes" that go beyond the normally valid hex
-    /// coordinates).
-    fn hex_array_index(&self, q: i32, r: i32) -> usize {
-        // r grows by 1 every row we go down.
-        let y = r;
+    #[test]
+    fn test_wall_kinds() {
+        let mut hex_grid = HexGrid::new(4, 3);
+        assert_eq!(hex_grid.wall_kind(1, 1, Direction::East), None);
+        assert!(hex_grid.can_cross(1, 1, Direction::East));
+        assert!(!hex_grid.blocks_sight(1, 1, Direction::East));
+
+        // No kind without a wall.
+        hex_grid.set_wall_kind(1, 1, Direction::East, WallKind::Window);
+        assert_eq!(hex_grid.wall_kind(1, 1, Direction::East), None);
+
+        // Kinds are shared by both sides of the wall, like colors.
+        hex_grid.set_wall(1, 1, Direction::East, Some(Color::BLACK));
+        assert_eq!(
+            hex_grid.wall_kind(2, 1, Direction::West),
+            Some(WallKind::Solid)
+        );
+        hex_grid.set_wall_kind(2, 1, Direction::West, WallKind::Window);
+        assert_eq!(
+            hex_grid.wall_kind(1, 1, Direction::East),
+            Some(WallKind::Window)
+        );
+        assert!(!hex_grid.can_cross(1, 1, Direction::East));
+        assert!(!hex_grid.blocks_sight(1, 1, Direction::East));
+
+        hex_grid.set_wall_kind(1, 1, Direction::East, WallKind::OpenDoor);
+        assert!(hex_grid.can_cross(1, 1, Direction::East));
+        assert!(hex_grid.can_cross(2, 1, Direction::West));
+
+        hex_grid.set_wall_kind(1, 1, Direction::East, WallKind::LockedDoor);
+        assert!(!hex_grid.can_cross(2, 1, Direction::West));
+        assert!(hex_grid.blocks_sight(2, 1, Direction::West));
+
+        // One way only, whichever side it was set from.
+        let one_way = WallKind::OneWay(Direction::West);
+        hex_grid.set_wall_kind(1, 1, Direction::East, one_way);
+        assert!(hex_grid.can_cross(2, 1, Direction::West));
+        assert!(!hex_grid.can_cross(1, 1, Direction::East));
+        assert!(!hex_grid.blocks_sight(1, 1, Direction::East));
+
+        // Removing the wall forgets its kind.
+        hex_grid.set_wall(1, 1, Direction::East, None);
+        hex_grid.set_wall(1, 1, Direction::East, Some(Color::BLACK));
+        assert_eq!(
+            hex_grid.wall_kind(1, 1, Direction::East),
+            Some(WallKind::Solid)
+        );
+
+        for kind in WallKind::ALL {
+            assert_eq!(wall_kind_from_u8(wall_kind_to_u8(kind)), Some(kind));
+        }
+        assert_eq!(wall_kind_from_u8(10), None);
+    }
+
+    #[test]
+    fn test_walls_out_of_bounds() {
+        let mut hex_grid = HexGrid::new(3, 3);
+        hex_grid.set_wall(10, 10, Direction::East, Some(Color::RED));
+        assert_eq!(hex_grid.wall(10, 10, Direction::East), None);
+
+        // Walls between an extra hex and a valid one are fine, though.
+        hex_grid.set_wall(3, 0, Direction::West, Some(Color::RED));
+        assert_eq!(hex_grid.wall(2, 0, Direction::East), Some(Color::RED));
+    }
+
+    #[test]
+    fn test_extra_layers() {
+        let mut hex_grid = HexGrid::new_wrapping(4, 3);
+        assert!(hex_grid.layer::<HexLayer<u8>>("elevation").is_none());
+
+        hex_grid.add_layer("elevation", HexLayer::new(hex_grid.layout(), 0u8));
+        hex_grid.add_layer("doors", EdgeLayer::new(hex_grid.layout(), false));
+
+        let elevation = hex_grid.layer_mut::<HexLayer<u8>>("elevation").unwrap();
+        elevation.set(-1, 1, 3);
+        let doors = hex_grid.layer_mut::<EdgeLayer<bool>>("doors").unwrap();
+        doors.set(1, 1, Direction::East, true);
+
+        let elevation = hex_grid.layer::<HexLayer<u8>>("elevation").unwrap();
+        assert_eq!(elevation.get(3, 1), Some(&3));
+        let doors = hex_grid.layer::<EdgeLayer<bool>>("doors").unwrap();
+        assert_eq!(doors.get(2, 1, Direction::West), Some(&true));
+
+        // Asking for the wrong type is like asking for a missing layer.
+        assert!(hex_grid.layer::<HexLayer<i32>>("elevation").is_none());
+        assert!(hex_grid.layer::<HexLayer<bool>>("doors").is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_extra_layer_layout_mismatch() {
+        let mut hex_grid = HexGrid::new(4, 3);
+        hex_grid.add_layer(
+            "elevation",
+            HexLayer::new(GridLayout::new(3, 4, false), 0u8),
+        );
+    }
+
+    #[test]
+    fn test_save_load_round_trip() {
+        let hex_grid = sample_grid();
+
+        let mut data = Vec::new();
+        hex_grid.save(&mut data).unwrap();
+        let loaded = HexGrid::load(&mut data.as_slice()).unwrap();
+
+        assert_grids_equal(&hex_grid, &loaded);
+        assert_eq!(loaded.wall(4, 0, Direction::East), Some(Color::YELLOW));
+        assert_eq!(loaded.wall(4, 1, Direction::East), Some(Color::ORANGE));
+        assert_eq!(loaded.wall(1, 3, Direction::SouthEast), Some(Color::GRAY));
+        assert_eq!(loaded.wall(-1, 3, Direction::SouthWest), Some(Color::BROWN));
+        assert_eq!(
+            loaded.wall(0, 0, Direction::SouthWest),
+            Some(Color::DARKGREEN)
+        );
+        assert_eq!(
+            loaded.wall_kind(4, 1, Direction::East),
+            Some(WallKind::OpenDoor)
+        );
+        assert_eq!(
+            loaded.wall_kind(1, 1, Direction::SouthWest),
+            Some(WallKind::OneWay(Direction::SouthWest))
+        );
+    }
+
+    #[test]
+    fn test_save_load_wrapping() {
+        let mut hex_grid = HexGrid::new_wrapping(4, 3);
+        hex_grid.set_hex_color(-1, 0, Color::RED);
+        hex_grid.set_wall(3, 1, Direction::East, Some(Color::BLUE));
+
+        let mut data = Vec::new();
+        hex_grid.save(&mut data).unwrap();
+        let loaded = HexGrid::load(&mut data.as_slice()).unwrap();
+
+        assert_grids_equal(&hex_grid, &loaded);
+        assert!(loaded.wraps_horizontally());
+        assert_eq!(loaded.hex_color(3, 0), Some(Color::RED));
+        assert_eq!(loaded.wall(0, 1, Direction::West), Some(Color::BLUE));
+    }
+
+    #[test]
+    fn test_load_older_versions() {
+        // Version 4 files are the same as version 5, minus the orientation
+        // byte.
+        let mut hex_grid = sample_grid();
+        let mut data = Vec::new();
+        hex_grid.save(&mut data).unwrap();
+        data[8..12].copy_from_slice(&4u32.to_le_bytes());
+        data.remove(22);
+
+        let loaded = HexGrid::load(&mut data.as_slice()).unwrap();
+        assert_grids_equal(&hex_grid, &loaded);
+
+        // Version 3 files are the same as version 4, minus the shape byte.
+        data[8..12].copy_from_slice(&3u32.to_le_bytes());
+        data.remove(12);
+
+        let loaded = HexGrid::load(&mut data.as_slice()).unwrap();
+        assert_grids_equal(&hex_grid, &loaded);
 
-        // q grows by 1 every (jagged) column we go right, but the coordinates
-        // are shifted to the left every other row.
-        let r2 = r / 2;
-        let x = q + r2;
+        // Version 2 files are the same as version 3, minus the wall kinds, so
+        // all their walls are solid.
+        hex_grid.wall_kinds.as_mut_slice().fill(WallKind::Solid);
+        data.truncate(data.len() - hex_grid.wall_kinds.as_slice().len());
+        data[8..12].copy_from_slice(&2u32.to_le_bytes());
 
-        // Now we can treat our storage as a 2D array.
-        (y * self.width + x) as usize
+        let loaded = HexGrid::load(&mut data.as_slice()).unwrap();
+        assert_grids_equal(&hex_grid, &loaded);
+
+        // Version 1 files are the same as version 2, minus the wrapping flag.
+        data[8..12].copy_from_slice(&1u32.to_le_bytes());
+        data.remove(20);
+
+        let loaded = HexGrid::load(&mut data.as_slice()).unwrap();
+        assert_grids_equal(&hex_grid, &loaded);
+    }
+
+    #[test]
+    fn test_shapes() {
+        let shapes = [
+            (MapShape::Hexagon { radius: 3 }, 37),
+            (MapShape::Hexagon { radius: 0 }, 1),
+            (
+                MapShape::Rhombus {
+                    width: 4,
+                    height: 3,
+                },
+                12,
+            ),
+            (MapShape::Triangle { size: 4 }, 10),
+        ];
+        for (shape, count) in shapes {
+            let mut hex_grid = HexGrid::with_shape(shape);
+            assert_eq!(hex_grid.axial_coords().count(), count, "{:?}", shape);
+
+            // Every hex has its own color, and every wall along the edges of
+            // the grid its own slot (and is drawn by some hex owning edges).
+            let owners: Vec<_> = hex_grid.axial_coords_ext().collect();
+            let mut expected = Vec::new();
+            for (i, (q, r)) in hex_grid.axial_coords().enumerate() {
+                let i = i as u8;
+                hex_grid.set_hex_color(q, r, Color::new(i, 0, 0, 255));
+                for dir in Direction::ALL {
+                    let color = Some(Color::new(i, dir as u8, 1, 255));
+                    if hex_grid.neighbor(q, r, dir).is_none() {
+                        hex_grid.set_wall(q, r, dir, color);
+                        expected.push((q, r, dir, color));
+                    }
+
+                    let (dq, dr) = dir.offset();
+                    let owner = match dir {
+                        Direction::West | Direction::NorthWest | Direction::NorthEast => (q, r),
+                        _ => (q + dq, r + dr),
+                    };
+                    assert!(owners.contains(&owner), "{:?} {:?}", shape, owner);
+                }
+            }
+            for (i, (q, r)) in hex_grid.axial_coords().enumerate() {
+                assert_eq!(
+                    hex_grid.hex_color(q, r),
+                    Some(Color::new(i as u8, 0, 0, 255))
+                );
+            }
+            for &(q, r, dir, color) in &expected {
+                assert_eq!(
+                    hex_grid.wall(q, r, dir),
+                    color,
+                    "{:?} ({}, {})",
+                    shape,
+                    q,
+                    r
+                );
+            }
+
+            let mut data = Vec::new();
+            hex_grid.save(&mut data).unwrap();
+            let loaded = HexGrid::load(&mut data.as_slice()).unwrap();
+            assert_grids_equal(&hex_grid, &loaded);
+            assert_eq!(loaded.shape(), shape);
+        }
+
+        // Corners of each shape.
+        let hex_grid = HexGrid::with_shape(MapShape::Hexagon { radius: 3 });
+        for hex in [(3, 0), (0, 3), (-3, 3), (-3, 0), (0, -3), (3, -3)] {
+            assert!(hex_grid.are_coords_valid(hex.0, hex.1));
+        }
+        assert!(!hex_grid.are_coords_valid(3, 1));
+        assert!(!hex_grid.are_coords_valid(-2, -2));
+        assert_eq!(hex_grid.neighbors(0, 0).count(), 6);
+        assert_eq!(hex_grid.neighbors(3, 0).count(), 3);
+
+        let hex_grid = HexGrid::with_shape(MapShape::Triangle { size: 4 });
+        assert!(hex_grid.are_coords_valid(3, 0));
+        assert!(hex_grid.are_coords_valid(0, 3));
+        assert!(!hex_grid.are_coords_valid(2, 2));
+        assert!(!hex_grid.are_coords_valid(-1, 2));
+    }
+
+    #[test]
+    fn test_flat_top() {
+        let shape = MapShape::Rectangle {
+            width: 5,
+            height: 3,
+        };
+        let mut hex_grid = HexGrid::with_orientation(shape, Orientation::FlatTop);
+        assert_eq!(hex_grid.orientation(), Orientation::FlatTop);
+        assert_eq!((hex_grid.width(), hex_grid.height()), (5, 3));
+        assert_eq!(hex_grid.axial_coords().count(), 15);
+
+        // Column by column, every other one shifted half a hex down.
+        let coords: Vec<_> = hex_grid.axial_coords().take(7).collect();
+        assert_eq!(
+            coords,
+            vec![(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2), (2, -1)]
+        );
+        assert!(hex_grid.are_coords_valid(4, 0));
+        assert!(!hex_grid.are_coords_valid(4, 1));
+
+        // Walls are shared, and the ones along the edges are covered by the
+        // hexes owning edges.
+        hex_grid.set_wall(1, 1, Direction::SouthEast, Some(Color::RED));
+        assert_eq!(hex_grid.wall(1, 2, Direction::NorthWest), Some(Color::RED));
+        let owners: Vec<_> = hex_grid.axial_coords_ext().collect();
+        let owned = hex_grid.layout().owned_directions();
+        for (q, r) in hex_grid.axial_coords() {
+            for dir in Direction::ALL {
+                let (dq, dr) = dir.offset();
+                let owner = if owned.contains(&dir) {
+                    (q, r)
+                } else {
+                    (q + dq, r + dr)
+                };
+                assert!(owners.contains(&owner), "{:?}", owner);
+            }
+        }
+
+        let mut data = Vec::new();
+        hex_grid.save(&mut data).unwrap();
+        let loaded = HexGrid::load(&mut data.as_slice()).unwrap();
+        assert_grids_equal(&hex_grid, &loaded);
+        assert_eq!(loaded.shape(), shape);
+        assert_eq!(loaded.orientation(), Orientation::FlatTop);
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let mut hex_grid = HexGrid::new_wrapping(5, 4);
+
+        // Hexes past the edges resolve to the other side.
+        assert_eq!(hex_grid.normalize(5, 0), Some((0, 0)));
+        assert_eq!(hex_grid.normalize(-1, 0), Some((4, 0)));
+        assert_eq!(hex_grid.normalize(-2, 3), Some((3, 3)));
+        assert_eq!(hex_grid.normalize(12, 2), Some((2, 2)));
+        assert_eq!(hex_grid.normalize(0, 4), None);
+        assert_eq!(hex_grid.normalize(0, -1), None);
+        assert_eq!(HexGrid::new(5, 4).normalize(5, 0), None);
+
+        hex_grid.set_hex_color(-1, 1, Color::RED);
+        assert_eq!(hex_grid.hex_color(4, 1), Some(Color::RED));
+        assert_eq!(hex_grid.hex_int(-1, 1), hex_grid.hex_int(4, 1));
+
+        // Every hex has six neighbors, except on the top and bottom rows.
+        assert_eq!(hex_grid.neighbors(4, 1).count(), 6);
+        assert_eq!(hex_grid.neighbor(4, 1, Direction::East), Some((0, 1)));
+        assert_eq!(hex_grid.neighbor(0, 0, Direction::West), Some((4, 0)));
+        assert_eq!(hex_grid.neighbors(0, 0).count(), 4);
+
+        // Walls across the seam are shared by both sides.
+        hex_grid.set_wall(4, 1, Direction::East, Some(Color::BLUE));
+        assert_eq!(hex_grid.wall(0, 1, Direction::West), Some(Color::BLUE));
+        assert_eq!(hex_grid.wall(5, 1, Direction::West), Some(Color::BLUE));
+        hex_grid.set_wall(0, 0, Direction::SouthWest, Some(Color::GRAY));
+        assert_eq!(hex_grid.wall(4, 1, Direction::NorthEast), Some(Color::GRAY));
+
+        // Distances take the shortest way around.
+        assert_eq!(hex_grid.distance((0, 0), (4, 0)), 1);
+        assert_eq!(hex_grid.distance((0, 0), (3, 0)), 2);
+        assert_eq!(hex_grid.distance((0, 0), (2, 0)), 2);
+        assert_eq!(hex_grid.distance((0, 0), (3, 2)), 2);
+        assert_eq!(hex_grid.unwrap_towards((0, 0), (4, 0)), (-1, 0));
+        assert_eq!(HexGrid::new(5, 4).distance((0, 0), (4, 0)), 4);
+    }
+
+    #[test]
+    fn test_wrapping_walls_are_shared() {
+        let mut hex_grid = HexGrid::new_wrapping(4, 3);
+        for (q, r) in hex_grid.axial_coords() {
+            for dir in Direction::ALL {
+                let color = Some(Color::new(q as u8, r as u8, dir as u8, 255));
+                hex_grid.set_wall(q, r, dir, color);
+                assert_eq!(hex_grid.wall(q, r, dir), color);
+                if let Some((nq, nr)) = hex_grid.neighbor(q, r, dir) {
+                    assert_eq!(hex_grid.wall(nq, nr, dir.opposite()), color);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_save_load_file() {
+        let hex_grid = sample_grid();
+        let path =
+            std::env::temp_dir().join(format!("hex_grid_test_{}.hexgrid", std::process::id()));
+
+        hex_grid.save_to_file(&path).unwrap();
+        let loaded = HexGrid::load_from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_grids_equal(&hex_grid, &loaded.unwrap());
     }
 
-    fn hex_array_index_ext(&self, q: i32, r: i32) -> usize {
-        // r grows by 1 every row we go down.
-        let y = r;
+    #[test]
+    fn test_load_rejects_bad_data() {
+        let mut data = Vec::new();
+        sample_grid().save(&mut data).unwrap();
+
+        // Wrong magic.
+        let mut bad = data.clone();
+        bad[0] = b'X';
+        let err = HexGrid::load(&mut bad.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Version from the future.
+        let mut bad = data.clone();
+        bad[8..12].copy_from_slice(&99u32.to_le_bytes());
+        let err = HexGrid::load(&mut bad.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Nonsense shape and size.
+        let mut bad = data.clone();
+        bad[12] = 7;
+        let err = HexGrid::load(&mut bad.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let mut bad = data.clone();
+        bad[13..17].copy_from_slice(&(-3i32).to_le_bytes());
+        let err = HexGrid::load(&mut bad.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Nonsense orientation.
+        let mut bad = data.clone();
+        bad[22] = 2;
+        let err = HexGrid::load(&mut bad.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
 
-        // q grows by 1 every (jagged) column we go right, but the coordinates
-        // are shifted to the left every other row.
-        let r2 = r / 2;
-        let x = q + r2;
+        // Nonsense wall kind.
+        let mut bad = data.clone();
+        *bad.last_mut().unwrap() = 42;
+        let err = HexGrid::load(&mut bad.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
 
-        // Now we can treat our storage as a 2D array.
-        (y * (self.width + 1) + x) as usize
+        // Truncated.
+        let bad = &data[..data.len() - 1];
+        let err = HexGrid::load(&mut &bad[..]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
     }
 }
@@ -0,0 +1,263 @@
+use crate::hex_grid::*;
+use crate::layers::Orientation;
+use raylib::prelude::*;
+
+use std::ops::Range;
+
+/// The hex, wall and iteration API shared by the grid backends: `HexGrid`,
+/// with fixed bounds and everything allocated up front, and
+/// `ChunkedHexGrid`, effectively unbounded and allocated in chunks as needed.
+///
+/// The renderer and the algorithms (pathfinding, visibility, the painting
+/// tools...) work on anything implementing this, so they work on either
+/// backend.
+pub trait HexMap {
+    /// Returns which way the hexes of the map are turned.
+    fn orientation(&self) -> Orientation;
+
+    /// Returns the canonical axial coordinates of the hex at the given axial
+    /// coordinates, or `None` if there is no such hex in the map. See
+    /// `HexGrid::normalize()`.
+    fn normalize(&self, q: i32, r: i32) -> Option<(i32, i32)>;
+
+    /// Returns the canonical name of the vertex at corner `corner` of the hex
+    /// at (`q`, `r`), or `None` if none of the hexes around it are in the map.
+    /// See `HexGrid::normalize_vertex()`.
+    fn normalize_vertex(&self, q: i32, r: i32, corner: u8) -> Option<Vertex>;
+
+    /// Returns the color of the hex at the given axial coordinates, or `None`
+    /// if there is no such hex in the map.
+    fn hex_color(&self, q: i32, r: i32) -> Option<Color>;
+
+    /// Sets the color of the hex at the given axial coordinates. See each
+    /// backend for which hexes can be set.
+    fn set_hex_color(&mut self, q: i32, r: i32, color: Color);
+
+    /// Returns the wall of the hex at (`q`, `r`) in the given direction. See
+    /// `HexGrid::wall()`.
+    fn wall(&self, q: i32, r: i32, dir: Direction) -> Option<Color>;
+
+    /// Sets the wall of the hex at (`q`, `r`) in the given direction. See
+    /// `HexGrid::set_wall()`.
+    fn set_wall(&mut self, q: i32, r: i32, dir: Direction, color: Option<Color>);
+
+    /// Returns the kind of the wall of the hex at (`q`, `r`) in the given
+    /// direction, or `None` if there is no wall there.
+    fn wall_kind(&self, q: i32, r: i32, dir: Direction) -> Option<WallKind>;
+
+    /// Sets the kind of the wall of the hex at (`q`, `r`) in the given
+    /// direction. Ignored if there is no wall there.
+    fn set_wall_kind(&mut self, q: i32, r: i32, dir: Direction, kind: WallKind);
+
+    /// Returns the directions of the three walls each hex owns: drawing those
+    /// for every hex in `axial_coords_ext()` covers all walls.
+    fn owned_directions(&self) -> [Direction; 3];
+
+    /// Iterates over the axial coordinates of all hexes in the map.
+    fn axial_coords(&self) -> Box<dyn Iterator<Item = (i32, i32)> + '_>;
+
+    /// Iterates over the axial coordinates of all hexes owning walls of the
+    /// map (see `owned_directions()`): the hexes of the map, and then some.
+    fn axial_coords_ext(&self) -> Box<dyn Iterator<Item = (i32, i32)> + '_>;
+
+    /// Returns the range of rows (see `GridLayout::rows()`) that hexes of the
+    /// map (or, with `ext`, hexes owning walls) can be in. Together with
+    /// `row_span()`, this lets the renderer only look at the hexes in view.
+    fn rows(&self, ext: bool) -> Range<i32>;
+
+    /// Returns the range of positions along row `row` (see
+    /// `GridLayout::row_span()`) that hexes of the map (or, with `ext`, hexes
+    /// owning walls) can be in. Hexes in these ranges may still be out of the
+    /// map: it is only guaranteed that there are none outside of them.
+    fn row_span(&self, row: i32, ext: bool) -> Range<i32>;
+
+    /// Returns the axial coordinates of the hex at position `x` along row
+    /// `row`. See `GridLayout::row_hex()`.
+    fn row_hex(&self, row: i32, x: i32) -> (i32, i32) {
+        match self.orientation() {
+            Orientation::PointyTop => (x, row),
+            Orientation::FlatTop => (row, x),
+        }
+    }
+
+    /// Returns a number to tell the hex at the given axial coordinates apart,
+    /// for debugging, if the map keeps one.
+    fn hex_int(&self, _q: i32, _r: i32) -> Option<i32> {
+        None
+    }
+
+    /// Returns the copy of the hex `to` closest to the hex `from`. This is
+    /// simply `to` unless the map wraps around horizontally; in this case, it
+    /// is `to` shifted by some multiple of the map width, so that going from
+    /// `from` straight to the result takes the shortest way around the map.
+    ///
+    /// Useful to draw or measure things across the seam. The result may be out
+    /// of the canonical range of coordinates, of course.
+    fn unwrap_towards(&self, _from: (i32, i32), to: (i32, i32)) -> (i32, i32) {
+        to
+    }
+
+    /// Checks if the given axial coordinates are those of a hex in the map.
+    fn are_coords_valid(&self, q: i32, r: i32) -> bool {
+        self.normalize(q, r).is_some()
+    }
+
+    /// Returns the canonical axial coordinates of the neighbor of the hex at
+    /// (`q`, `r`) in the given direction, or `None` if that neighbor is out of
+    /// the map.
+    fn neighbor(&self, q: i32, r: i32, dir: Direction) -> Option<(i32, i32)> {
+        let (dq, dr) = dir.offset();
+        self.normalize(q + dq, r + dr)
+    }
+
+    /// Iterates over the neighbors of the hex at (`q`, `r`) that are in the
+    /// map, yielding their direction and axial coordinates.
+    fn neighbors(&self, q: i32, r: i32) -> Box<dyn Iterator<Item = (Direction, (i32, i32))> + '_> {
+        Box::new(
+            Direction::ALL
+                .iter()
+                .filter_map(move |&dir| self.neighbor(q, r, dir).map(|hex| (dir, hex))),
+        )
+    }
+
+    /// Returns the distance, in number of hexes, between two hexes. Takes the
+    /// shortest way around on maps that wrap around.
+    fn distance(&self, a: (i32, i32), b: (i32, i32)) -> u32 {
+        axial_distance(a, self.unwrap_towards(a, b))
+    }
+
+    /// Checks if we can leave the hex at (`q`, `r`) in direction `dir`, as far
+    /// as its wall on that side is concerned (see
+    /// `WallKind::blocks_movement()`). Doesn't check that there is a hex to go
+    /// to.
+    fn can_cross(&self, q: i32, r: i32, dir: Direction) -> bool {
+        !self
+            .wall_kind(q, r, dir)
+            .is_some_and(|kind| kind.blocks_movement(dir))
+    }
+
+    /// Checks if the wall of the hex at (`q`, `r`) in direction `dir` (if any)
+    /// blocks sight.
+    fn blocks_sight(&self, q: i32, r: i32, dir: Direction) -> bool {
+        self.wall_kind(q, r, dir)
+            .is_some_and(WallKind::blocks_sight)
+    }
+
+    /// Returns the (up to three) edges leaving the given vertex: the canonical
+    /// name of the vertex at their other end, and the wall along them, as the
+    /// coordinates and direction of a hex to pass to `wall()` or `set_wall()`.
+    ///
+    /// Only edges touching at least one hex of the map are included. Returns
+    /// nothing for vertices out of the map.
+    fn vertex_edges(&self, q: i32, r: i32, corner: u8) -> Vec<(Vertex, (i32, i32, Direction))> {
+        let mut edges = Vec::new();
+        let Some((q, r, corner)) = self.normalize_vertex(q, r, corner) else {
+            return edges;
+        };
+
+        // Each of the three hexes around the vertex has two of the edges,
+        // going to the corners before and after this one.
+        for (q, r, corner) in vertex_names(q, r, corner) {
+            let prev = (corner + 5) % 6;
+            let next = (corner + 1) % 6;
+            for (dir, other) in [
+                (Direction::ALL[prev as usize], prev),
+                (Direction::ALL[corner as usize], next),
+            ] {
+                if !self.are_coords_valid(q, r) && self.neighbor(q, r, dir).is_none() {
+                    continue;
+                }
+                if let Some(end) = self.normalize_vertex(q, r, other) {
+                    if !edges.iter().any(|(e, _)| *e == end) {
+                        edges.push((end, (q, r, dir)));
+                    }
+                }
+            }
+        }
+
+        edges
+    }
+}
+
+impl HexMap for HexGrid {
+    fn orientation(&self) -> Orientation {
+        HexGrid::orientation(self)
+    }
+
+    fn normalize(&self, q: i32, r: i32) -> Option<(i32, i32)> {
+        HexGrid::normalize(self, q, r)
+    }
+
+    fn normalize_vertex(&self, q: i32, r: i32, corner: u8) -> Option<Vertex> {
+        HexGrid::normalize_vertex(self, q, r, corner)
+    }
+
+    fn hex_color(&self, q: i32, r: i32) -> Option<Color> {
+        HexGrid::hex_color(self, q, r)
+    }
+
+    fn set_hex_color(&mut self, q: i32, r: i32, color: Color) {
+        HexGrid::set_hex_color(self, q, r, color)
+    }
+
+    fn wall(&self, q: i32, r: i32, dir: Direction) -> Option<Color> {
+        HexGrid::wall(self, q, r, dir)
+    }
+
+    fn set_wall(&mut self, q: i32, r: i32, dir: Direction, color: Option<Color>) {
+        HexGrid::set_wall(self, q, r, dir, color)
+    }
+
+    fn wall_kind(&self, q: i32, r: i32, dir: Direction) -> Option<WallKind> {
+        HexGrid::wall_kind(self, q, r, dir)
+    }
+
+    fn set_wall_kind(&mut self, q: i32, r: i32, dir: Direction, kind: WallKind) {
+        HexGrid::set_wall_kind(self, q, r, dir, kind)
+    }
+
+    fn owned_directions(&self) -> [Direction; 3] {
+        self.layout().owned_directions()
+    }
+
+    fn axial_coords(&self) -> Box<dyn Iterator<Item = (i32, i32)> + '_> {
+        Box::new(HexGrid::axial_coords(self))
+    }
+
+    fn axial_coords_ext(&self) -> Box<dyn Iterator<Item = (i32, i32)> + '_> {
+        Box::new(HexGrid::axial_coords_ext(self))
+    }
+
+    fn rows(&self, ext: bool) -> Range<i32> {
+        if ext {
+            self.layout().ext_rows()
+        } else {
+            self.layout().rows()
+        }
+    }
+
+    fn row_span(&self, row: i32, ext: bool) -> Range<i32> {
+        if ext {
+            self.layout().ext_row_span(row)
+        } else {
+            self.layout().row_span(row)
+        }
+    }
+
+    fn hex_int(&self, q: i32, r: i32) -> Option<i32> {
+        HexGrid::hex_int(self, q, r)
+    }
+
+    fn unwrap_towards(&self, from: (i32, i32), to: (i32, i32)) -> (i32, i32) {
+        if !self.wraps_horizontally() {
+            return to;
+        }
+
+        let width = self.width();
+        let k = ((from.0 - to.0) as f32 / width as f32).round() as i32;
+        (k - 1..=k + 1)
+            .map(|k| (to.0 + k * width, to.1))
+            .min_by_key(|&hex| axial_distance(from, hex))
+            .unwrap()
+    }
+}
@@ -0,0 +1,368 @@
+use crate::hex_grid::*;
+use raylib::prelude::*;
+
+use std::collections::VecDeque;
+
+/// A single change made to a grid, with enough information to apply it again
+/// or to take it back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    /// The hex at (`q`, `r`) was painted.
+    HexColor {
+        q: i32,
+        r: i32,
+        before: Color,
+        after: Color,
+    },
+
+    /// The wall of the hex at (`q`, `r`) in direction `dir` was added, removed
+    /// or repainted.
+    Wall {
+        q: i32,
+        r: i32,
+        dir: Direction,
+        before: Option<Color>,
+        after: Option<Color>,
+    },
+
+    /// The kind of the wall of the hex at (`q`, `r`) in direction `dir` was
+    /// changed.
+    WallKind {
+        q: i32,
+        r: i32,
+        dir: Direction,
+        before: WallKind,
+        after: WallKind,
+    },
+}
+
+impl Change {
+    /// Makes the change (again).
+    pub fn apply(&self, hex_grid: &mut HexGrid) {
+        match *self {
+            Change::HexColor { q, r, after, .. } => hex_grid.set_hex_color(q, r, after),
+            Change::Wall {
+                q, r, dir, after, ..
+            } => hex_grid.set_wall(q, r, dir, after),
+            Change::WallKind {
+                q, r, dir, after, ..
+            } => hex_grid.set_wall_kind(q, r, dir, after),
+        }
+    }
+
+    /// Takes the change back.
+    pub fn revert(&self, hex_grid: &mut HexGrid) {
+        match *self {
+            Change::HexColor { q, r, before, .. } => hex_grid.set_hex_color(q, r, before),
+            Change::Wall {
+                q, r, dir, before, ..
+            } => hex_grid.set_wall(q, r, dir, before),
+            Change::WallKind {
+                q, r, dir, before, ..
+            } => hex_grid.set_wall_kind(q, r, dir, before),
+        }
+    }
+}
+
+/// Undo and redo for changes made to a grid.
+///
+/// Changes are made through the history (instead of directly on the grid), so
+/// that it can record them. All changes made between two calls to
+/// `end_step()` are grouped in a single step, which is what `undo()` and
+/// `redo()` work with: in the editor, a step is a whole mouse stroke.
+///
+/// Only the last `max_steps` steps are kept; older ones are forgotten.
+pub struct History {
+    /// The steps that can be undone, oldest first.
+    undo_steps: VecDeque<Vec<Change>>,
+
+    /// The steps that can be redone, most recently undone last.
+    redo_steps: Vec<Vec<Change>>,
+
+    /// The changes in the step being recorded.
+    current: Vec<Change>,
+
+    /// The maximum number of steps in `undo_steps`.
+    max_steps: usize,
+}
+
+impl History {
+    /// Creates a new, empty history that remembers up to `max_steps` steps.
+    pub fn new(max_steps: usize) -> Self {
+        Self {
+            undo_steps: VecDeque::new(),
+            redo_steps: Vec::new(),
+            current: Vec::new(),
+            max_steps,
+        }
+    }
+
+    /// Paints the hex at (`q`, `r`), recording the change. Painting a hex out
+    /// of the grid, or with the color it already has, changes nothing and
+    /// records nothing.
+    pub fn set_hex_color(&mut self, hex_grid: &mut HexGrid, q: i32, r: i32, color: Color) {
+        let Some(before) = hex_grid.hex_color(q, r) else {
+            return;
+        };
+
+        if before != color {
+            hex_grid.set_hex_color(q, r, color);
+            self.record(Change::HexColor {
+                q,
+                r,
+                before,
+                after: color,
+            });
+        }
+    }
+
+    /// Sets the wall of the hex at (`q`, `r`) in direction `dir`, recording
+    /// the change. Same as `HexGrid::set_wall()` otherwise, and like in
+    /// `set_hex_color()`, changes that change nothing are not recorded.
+    pub fn set_wall(
+        &mut self,
+        hex_grid: &mut HexGrid,
+        q: i32,
+        r: i32,
+        dir: Direction,
+        color: Option<Color>,
+    ) {
+        // Removing a wall forgets its kind, so record that first: undoing
+        // puts the wall back, then its kind.
+        if color.is_none() {
+            self.set_wall_kind(hex_grid, q, r, dir, WallKind::Solid);
+        }
+
+        let before = hex_grid.wall(q, r, dir);
+        hex_grid.set_wall(q, r, dir, color);
+
+        // Reading it back also catches walls `set_wall()` ignored.
+        let after = hex_grid.wall(q, r, dir);
+        if before != after {
+            self.record(Change::Wall {
+                q,
+                r,
+                dir,
+                before,
+                after,
+            });
+        }
+    }
+
+    /// Sets the kind of the wall of the hex at (`q`, `r`) in direction `dir`,
+    /// recording the change. Same as `HexGrid::set_wall_kind()` otherwise.
+    pub fn set_wall_kind(
+        &mut self,
+        hex_grid: &mut HexGrid,
+        q: i32,
+        r: i32,
+        dir: Direction,
+        kind: WallKind,
+    ) {
+        let Some(before) = hex_grid.wall_kind(q, r, dir) else {
+            return;
+        };
+        hex_grid.set_wall_kind(q, r, dir, kind);
+
+        let after = hex_grid.wall_kind(q, r, dir).unwrap_or(before);
+        if before != after {
+            self.record(Change::WallKind {
+                q,
+                r,
+                dir,
+                before,
+                after,
+            });
+        }
+    }
+
+    /// Finishes the step being recorded. Does nothing if no changes were
+    /// recorded since the last step.
+    pub fn end_step(&mut self) {
+        if self.current.is_empty() {
+            return;
+        }
+
+        self.undo_steps.push_back(std::mem::take(&mut self.current));
+        while self.undo_steps.len() > self.max_steps {
+            self.undo_steps.pop_front();
+        }
+    }
+
+    /// Takes back the last step (finishing the current one first). Returns
+    /// `false` if there was nothing to undo.
+    pub fn undo(&mut self, hex_grid: &mut HexGrid) -> bool {
+        self.end_step();
+        let Some(step) = self.undo_steps.pop_back() else {
+            return false;
+        };
+
+        for change in step.iter().rev() {
+            change.revert(hex_grid);
+        }
+        self.redo_steps.push(step);
+        true
+    }
+
+    /// Makes again the last step taken back by `undo()`. Returns `false` if
+    /// there was nothing to redo.
+    pub fn redo(&mut self, hex_grid: &mut HexGrid) -> bool {
+        self.end_step();
+        let Some(step) = self.redo_steps.pop() else {
+            return false;
+        };
+
+        for change in &step {
+            change.apply(hex_grid);
+        }
+        self.undo_steps.push_back(step);
+        true
+    }
+
+    /// Forgets everything. Needed whenever the grid is replaced by a different
+    /// one.
+    pub fn clear(&mut self) {
+        self.undo_steps.clear();
+        self.redo_steps.clear();
+        self.current.clear();
+    }
+
+    //
+    // Internal helpers
+    //
+
+    fn record(&mut self, change: Change) {
+        // A new change makes whatever was undone impossible to redo.
+        self.redo_steps.clear();
+        self.current.push(change);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colors(hex_grid: &HexGrid) -> Vec<Color> {
+        hex_grid
+            .axial_coords()
+            .map(|(q, r)| hex_grid.hex_color(q, r).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_undo_redo_stroke() {
+        let mut hex_grid = HexGrid::new(4, 3);
+        let mut history = History::new(10);
+        let original = colors(&hex_grid);
+
+        // One stroke painting a few hexes and adding a wall.
+        history.set_hex_color(&mut hex_grid, 0, 0, Color::RED);
+        history.set_hex_color(&mut hex_grid, 1, 0, Color::RED);
+        history.set_wall(&mut hex_grid, 1, 0, Direction::East, Some(Color::BLACK));
+        history.end_step();
+        let painted = colors(&hex_grid);
+
+        assert!(history.undo(&mut hex_grid));
+        assert_eq!(colors(&hex_grid), original);
+        assert_eq!(hex_grid.wall(2, 0, Direction::West), None);
+        assert!(!history.undo(&mut hex_grid));
+
+        assert!(history.redo(&mut hex_grid));
+        assert_eq!(colors(&hex_grid), painted);
+        assert_eq!(hex_grid.wall(2, 0, Direction::West), Some(Color::BLACK));
+        assert!(!history.redo(&mut hex_grid));
+    }
+
+    #[test]
+    fn test_steps_are_undone_in_order() {
+        let mut hex_grid = HexGrid::new(4, 3);
+        let mut history = History::new(10);
+
+        history.set_wall(&mut hex_grid, 1, 1, Direction::West, Some(Color::BLACK));
+        history.end_step();
+        history.set_wall(&mut hex_grid, 1, 1, Direction::West, Some(Color::RED));
+        history.end_step();
+        history.set_wall(&mut hex_grid, 0, 1, Direction::East, None);
+        history.end_step();
+
+        assert!(history.undo(&mut hex_grid));
+        assert_eq!(hex_grid.wall(1, 1, Direction::West), Some(Color::RED));
+        assert!(history.undo(&mut hex_grid));
+        assert_eq!(hex_grid.wall(1, 1, Direction::West), Some(Color::BLACK));
+        assert!(history.undo(&mut hex_grid));
+        assert_eq!(hex_grid.wall(1, 1, Direction::West), None);
+    }
+
+    #[test]
+    fn test_undo_wall_kinds() {
+        let mut hex_grid = HexGrid::new(4, 3);
+        let mut history = History::new(10);
+        let dir = Direction::East;
+
+        history.set_wall(&mut hex_grid, 1, 1, dir, Some(Color::BLACK));
+        history.set_wall_kind(&mut hex_grid, 1, 1, dir, WallKind::LockedDoor);
+        history.end_step();
+        history.set_wall(&mut hex_grid, 1, 1, dir, None);
+        history.end_step();
+
+        // Putting the wall back brings its kind back, too.
+        assert!(history.undo(&mut hex_grid));
+        assert_eq!(hex_grid.wall_kind(1, 1, dir), Some(WallKind::LockedDoor));
+        assert!(history.redo(&mut hex_grid));
+        assert_eq!(hex_grid.wall_kind(1, 1, dir), None);
+
+        assert!(history.undo(&mut hex_grid));
+        assert!(history.undo(&mut hex_grid));
+        assert_eq!(hex_grid.wall_kind(1, 1, dir), None);
+        assert!(history.redo(&mut hex_grid));
+        assert_eq!(hex_grid.wall_kind(1, 1, dir), Some(WallKind::LockedDoor));
+    }
+
+    #[test]
+    fn test_no_op_changes_are_not_recorded() {
+        let mut hex_grid = HexGrid::new(4, 3);
+        let mut history = History::new(10);
+        hex_grid.set_hex_color(0, 0, Color::RED);
+
+        history.set_hex_color(&mut hex_grid, 0, 0, Color::RED);
+        history.set_hex_color(&mut hex_grid, 10, 0, Color::RED);
+        history.set_wall(&mut hex_grid, 0, 0, Direction::West, None);
+        history.set_wall(&mut hex_grid, 10, 10, Direction::West, Some(Color::RED));
+        history.set_wall_kind(&mut hex_grid, 0, 0, Direction::West, WallKind::Window);
+        history.end_step();
+        assert!(!history.undo(&mut hex_grid));
+    }
+
+    #[test]
+    fn test_new_change_discards_redo() {
+        let mut hex_grid = HexGrid::new(4, 3);
+        let mut history = History::new(10);
+
+        history.set_hex_color(&mut hex_grid, 0, 0, Color::RED);
+        history.end_step();
+        history.undo(&mut hex_grid);
+        history.set_hex_color(&mut hex_grid, 1, 0, Color::BLUE);
+        history.end_step();
+
+        assert!(!history.redo(&mut hex_grid));
+        assert_eq!(hex_grid.hex_color(0, 0), Some(Color::MAGENTA));
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut hex_grid = HexGrid::new(4, 3);
+        let mut history = History::new(2);
+
+        for (i, (q, r)) in hex_grid.axial_coords().take(5).enumerate() {
+            let i = i as u8;
+            history.set_hex_color(&mut hex_grid, q, r, Color::new(i, i, i, 255));
+            history.end_step();
+        }
+
+        assert!(history.undo(&mut hex_grid));
+        assert!(history.undo(&mut hex_grid));
+        assert!(!history.undo(&mut hex_grid));
+        assert_eq!(hex_grid.hex_color(2, 0), Some(Color::new(2, 2, 2, 255)));
+        assert_eq!(hex_grid.hex_color(3, 0), Some(Color::MAGENTA));
+    }
+}
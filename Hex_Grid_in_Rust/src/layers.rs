@@ -0,0 +1,903 @@
+use crate::hex_grid::{vertex_names, Direction, Vertex};
+
+use std::any::Any;
+use std::ops::Range;
+
+/// The shape and size of a grid, in axial coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapShape {
+    /// `width` hexes across and `height` rows down, with every other row
+    /// shifted by half a hex (so the sides zigzag). The only shape that can
+    /// wrap around horizontally. (Flat-top rectangles go in columns instead:
+    /// see `Orientation`.)
+    Rectangle { width: i32, height: i32 },
+
+    /// Every hex up to `radius` hexes away from (0, 0).
+    Hexagon { radius: i32 },
+
+    /// The hexes with `q` in `0..width` and `r` in `0..height`: a
+    /// parallelogram, slanting to the south-east.
+    Rhombus { width: i32, height: i32 },
+
+    /// The hexes with `q >= 0`, `r >= 0` and `q + r < size`: a triangle with
+    /// `size` hexes on each side, pointing down.
+    Triangle { size: i32 },
+}
+
+/// Which way the hexes of a grid are turned. Chosen when the grid is created.
+///
+/// Axial coordinates, directions and corners work the same either way: a
+/// flat-top grid looks like a pointy-top one turned 30 degrees clockwise, so
+/// each direction points 30 degrees clockwise of its name (`East` is really
+/// south-east, `NorthWest` is north, and so on), and corner 0 is the east one.
+///
+/// Shapes, though, are laid out in columns instead of rows: a flat-top
+/// `Rectangle` has `width` columns of `height` hexes, with every other column
+/// shifted by half a hex. Under the hood, a flat-top grid is laid out like a
+/// pointy-top one mirrored along the diagonal (swapping `q` and `r`), which
+/// turns its rows into columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Orientation {
+    /// A corner at the top of each hex, and hexes in rows.
+    #[default]
+    PointyTop,
+
+    /// An edge at the top of each hex, and hexes in columns. Flat-top grids
+    /// can't wrap around.
+    FlatTop,
+}
+
+/// The shape of a grid: its size, which way its hexes are turned, and whether
+/// it wraps around horizontally.
+///
+/// This is what maps axial coordinates to indices into the vectors where each
+/// layer stores its data, so it is shared by the grid and all of its layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridLayout {
+    /// The shape and size of the grid, as laid out: mirrored, for flat-top
+    /// grids (see `mirror()`).
+    shape: MapShape,
+
+    /// Which way the hexes are turned.
+    orientation: Orientation,
+
+    /// Does the grid wrap around horizontally? Only pointy-top rectangles can.
+    wraps: bool,
+}
+
+impl GridLayout {
+    /// Creates a new pointy-top rectangular layout.
+    pub fn new(width: i32, height: i32, wraps: bool) -> Self {
+        let mut layout = Self::with_shape(MapShape::Rectangle { width, height });
+        layout.wraps = wraps;
+        layout
+    }
+
+    /// Creates a new pointy-top layout of any shape, which doesn't wrap around.
+    pub fn with_shape(shape: MapShape) -> Self {
+        Self::with_orientation(shape, Orientation::PointyTop)
+    }
+
+    /// Creates a new layout of any shape and orientation, which doesn't wrap
+    /// around.
+    pub fn with_orientation(shape: MapShape, orientation: Orientation) -> Self {
+        let (what, size, min) = match shape {
+            MapShape::Rectangle { width, height } | MapShape::Rhombus { width, height } => {
+                assert!(width > 0, "width must be greater than 0, got {}", width);
+                ("height", height, 1)
+            }
+            MapShape::Hexagon { radius } => ("radius", radius, 0),
+            MapShape::Triangle { size } => ("size", size, 1),
+        };
+        assert!(
+            size >= min,
+            "{} must be at least {}, got {}",
+            what,
+            min,
+            size
+        );
+
+        Self {
+            shape: mirror_shape(shape, orientation),
+            orientation,
+            wraps: false,
+        }
+    }
+
+    /// Returns the shape of the grid.
+    pub fn shape(&self) -> MapShape {
+        mirror_shape(self.shape, self.orientation)
+    }
+
+    /// Returns which way the hexes of the grid are turned.
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Returns the number of hexes across the grid: in the widest row, for
+    /// pointy-top grids, and the number of columns, for flat-top ones.
+    pub fn width(&self) -> i32 {
+        match self.orientation {
+            Orientation::PointyTop => self.row_len(),
+            Orientation::FlatTop => self.rows().len() as i32,
+        }
+    }
+
+    /// Returns the number of hexes down the grid: the number of rows, for
+    /// pointy-top grids, and in the tallest column, for flat-top ones.
+    pub fn height(&self) -> i32 {
+        match self.orientation {
+            Orientation::PointyTop => self.rows().len() as i32,
+            Orientation::FlatTop => self.row_len(),
+        }
+    }
+
+    /// Checks if the grid wraps around horizontally.
+    pub fn wraps_horizontally(&self) -> bool {
+        self.wraps
+    }
+
+    /// Returns the range of `r` coordinates of the rows of the grid. On
+    /// flat-top grids, rows run north to south (so they are really columns),
+    /// and this is the range of their `q` coordinates. See `row_hex()`.
+    pub fn rows(&self) -> Range<i32> {
+        match self.shape {
+            MapShape::Rectangle { height, .. } | MapShape::Rhombus { height, .. } => 0..height,
+            MapShape::Hexagon { radius } => -radius..radius + 1,
+            MapShape::Triangle { size } => 0..size,
+        }
+    }
+
+    /// Returns the range of canonical `q` coordinates (`r` coordinates, on
+    /// flat-top grids) of the hexes in row `r`, which is empty if there is no
+    /// such row.
+    pub fn row_span(&self, r: i32) -> Range<i32> {
+        if !self.rows().contains(&r) {
+            return 0..0;
+        }
+
+        match self.shape {
+            MapShape::Rectangle { width, .. } => -(r / 2)..width - r / 2,
+            MapShape::Hexagon { radius } => (-radius).max(-radius - r)..radius.min(radius - r) + 1,
+            MapShape::Rhombus { width, .. } => 0..width,
+            MapShape::Triangle { size } => 0..size - r,
+        }
+    }
+
+    /// Returns the range of `r` coordinates of the rows of hexes owning edges
+    /// (see `EdgeLayer`): the rows of the grid, plus one more to the south.
+    pub fn ext_rows(&self) -> Range<i32> {
+        let rows = self.rows();
+        rows.start..rows.end + 1
+    }
+
+    /// Like `row_span()`, but for the hexes owning edges: the hexes of the
+    /// grid, plus their neighbors to the east, south-east and south-west.
+    pub fn ext_row_span(&self, r: i32) -> Range<i32> {
+        let own = self.row_span(r);
+        let north = self.row_span(r - 1);
+        match (own.is_empty(), north.is_empty()) {
+            (true, true) => 0..0,
+            (false, true) => own.start..own.end + 1,
+            (true, false) => north.start - 1..north.end,
+            (false, false) => own.start.min(north.start - 1)..(own.end + 1).max(north.end),
+        }
+    }
+
+    /// Returns the axial coordinates of the hex at position `x` along row `row`
+    /// (see `rows()` and `row_span()`): (`x`, `row`) on pointy-top grids, and
+    /// (`row`, `x`) on flat-top ones.
+    pub fn row_hex(&self, row: i32, x: i32) -> (i32, i32) {
+        self.mirror(x, row)
+    }
+
+    /// Returns the directions of the three edges each hex owns (see
+    /// `EdgeLayer`): west, north-west and north-east on pointy-top grids, and
+    /// mirrored on flat-top ones (which, being turned, puts them on the
+    /// north, north-west and south-west sides of each hex).
+    pub fn owned_directions(&self) -> [Direction; 3] {
+        [Direction::West, Direction::NorthWest, Direction::NorthEast]
+            .map(|dir| self.mirror_dir(dir))
+    }
+
+    /// Returns the canonical axial coordinates of the hex at the given axial
+    /// coordinates, or `None` if they are not valid. See
+    /// `HexGrid::normalize()`.
+    pub fn normalize(&self, q: i32, r: i32) -> Option<(i32, i32)> {
+        let (q, r) = self.mirror(q, r);
+        let (q, r) = self.normalize_mirrored(q, r)?;
+        Some(self.mirror(q, r))
+    }
+
+    /// Returns the canonical name of the vertex at corner `corner` of the hex
+    /// at (`q`, `r`), or `None` if none of the hexes around it are valid. See
+    /// `HexGrid::normalize_vertex()`.
+    pub fn normalize_vertex(&self, q: i32, r: i32, corner: u8) -> Option<Vertex> {
+        let (q, r, corner) = self.mirror_vertex(q, r, corner);
+        let (q, r, corner) = self.normalize_vertex_mirrored(q, r, corner)?;
+        Some(self.mirror_vertex(q, r, corner))
+    }
+
+    //
+    // Internal helpers
+    //
+    // These work in the coordinates hexes are laid out in, which are the axial
+    // coordinates mirrored along the diagonal on flat-top grids (see
+    // `mirror()`), so that everything is laid out in rows, pointy-top style.
+    // Only the public methods, and the `*_index()` ones, take plain axial
+    // coordinates.
+    //
+
+    /// Swaps `q` and `r` on flat-top grids, and leaves them alone on pointy-top
+    /// ones. Its own inverse.
+    fn mirror(&self, q: i32, r: i32) -> (i32, i32) {
+        match self.orientation {
+            Orientation::PointyTop => (q, r),
+            Orientation::FlatTop => (r, q),
+        }
+    }
+
+    /// Like `mirror()`, but for directions: mirroring swaps east and
+    /// south-east, south-west and north-east, and west and north-west.
+    fn mirror_dir(&self, dir: Direction) -> Direction {
+        match self.orientation {
+            Orientation::PointyTop => dir,
+            Orientation::FlatTop => Direction::ALL[(7 - dir as usize) % 6],
+        }
+    }
+
+    /// Like `mirror()`, but for vertices. The corners between the walls
+    /// swapped by `mirror_dir()` get swapped, too.
+    fn mirror_vertex(&self, q: i32, r: i32, corner: u8) -> Vertex {
+        match self.orientation {
+            Orientation::PointyTop => (q, r, corner),
+            Orientation::FlatTop => (r, q, (8 - corner % 6) % 6),
+        }
+    }
+
+    /// The number of hexes in the longest row.
+    fn row_len(&self) -> i32 {
+        match self.shape {
+            MapShape::Rectangle { width, .. } | MapShape::Rhombus { width, .. } => width,
+            MapShape::Hexagon { radius } => 2 * radius + 1,
+            MapShape::Triangle { size } => size,
+        }
+    }
+
+    /// Like `normalize()`, in mirrored coordinates.
+    fn normalize_mirrored(&self, q: i32, r: i32) -> Option<(i32, i32)> {
+        if !self.wraps {
+            return self.row_span(r).contains(&q).then_some((q, r));
+        }
+
+        if !self.rows().contains(&r) {
+            return None;
+        }
+        let r2 = r / 2;
+        let x = (q + r2).rem_euclid(self.row_len());
+        Some((x - r2, r))
+    }
+
+    /// Like `normalize_vertex()`, in mirrored coordinates.
+    fn normalize_vertex_mirrored(&self, q: i32, r: i32, corner: u8) -> Option<Vertex> {
+        // The top and bottom corners of the neighbors across the walls on
+        // each side of the corner.
+        let (dq, dr, corner) = match corner % 6 {
+            0 => (1, -1, 2),
+            1 => (0, 1, 5),
+            3 => (-1, 1, 5),
+            4 => (0, -1, 2),
+            corner => (0, 0, corner),
+        };
+        let (mut q, r) = (q + dq, r + dr);
+
+        let valid = vertex_names(q, r, corner)
+            .iter()
+            .any(|&(q, r, _)| self.normalize_mirrored(q, r).is_some());
+        if !valid {
+            return None;
+        }
+
+        // Like `normalize()`, but the hex may be out of the grid.
+        if self.wraps {
+            let r2 = r / 2;
+            q = (q + r2).rem_euclid(self.row_len()) - r2;
+        }
+
+        Some((q, r, corner))
+    }
+
+    /// The number of elements in a hex layer.
+    fn hex_len(&self) -> usize {
+        self.row_start(self.rows().end)
+    }
+
+    /// Returns the index of the first hex of row `r` in a hex layer (or, for
+    /// the row after the last one, the number of hexes).
+    fn row_start(&self, r: i32) -> usize {
+        let start = match self.shape {
+            MapShape::Rectangle { width, .. } | MapShape::Rhombus { width, .. } => r * width,
+            MapShape::Triangle { size } => r * size - r * (r - 1) / 2,
+            MapShape::Hexagon { radius } => {
+                // Rows grow by one hex down to the middle one, and then shrink.
+                let k = r + radius;
+                if k <= radius {
+                    k * (radius + 1) + k * (k - 1) / 2
+                } else {
+                    let m = k - radius;
+                    let top = radius * (radius + 1) + radius * (radius - 1) / 2;
+                    top + m * (2 * radius + 1) - m * (m - 1) / 2
+                }
+            }
+        };
+        start as usize
+    }
+
+    /// The number of elements in each of the three vectors of an edge layer.
+    fn ext_len(&self) -> usize {
+        match self.shape {
+            MapShape::Rectangle { width, height } => ((width + 1) * (height + 1)) as usize,
+            _ => {
+                let (qs, rs) = self.ext_box();
+                qs.len() * rs.len()
+            }
+        }
+    }
+
+    /// The box, in axial coordinates, where edge layers of grids other than
+    /// rectangles store their hexes owning edges: the range of `q` coordinates
+    /// of the grid, one more each way, and `ext_rows()`.
+    fn ext_box(&self) -> (Range<i32>, Range<i32>) {
+        let qs = match self.shape {
+            MapShape::Rectangle { width, .. } | MapShape::Rhombus { width, .. } => 0..width,
+            MapShape::Hexagon { radius } => -radius..radius + 1,
+            MapShape::Triangle { size } => 0..size,
+        };
+        (qs.start - 1..qs.end + 1, self.ext_rows())
+    }
+
+    /// Returns the index where we store the hex located at the given axial
+    /// coordinates, or `None` if they are not valid.
+    fn hex_index(&self, q: i32, r: i32) -> Option<usize> {
+        let (q, r) = self.mirror(q, r);
+        let (q, r) = self.normalize_mirrored(q, r)?;
+
+        // Rows are stored one after the other, each from west to east.
+        Some(self.row_start(r) + (q - self.row_span(r).start) as usize)
+    }
+
+    /// Returns the index where we store the edge of the hex at (`q`, `r`) in
+    /// the given direction. See `EdgeLayer` for how edges are stored.
+    ///
+    /// Returns `None` unless the edge touches at least one valid hex.
+    fn edge_index(&self, q: i32, r: i32, dir: Direction) -> Option<usize> {
+        let (q, r) = self.mirror(q, r);
+        let dir = self.mirror_dir(dir);
+        let (dq, dr) = dir.offset();
+        if self.normalize_mirrored(q, r).is_none()
+            && self.normalize_mirrored(q + dq, r + dr).is_none()
+        {
+            return None;
+        }
+
+        // East edge is the same as the west edge of the hex to the east, and so
+        // on.
+        let (mut owner_q, owner_r, owned_dir) = match dir {
+            Direction::East | Direction::SouthEast | Direction::SouthWest => {
+                (q + dq, r + dr, dir.opposite())
+            }
+            _ => (q, r, dir),
+        };
+
+        // Like `normalize()`, but the owner may be on the extra row.
+        if self.wraps {
+            let r2 = owner_r / 2;
+            owner_q = (owner_q + r2).rem_euclid(self.row_len()) - r2;
+        }
+
+        let vector = match owned_dir {
+            Direction::West => 0,
+            Direction::NorthWest => 1,
+            _ => 2,
+        };
+
+        Some(vector * self.ext_len() + self.hex_index_ext(owner_q, owner_r))
+    }
+
+    /// Like `hex_index()`, but for the extra row and column of edge layers, in
+    /// mirrored coordinates.
+    ///
+    /// Validity of the coordinates is not checked by design.
+    fn hex_index_ext(&self, q: i32, r: i32) -> usize {
+        match self.shape {
+            MapShape::Rectangle { width, .. } => {
+                let y = r;
+                let r2 = r / 2;
+                let x = q + r2;
+                (y * (width + 1) + x) as usize
+            }
+            _ => {
+                let (qs, rs) = self.ext_box();
+                (r - rs.start) as usize * qs.len() + (q - qs.start) as usize
+            }
+        }
+    }
+
+    /// The number of elements in each of the two vectors of a vertex layer.
+    fn vertex_block_len(&self) -> usize {
+        let (qs, rs) = self.ext_box();
+        match self.shape {
+            MapShape::Rectangle { width, .. } => ((width + 2) * rs.len() as i32) as usize,
+            // The box of `ext_box()`, plus a row to the north.
+            _ => qs.len() * (rs.len() + 1),
+        }
+    }
+
+    /// Returns the index where we store the vertex at corner `corner` of the
+    /// hex at (`q`, `r`). See `VertexLayer` for how vertices are stored.
+    ///
+    /// Returns `None` unless the vertex touches at least one valid hex.
+    fn vertex_index(&self, q: i32, r: i32, corner: u8) -> Option<usize> {
+        let (q, r, corner) = self.mirror_vertex(q, r, corner);
+        let (q, r, corner) = self.normalize_vertex_mirrored(q, r, corner)?;
+
+        let block = if corner == 5 { 0 } else { 1 };
+        let index = match self.shape {
+            MapShape::Rectangle { width, .. } => {
+                // Top corners go from the first row to the extra one at the
+                // bottom, bottom corners from the extra one at the top to the
+                // last row.
+                let y = if corner == 5 { r } else { r + 1 };
+
+                // Rows may need an extra vertex on either side.
+                let x = q + r / 2 + 1;
+
+                (y * (width + 2) + x) as usize
+            }
+            _ => {
+                let (qs, rs) = self.ext_box();
+                (r - rs.start + 1) as usize * qs.len() + (q - qs.start) as usize
+            }
+        };
+
+        Some(block * self.vertex_block_len() + index)
+    }
+}
+
+/// Mirrors `shape` along the diagonal, if `orientation` is flat-top, which
+/// swaps the width and height of rectangles and rhombi. Its own inverse.
+fn mirror_shape(shape: MapShape, orientation: Orientation) -> MapShape {
+    match (orientation, shape) {
+        (Orientation::FlatTop, MapShape::Rectangle { width, height }) => MapShape::Rectangle {
+            width: height,
+            height: width,
+        },
+        (Orientation::FlatTop, MapShape::Rhombus { width, height }) => MapShape::Rhombus {
+            width: height,
+            height: width,
+        },
+        _ => shape,
+    }
+}
+
+/// A layer of data of the grid. Implemented by `HexLayer` and `EdgeLayer`, so
+/// that any of them can be registered with `HexGrid::add_layer()`.
+pub trait GridLayer: Any {
+    /// Returns the layout of the grid this layer was created for.
+    fn layout(&self) -> GridLayout;
+}
+
+/// A value of type `T` for each hex of a grid.
+///
+/// Values are stored in a single vector, row after row, so that a bunch of
+/// these makes a nice struct-of-arrays.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HexLayer<T> {
+    layout: GridLayout,
+    data: Vec<T>,
+}
+
+impl<T: Clone> HexLayer<T> {
+    /// Creates a new layer for grids with the given layout, with every hex set
+    /// to `value`.
+    pub fn new(layout: GridLayout, value: T) -> Self {
+        Self {
+            layout,
+            data: vec![value; layout.hex_len()],
+        }
+    }
+}
+
+impl<T> HexLayer<T> {
+    /// Returns the value of the hex at the given axial coordinates, or `None`
+    /// if they are not valid. Coordinates are normalized first, so this works
+    /// across the seam of grids that wrap around.
+    pub fn get(&self, q: i32, r: i32) -> Option<&T> {
+        let index = self.layout.hex_index(q, r)?;
+        Some(&self.data[index])
+    }
+
+    /// Mutable version of `get()`.
+    pub fn get_mut(&mut self, q: i32, r: i32) -> Option<&mut T> {
+        let index = self.layout.hex_index(q, r)?;
+        Some(&mut self.data[index])
+    }
+
+    /// Sets the value of the hex at the given axial coordinates. Attempts to
+    /// set hexes out of the grid are ignored.
+    pub fn set(&mut self, q: i32, r: i32, value: T) {
+        if let Some(v) = self.get_mut(q, r) {
+            *v = value;
+        }
+    }
+
+    /// Returns all values, in storage order (which is the same order
+    /// `HexGrid::axial_coords()` yields the hexes).
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    /// Mutable version of `as_slice()`.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+}
+
+impl<T: 'static> GridLayer for HexLayer<T> {
+    fn layout(&self) -> GridLayout {
+        self.layout
+    }
+}
+
+/// A value of type `T` for each edge of a grid (that is, for each segment
+/// between two neighboring hexes, or between a hex and the outside of the
+/// grid). This is how walls are stored.
+///
+/// Each hex owns its west, north-west and north-east edges; the other three
+/// are owned by the neighbors in those directions. (Flat-top grids store
+/// everything mirrored, see `Orientation`, so there hexes own the edges in
+/// `GridLayout::owned_directions()`, and rows are columns.) We store the
+/// values of each of those three directions in a block of (width + 1) *
+/// (height + 1) elements, with the extra row and column storing data for the
+/// edges along the east and south of the grid. (For shapes other than
+/// rectangles, each block covers the box around the hexes owning edges
+/// instead, in axial coordinates: simpler, if a bit wasteful.)
+///
+/// Conveniently, we treat the grid as wrapping around horizontally, so the
+/// extra hexes can be interpreted as being either to the east or the west of
+/// the grid, as needed. Different edges of the same "out-of-bounds hex" can
+/// even be used as both west and east of the grid, at the same time (this
+/// happens on odd rows).
+///
+/// (When the grid itself wraps around horizontally, the extra column is not
+/// used at all: the east edge of the easternmost hex of a row is simply the
+/// west edge of the westernmost one.)
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdgeLayer<T> {
+    layout: GridLayout,
+    data: Vec<T>,
+}
+
+impl<T: Clone> EdgeLayer<T> {
+    /// Creates a new layer for grids with the given layout, with every edge set
+    /// to `value`.
+    pub fn new(layout: GridLayout, value: T) -> Self {
+        Self {
+            layout,
+            data: vec![value; 3 * layout.ext_len()],
+        }
+    }
+}
+
+impl<T> EdgeLayer<T> {
+    /// Returns the value of the edge of the hex at (`q`, `r`) in the given
+    /// direction. The same edge can be reached from both hexes sharing it.
+    ///
+    /// Works for every edge touching at least one hex in the grid, including
+    /// the edges along the borders of the grid. Returns `None` for other
+    /// edges.
+    pub fn get(&self, q: i32, r: i32, dir: Direction) -> Option<&T> {
+        let index = self.layout.edge_index(q, r, dir)?;
+        Some(&self.data[index])
+    }
+
+    /// Mutable version of `get()`.
+    pub fn get_mut(&mut self, q: i32, r: i32, dir: Direction) -> Option<&mut T> {
+        let index = self.layout.edge_index(q, r, dir)?;
+        Some(&mut self.data[index])
+    }
+
+    /// Sets the value of the edge of the hex at (`q`, `r`) in the given
+    /// direction. Same rules as in `get()`; attempts to set other edges are
+    /// ignored.
+    pub fn set(&mut self, q: i32, r: i32, dir: Direction, value: T) {
+        if let Some(v) = self.get_mut(q, r, dir) {
+            *v = value;
+        }
+    }
+
+    /// Returns all values, in storage order: the edges in each of
+    /// `GridLayout::owned_directions()`, one direction after the other, each
+    /// including the extra row and column.
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    /// Mutable version of `as_slice()`.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+}
+
+impl<T: 'static> GridLayer for EdgeLayer<T> {
+    fn layout(&self) -> GridLayout {
+        self.layout
+    }
+}
+
+/// A value of type `T` for each vertex of a grid (that is, for each corner
+/// where hexes meet, including those along the borders of the grid). See
+/// `Vertex`.
+///
+/// Each hex owns its top and bottom corners (east and west, on flat-top
+/// grids, mirrored like for `EdgeLayer`), which covers all vertices. We
+/// store the values of each of the two in a block of (width + 2) * (height + 1)
+/// elements: the borders of the grid need an extra row (at the bottom for top
+/// corners, at the top for bottom corners) and, depending on the row, an extra
+/// column at either side. (Like with `EdgeLayer`, shapes other than rectangles
+/// use a box in axial coordinates, with an extra row at both ends.)
+#[derive(Debug, Clone, PartialEq)]
+pub struct VertexLayer<T> {
+    layout: GridLayout,
+    data: Vec<T>,
+}
+
+impl<T: Clone> VertexLayer<T> {
+    /// Creates a new layer for grids with the given layout, with every vertex
+    /// set to `value`.
+    pub fn new(layout: GridLayout, value: T) -> Self {
+        Self {
+            layout,
+            data: vec![value; 2 * layout.vertex_block_len()],
+        }
+    }
+}
+
+impl<T> VertexLayer<T> {
+    /// Returns the value of the vertex at corner `corner` of the hex at (`q`,
+    /// `r`). The same vertex can be reached from all three hexes sharing it.
+    ///
+    /// Works for every vertex touching at least one hex in the grid, including
+    /// the vertices along the borders of the grid. Returns `None` for other
+    /// vertices.
+    pub fn get(&self, q: i32, r: i32, corner: u8) -> Option<&T> {
+        let index = self.layout.vertex_index(q, r, corner)?;
+        Some(&self.data[index])
+    }
+
+    /// Mutable version of `get()`.
+    pub fn get_mut(&mut self, q: i32, r: i32, corner: u8) -> Option<&mut T> {
+        let index = self.layout.vertex_index(q, r, corner)?;
+        Some(&mut self.data[index])
+    }
+
+    /// Sets the value of the vertex at corner `corner` of the hex at (`q`,
+    /// `r`). Same rules as in `get()`; attempts to set other vertices are
+    /// ignored.
+    pub fn set(&mut self, q: i32, r: i32, corner: u8, value: T) {
+        if let Some(v) = self.get_mut(q, r, corner) {
+            *v = value;
+        }
+    }
+
+    /// Returns all values, in storage order: the top corners, then the bottom
+    /// ones (the west ones, then the east ones, on flat-top grids), each
+    /// including the extra rows and columns.
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    /// Mutable version of `as_slice()`.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+}
+
+impl<T: 'static> GridLayer for VertexLayer<T> {
+    fn layout(&self) -> GridLayout {
+        self.layout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_hex_layer() {
+        let mut layer = HexLayer::new(GridLayout::new(4, 3, false), 0u8);
+        layer.set(1, 1, 7);
+        *layer.get_mut(-1, 2).unwrap() += 2;
+        assert_eq!(layer.get(1, 1), Some(&7));
+        assert_eq!(layer.get(-1, 2), Some(&2));
+        assert_eq!(layer.get(0, 0), Some(&0));
+
+        // Out of the grid.
+        layer.set(4, 0, 9);
+        assert_eq!(layer.get(4, 0), None);
+        assert_eq!(layer.get(0, 3), None);
+        assert!(layer.as_slice().iter().all(|&v| v != 9));
+
+        // Storage order.
+        assert_eq!(layer.as_slice().len(), 12);
+        assert_eq!(layer.as_slice()[5], 7);
+        assert_eq!(layer.as_slice()[8], 2);
+    }
+
+    #[test]
+    fn test_hex_layer_wrapping() {
+        let mut layer = HexLayer::new(GridLayout::new(4, 3, true), 'a');
+        layer.set(4, 0, 'b');
+        assert_eq!(layer.get(0, 0), Some(&'b'));
+        assert_eq!(layer.get(-4, 0), Some(&'b'));
+    }
+
+    #[test]
+    fn test_shapes() {
+        let shapes = [
+            MapShape::Rectangle {
+                width: 4,
+                height: 4,
+            },
+            MapShape::Hexagon { radius: 4 },
+            MapShape::Rhombus {
+                width: 5,
+                height: 3,
+            },
+            MapShape::Triangle { size: 5 },
+        ];
+        let orientations = [Orientation::PointyTop, Orientation::FlatTop];
+        for (shape, orientation) in shapes
+            .into_iter()
+            .flat_map(|s| orientations.map(|o| (s, o)))
+        {
+            let layout = GridLayout::with_orientation(shape, orientation);
+            assert_eq!(layout.shape(), shape);
+
+            // Hexes are stored densely, row after row.
+            let hexes: Vec<_> = layout
+                .rows()
+                .flat_map(|row| layout.row_span(row).map(move |x| layout.row_hex(row, x)))
+                .collect();
+            assert_eq!(hexes.len(), layout.hex_len());
+            for (i, &(q, r)) in hexes.iter().enumerate() {
+                assert_eq!(layout.hex_index(q, r), Some(i), "{:?}", shape);
+            }
+
+            // Each edge and vertex touching the grid gets a slot of its own.
+            let mut edges = HashSet::new();
+            let mut edge_indices = HashSet::new();
+            let mut vertices = HashSet::new();
+            let mut vertex_indices = HashSet::new();
+            for &(q, r) in &hexes {
+                for dir in Direction::ALL {
+                    let (dq, dr) = dir.offset();
+                    let edge = if dir as u8 <= 2 {
+                        (q + dq, r + dr, dir.opposite())
+                    } else {
+                        (q, r, dir)
+                    };
+                    let index = layout.edge_index(q, r, dir).unwrap();
+                    assert!(index < 3 * layout.ext_len());
+                    if edges.insert(edge) {
+                        assert!(edge_indices.insert(index), "{:?} {:?}", shape, edge);
+                    }
+                }
+                for corner in 0..6 {
+                    let vertex = layout.normalize_vertex(q, r, corner).unwrap();
+                    let index = layout.vertex_index(q, r, corner).unwrap();
+                    assert!(index < 2 * layout.vertex_block_len());
+                    if vertices.insert(vertex) {
+                        assert!(vertex_indices.insert(index), "{:?} {:?}", shape, vertex);
+                    }
+                }
+            }
+            assert_eq!(edge_indices.len(), edges.len());
+            assert_eq!(vertex_indices.len(), vertices.len());
+        }
+    }
+
+    #[test]
+    fn test_flat_top() {
+        let layout = GridLayout::with_orientation(
+            MapShape::Rectangle {
+                width: 4,
+                height: 3,
+            },
+            Orientation::FlatTop,
+        );
+        assert_eq!((layout.width(), layout.height()), (4, 3));
+
+        // Columns, with every other one shifted half a hex down.
+        assert_eq!(layout.rows(), 0..4);
+        assert_eq!(layout.row_span(3), -1..2);
+        assert_eq!(layout.row_hex(3, -1), (3, -1));
+        assert_eq!(layout.normalize(3, -1), Some((3, -1)));
+        assert_eq!(layout.normalize(3, 2), None);
+        assert_eq!(layout.normalize(4, 0), None);
+
+        // Hexes own their north, north-west and south-west edges, and their
+        // east and west corners.
+        assert_eq!(
+            layout.owned_directions(),
+            [Direction::NorthWest, Direction::West, Direction::SouthWest]
+        );
+        let mut layer = EdgeLayer::new(layout, 0);
+        layer.set(1, 1, Direction::SouthEast, 1);
+        assert_eq!(layer.get(1, 2, Direction::NorthWest), Some(&1));
+        assert_eq!(layout.normalize_vertex(1, 1, 0), Some((1, 1, 0)));
+        assert_eq!(layout.normalize_vertex(1, 1, 1), Some((2, 1, 3)));
+        assert_eq!(layout.normalize_vertex(1, 1, 5), Some((2, 0, 3)));
+    }
+
+    #[test]
+    fn test_edge_layer() {
+        let layout = GridLayout::new(3, 3, false);
+        let mut layer = EdgeLayer::new(layout, false);
+        layer.set(1, 1, Direction::East, true);
+        assert_eq!(layer.get(2, 1, Direction::West), Some(&true));
+        assert_eq!(layer.get(1, 1, Direction::West), Some(&false));
+
+        // Edges along the borders are fine, edges away from the grid are not.
+        layer.set(-1, 2, Direction::SouthWest, true);
+        assert_eq!(layer.get(-2, 3, Direction::NorthEast), Some(&true));
+        assert_eq!(layer.get(10, 10, Direction::East), None);
+
+        assert_eq!(layer.as_slice().len(), 3 * 16);
+        assert_eq!(layer.as_slice().iter().filter(|&&v| v).count(), 2);
+    }
+
+    #[test]
+    fn test_vertex_layer() {
+        let layout = GridLayout::new(3, 3, false);
+        let mut layer = VertexLayer::new(layout, 0);
+        layer.set(1, 1, 0, 1);
+        assert_eq!(layer.get(2, 0, 2), Some(&1));
+        assert_eq!(layer.get(2, 1, 4), Some(&1));
+        assert_eq!(layer.get(1, 1, 1), Some(&0));
+
+        // Every vertex along the borders gets a slot of its own, and vertices
+        // away from the grid get none.
+        let mut count = 0;
+        for r in -1..=4 {
+            for q in -4..=4 {
+                for corner in [2, 5] {
+                    if let Some(v) = layer.get_mut(q, r, corner) {
+                        assert_eq!(*v, if (q, r, corner) == (2, 0, 2) { 1 } else { 0 });
+                        *v += 2;
+                        count += 1;
+                    }
+                }
+            }
+        }
+        let mut vertices = HashSet::new();
+        for r in 0..3 {
+            for x in 0..3 {
+                for corner in 0..6 {
+                    vertices.insert(layout.normalize_vertex(x - r / 2, r, corner).unwrap());
+                }
+            }
+        }
+        assert_eq!(count, vertices.len());
+        assert_eq!(layer.get(10, 10, 5), None);
+        assert_eq!(layer.as_slice().len(), 2 * 5 * 4);
+
+        let mut layer = VertexLayer::new(GridLayout::new(3, 3, true), 'a');
+        layer.set(3, 0, 5, 'b');
+        assert_eq!(layer.get(0, 0, 5), Some(&'b'));
+        assert_eq!(layer.get(-3, 0, 5), Some(&'b'));
+        assert_eq!(layer.get(3, -1, 1), Some(&'b'));
+    }
+}
@@ -1,9 +1,32 @@
+mod automaton;
+mod backend;
+mod chunked;
 mod hex_grid;
+mod hex_map;
+mod history;
+mod layers;
+mod maze;
 
 use hex_grid::*;
+use hex_map::HexMap;
+use layers::*;
+mod pathfinding;
+mod png;
+mod raster;
 mod render;
+mod rooms;
+mod stamp;
+mod svg;
+mod terrain;
+mod tools;
+mod visibility;
 use raylib::prelude::*;
 
+use backend::DrawBackend;
+use chunked::ChunkedHexGrid;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
 const COLORS: [Color; 8] = [
     Color::BLUE,
     Color::LIGHTGREEN,
@@ -18,39 +41,229 @@ const COLORS: [Color; 8] = [
 const SCREEN_WIDTH: i32 = 1280;
 const SCREEN_HEIGHT: i32 = 720;
 
+// How far hexes can see, in the visibility overlay.
+const SIGHT_RADIUS: i32 = 8;
+
+// Where the map is saved to and loaded from.
+const MAP_FILE: &str = "map.hexgrid";
+
+// The environment variable telling where the open world evicts its chunks to.
+// Chunks only live there while the editor runs, so by default they go to a
+// directory of their own in the system's temporary directory.
+const WORLD_DIR_VAR: &str = "HEX_GRID_WORLD_DIR";
+const WORLD_DIR: &str = "hex-grid-world";
+
+// Where the map is exported to as an image.
+const SVG_FILE: &str = "map.svg";
+
+// Where screenshots are saved to, and how big (relative to the screen) they
+// are.
+const SCREENSHOT_FILE: &str = "screenshot.png";
+const SCREENSHOT_SCALE: f32 = 1.0;
+
+// The name of the editor's elevation layer, and the highest elevation.
+const ELEVATION: &str = "elevation";
+const MAX_ELEVATION: u8 = 5;
+
+// The name of the editor's layer of road junctions (or settlements, or...) at
+// the vertices of the grid, and their color.
+const JUNCTIONS: &str = "junctions";
+const JUNCTION_COLOR: Color = Color::GOLD;
+
+// How far around the view (in chunks) the chunks of the open world are loaded
+// back, and evicted.
+const WORLD_LOAD_MARGIN: i32 = 1;
+const WORLD_EVICT_MARGIN: i32 = 3;
+
+// How many strokes can be undone.
+const MAX_UNDO_STEPS: usize = 100;
+
+// How far the camera can zoom out and in, and how much each step of the mouse
+// wheel zooms.
+const MIN_ZOOM: f32 = 0.05;
+const MAX_ZOOM: f32 = 4.0;
+const ZOOM_STEP: f32 = 1.1;
+
+// The largest radius of the brush.
+const MAX_BRUSH_RADIUS: i32 = 5;
+
+// The most movement points units can have in `Mode::Range`, and the color their
+// range is outlined with.
+const MAX_MOVEMENT_POINTS: u32 = 12;
+const RANGE_COLOR: Color = Color::SKYBLUE;
+
+// The rule of the Game of Life played with the current color ("B2/S34"), and
+// how many seconds each generation lasts while it plays.
+const LIFE_BIRTH: [usize; 1] = [2];
+const LIFE_SURVIVAL: [usize; 2] = [3, 4];
+const LIFE_INTERVAL: f32 = 0.2;
+
+// The maps creating a new map cycles through.
+const NEW_MAPS: [NewMap; 7] = [
+    NewMap::WrappingRectangle {
+        width: 19,
+        height: 11,
+    },
+    NewMap::Shaped(
+        MapShape::Rectangle {
+            width: 19,
+            height: 11,
+        },
+        Orientation::PointyTop,
+    ),
+    NewMap::Shaped(
+        MapShape::Rectangle {
+            width: 19,
+            height: 9,
+        },
+        Orientation::FlatTop,
+    ),
+    NewMap::Shaped(MapShape::Hexagon { radius: 6 }, Orientation::PointyTop),
+    NewMap::Shaped(MapShape::Hexagon { radius: 5 }, Orientation::FlatTop),
+    NewMap::Shaped(
+        MapShape::Rhombus {
+            width: 14,
+            height: 11,
+        },
+        Orientation::PointyTop,
+    ),
+    NewMap::Shaped(MapShape::Triangle { size: 13 }, Orientation::PointyTop),
+];
+
+// Where the camera puts the north-west corner of new (or loaded) maps on the
+// screen.
+const MAP_OFFSET: Vector2 = Vector2 { x: 70.0, y: 70.0 };
+
 #[derive(PartialEq, Clone, Copy)]
 enum Mode {
     Hex,
     AddWall,
     RemoveWall,
+    Path,
+    Range,
+    Elevation,
+    Junction,
+    Stamp,
+}
+
+/// How `Mode::Hex` paints.
+#[derive(PartialEq, Clone, Copy)]
+enum Tool {
+    /// Every hex within the brush radius of the cursor, while dragging.
+    Brush,
+
+    /// A straight line of hexes, from where the drag starts to where it ends.
+    Line,
+
+    /// The rows and columns between where the drag starts and ends.
+    Rectangle,
+
+    /// Every hex as far from where the drag starts as where it ends.
+    Range,
+
+    /// The area of the same color as the clicked hex.
+    Fill,
+
+    /// Same as `Fill`, but stopping at walls, too.
+    FillWithinWalls,
+}
+
+/// A kind of map for `new_map()` to create.
+#[derive(Clone, Copy)]
+enum NewMap {
+    /// A pointy-top rectangle wrapping around horizontally (the only maps that
+    /// can wrap around).
+    WrappingRectangle { width: i32, height: i32 },
+
+    /// A map of the given shape, with its hexes turned the given way.
+    Shaped(MapShape, Orientation),
 }
 
 fn main() {
-    let mut hex_grid = HexGrid::new(19, 11);
+    let mut map_count = 0;
+    let mut hex_grid = new_map(NEW_MAPS[0]);
     let mut mode = Mode::Hex;
     let mut color: usize = 0;
+    let mut show_rooms = false;
+    let mut show_visibility = false;
+
+    // The heatmap of distances to the junctions (or to the hex under the
+    // cursor, if there are none): off, on, and on with the distances written
+    // on the hexes.
+    let mut heatmap_labels: Option<bool> = None;
+    let mut history = history::History::new(MAX_UNDO_STEPS);
+
+    // The open world, and whether we are editing it instead of the map.
+    let mut world = ChunkedHexGrid::new(Orientation::PointyTop, COLORS[0], world_dir());
+    let mut in_world = false;
 
-    let renderer = render::HexGridRenderer::new(35.0);
+    // The painting tool, and where its current drag started.
+    let mut tool = Tool::Brush;
+    let mut brush_radius = 0;
+    let mut tool_start: Option<(i32, i32)> = None;
+
+    // The vertex where the current drag started in the wall modes, and the
+    // kind of the walls `Mode::AddWall` adds.
+    let mut wall_start: Option<Vertex> = None;
+    let mut wall_kind = WallKind::Solid;
+
+    // Each maze uses the next algorithm and the next seed, and so does each
+    // terrain (minus the algorithm).
+    let mut maze_count: u128 = 0;
+    let mut terrain_count: u128 = 0;
+    let terrain_settings = terrain::TerrainSettings::default();
+
+    // The hexes picked in `Mode::Path`, and the path found between them.
+    let mut path_start: Option<(i32, i32)> = None;
+    let mut path_result: Option<Result<pathfinding::Path, pathfinding::PathError>> = None;
+
+    // The hex of the unit placed in `Mode::Range` (if any; otherwise, the range
+    // is shown from the hex under the cursor), and its movement points.
+    let mut unit: Option<(i32, i32)> = None;
+    let mut movement_points: u32 = 4;
+
+    // The stamp copied in `Mode::Stamp`, and where the drag copying it started.
+    let mut stamp: Option<stamp::Stamp> = None;
+    let mut stamp_start: Option<(i32, i32)> = None;
+
+    // The Game of Life while it plays, with the color it plays with, and the
+    // time since its last generation.
+    let mut life: Option<(automaton::Automaton<bool>, Color)> = None;
+    let mut life_time = 0.0;
+
+    let mut renderer = render::HexGridRenderer::new(35.0);
+    renderer.set_ghost_columns(1);
 
     let (mut rl, thread) = raylib::init()
         .size(SCREEN_WIDTH, SCREEN_HEIGHT)
         .title("Hex Grid!")
         .build();
 
-    let cam = Camera2D {
-        offset: Vector2::new(70.0, 70.0),
+    let mut cam = Camera2D {
         zoom: 1.0,
         ..Default::default()
     };
-
-    // Paint all hexes the same color.
-    for (q, r) in hex_grid.axial_coords() {
-        hex_grid.set_hex_color(q, r, COLORS[0]);
-    }
+    fit_camera(&mut cam, &mut renderer, &hex_grid);
 
     while !rl.window_should_close() {
         // Handle input
-        let mouse_pos = rl.get_mouse_position() - cam.offset;
+        let screen_mouse_pos = rl.get_mouse_position();
+
+        // Middle-drag pans, and the mouse wheel zooms around the cursor (by
+        // moving the camera so that what's under it stays put).
+        if rl.is_mouse_button_down(MouseButton::MOUSE_BUTTON_MIDDLE) {
+            cam.target -= rl.get_mouse_delta() / cam.zoom;
+        }
+        let wheel = rl.get_mouse_wheel_move();
+        if wheel != 0.0 {
+            cam.target = render::screen_to_world(&cam, screen_mouse_pos);
+            cam.offset = screen_mouse_pos;
+            cam.zoom = (cam.zoom * ZOOM_STEP.powf(wheel)).clamp(MIN_ZOOM, MAX_ZOOM);
+        }
+
+        let mouse_pos = render::screen_to_world(&cam, screen_mouse_pos);
+        let view = render::camera_view(&cam, SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32);
+        renderer.set_view(Some(view));
 
         if rl.is_key_pressed(KeyboardKey::KEY_C) {
             color = (color + 1) % COLORS.len();
@@ -58,59 +271,919 @@ fn main() {
             mode = match mode {
                 Mode::Hex => Mode::AddWall,
                 Mode::AddWall => Mode::RemoveWall,
-                Mode::RemoveWall => Mode::Hex,
+                Mode::RemoveWall => Mode::Path,
+                Mode::Path => Mode::Range,
+                Mode::Range => Mode::Elevation,
+                Mode::Elevation => Mode::Junction,
+                Mode::Junction => Mode::Stamp,
+                Mode::Stamp => Mode::Hex,
+            };
+            path_start = None;
+            path_result = None;
+        } else if rl.is_key_pressed(KeyboardKey::KEY_T) {
+            tool = match tool {
+                Tool::Brush => Tool::Line,
+                Tool::Line => Tool::Rectangle,
+                Tool::Rectangle => Tool::Range,
+                Tool::Range => Tool::Fill,
+                Tool::Fill => Tool::FillWithinWalls,
+                Tool::FillWithinWalls => Tool::Brush,
             };
+        } else if rl.is_key_pressed(KeyboardKey::KEY_K) {
+            let kinds = WallKind::ALL;
+            let i = kinds
+                .iter()
+                .position(|&kind| kind == wall_kind)
+                .unwrap_or(0);
+            wall_kind = kinds[(i + 1) % kinds.len()];
+        } else if rl.is_key_pressed(KeyboardKey::KEY_LEFT_BRACKET) {
+            if mode == Mode::Range {
+                movement_points = movement_points.saturating_sub(1);
+            } else if mode == Mode::Stamp {
+                stamp = stamp.map(|stamp| stamp.rotated(-1));
+            } else {
+                brush_radius = (brush_radius - 1).max(0);
+            }
+        } else if rl.is_key_pressed(KeyboardKey::KEY_RIGHT_BRACKET) {
+            if mode == Mode::Range {
+                movement_points = (movement_points + 1).min(MAX_MOVEMENT_POINTS);
+            } else if mode == Mode::Stamp {
+                stamp = stamp.map(|stamp| stamp.rotated(1));
+            } else {
+                brush_radius = (brush_radius + 1).min(MAX_BRUSH_RADIUS);
+            }
+        } else if rl.is_key_pressed(KeyboardKey::KEY_N) && !in_world {
+            map_count += 1;
+            hex_grid = new_map(NEW_MAPS[map_count % NEW_MAPS.len()]);
+            fit_camera(&mut cam, &mut renderer, &hex_grid);
+            history.clear();
+            path_start = None;
+            path_result = None;
+            life = None;
+        } else if rl.is_key_pressed(KeyboardKey::KEY_Z) && !in_world {
+            // Undoing while Life plays pauses it, and undoes it all.
+            if life.take().is_some() {
+                history.end_step();
+            }
+            history.undo(&mut hex_grid);
+        } else if rl.is_key_pressed(KeyboardKey::KEY_Y) && !in_world {
+            if life.take().is_some() {
+                history.end_step();
+            }
+            history.redo(&mut hex_grid);
+        } else if rl.is_key_pressed(KeyboardKey::KEY_G) && !in_world {
+            let algorithms = maze::MazeAlgorithm::ALL;
+            let algorithm = algorithms[maze_count as usize % algorithms.len()];
+            let mut rng = pcg64s::Rand::new(maze_count);
+            maze::generate_maze(&mut hex_grid, algorithm, COLORS[color], &mut rng);
+            println!("Generated maze using {:?}, seed {}", algorithm, maze_count);
+            maze_count += 1;
+            history.clear();
+            path_start = None;
+            path_result = None;
+            life = None;
+        } else if rl.is_key_pressed(KeyboardKey::KEY_B) && !in_world {
+            let mut rng = pcg64s::Rand::new(terrain_count);
+            terrain::generate_terrain(&mut hex_grid, &terrain_settings, &mut rng);
+            println!("Generated terrain, seed {}", terrain_count);
+            terrain_count += 1;
+            history.clear();
+            path_start = None;
+            path_result = None;
+            life = None;
+        } else if rl.is_key_pressed(KeyboardKey::KEY_R) {
+            show_rooms = !show_rooms;
+        } else if rl.is_key_pressed(KeyboardKey::KEY_H) {
+            heatmap_labels = match heatmap_labels {
+                None => Some(false),
+                Some(false) => Some(true),
+                Some(true) => None,
+            };
+        } else if rl.is_key_pressed(KeyboardKey::KEY_V) {
+            show_visibility = !show_visibility;
+        } else if rl.is_key_pressed(KeyboardKey::KEY_X) {
+            stamp = stamp.map(|stamp| stamp.mirrored());
+        } else if rl.is_key_pressed(KeyboardKey::KEY_SPACE) && !in_world {
+            // A whole session of play, from here to the pause, is a single
+            // step (ending whatever was painted before it).
+            history.end_step();
+            if life.take().is_none() {
+                tool_start = None;
+                wall_start = None;
+                stamp_start = None;
+                life = Some((start_life(&hex_grid, COLORS[color]), COLORS[color]));
+                life_time = 0.0;
+            }
+        } else if rl.is_key_pressed(KeyboardKey::KEY_PERIOD) && !in_world {
+            // Stepping pauses, with the generation it plays as the last one
+            // of the session; from a pause, it is a step of its own.
+            let (mut automaton, life_color) = life
+                .take()
+                .unwrap_or_else(|| (start_life(&hex_grid, COLORS[color]), COLORS[color]));
+            tool_start = None;
+            step_life(&mut automaton, &mut hex_grid, &mut history, life_color);
+            history.end_step();
+        } else if rl.is_key_pressed(KeyboardKey::KEY_S) && !in_world {
+            match hex_grid.save_to_file(MAP_FILE) {
+                Ok(()) => println!("Map saved to {}", MAP_FILE),
+                Err(err) => eprintln!("Error saving map to {}: {}", MAP_FILE, err),
+            }
+        } else if rl.is_key_pressed(KeyboardKey::KEY_E) && !in_world {
+            match renderer.save_svg_to_file(&hex_grid, true, SVG_FILE) {
+                Ok(()) => println!("Map exported to {}", SVG_FILE),
+                Err(err) => eprintln!("Error exporting map to {}: {}", SVG_FILE, err),
+            }
+        } else if rl.is_key_pressed(KeyboardKey::KEY_P) && !in_world {
+            match renderer.save_png_to_file(&hex_grid, SCREENSHOT_SCALE, SCREENSHOT_FILE) {
+                Ok(()) => println!("Screenshot saved to {}", SCREENSHOT_FILE),
+                Err(err) => eprintln!("Error saving screenshot to {}: {}", SCREENSHOT_FILE, err),
+            }
+        } else if rl.is_key_pressed(KeyboardKey::KEY_O) {
+            in_world = !in_world;
+            tool_start = None;
+            wall_start = None;
+            stamp_start = None;
+            if in_world {
+                renderer.set_orientation(world.orientation());
+                cam.target = Vector2::zero();
+                cam.offset = Vector2::new(SCREEN_WIDTH as f32 / 2.0, SCREEN_HEIGHT as f32 / 2.0);
+                cam.zoom = 1.0;
+            } else {
+                fit_camera(&mut cam, &mut renderer, &hex_grid);
+            }
+        } else if rl.is_key_pressed(KeyboardKey::KEY_L) && !in_world {
+            match HexGrid::load_from_file(MAP_FILE) {
+                Ok(loaded) => {
+                    // Elevation and junctions are not saved, so they start
+                    // over.
+                    hex_grid = loaded;
+                    add_editor_layers(&mut hex_grid);
+                    fit_camera(&mut cam, &mut renderer, &hex_grid);
+                    history.clear();
+                    life = None;
+                    println!("Map loaded from {}", MAP_FILE);
+                }
+                Err(err) => eprintln!("Error loading map from {}: {}", MAP_FILE, err),
+            }
         }
 
-        if rl.is_mouse_button_down(MouseButton::MOUSE_BUTTON_LEFT) {
-            match mode {
-                Mode::Hex => {
+        if in_world {
+            update_world(&mut world, &renderer, view);
+
+            // Same as on the map, minus undo. The other modes need the map's
+            // extra layers, so they do nothing here.
+            let left = MouseButton::MOUSE_BUTTON_LEFT;
+            if mode == Mode::Hex {
+                let hex = renderer.hex_coords_at_pos(mouse_pos);
+                if rl.is_mouse_button_pressed(left) {
+                    tool_start = Some(hex);
+                }
+                if let (true, Some(start)) = (tool_paints(&rl, tool), tool_start) {
+                    // The tools only cover hexes already in the world, so the
+                    // hex under the cursor is painted first: that allocates its
+                    // chunk, which is how the world grows.
+                    world.set_hex_color(hex.0, hex.1, COLORS[color]);
+                    for (q, r) in tool_hexes(&world, tool, brush_radius, start, hex) {
+                        world.set_hex_color(q, r, COLORS[color]);
+                    }
+                }
+                if !rl.is_mouse_button_down(left) {
+                    tool_start = None;
+                }
+            } else if mode == Mode::AddWall || mode == Mode::RemoveWall {
+                if rl.is_mouse_button_pressed(left) {
+                    wall_start = Some(renderer.vertex_at_pos(mouse_pos));
+                } else if rl.is_mouse_button_released(left) {
+                    if let Some(start) = wall_start.take() {
+                        let color = (mode == Mode::AddWall).then_some(COLORS[color]);
+                        for (q, r, dir) in wall_tool_walls(&world, &renderer, start, mouse_pos) {
+                            world.set_wall(q, r, dir, color);
+                            world.set_wall_kind(q, r, dir, wall_kind_towards(wall_kind, dir));
+                        }
+                    }
+                }
+            } else if mode == Mode::Stamp {
+                let hex = renderer.hex_coords_at_pos(mouse_pos);
+                if rl.is_mouse_button_pressed(left) {
+                    stamp_start = Some(hex);
+                } else if rl.is_mouse_button_released(left) {
+                    if let Some(start) = stamp_start.take() {
+                        let hexes = tools::hex_rectangle(&world, start, hex);
+                        stamp = Some(stamp::Stamp::copy(&world, &hexes, start));
+                    }
+                } else if let (true, Some(stamp)) = (
+                    rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_RIGHT),
+                    &stamp,
+                ) {
+                    stamp.paste(&mut world, hex);
+                }
+            }
+
+            let mut d = rl.begin_drawing(&thread);
+            d.clear_background(Color::WHITE);
+            {
+                let mut mode_2d = d.begin_mode2D(cam);
+                let mut d2 = backend::RaylibBackend(&mut mode_2d);
+                renderer.draw(&mut d2, &world);
+                if mode == Mode::AddWall || mode == Mode::RemoveWall {
+                    let (q, r, dir) = renderer.wall_at_pos(mouse_pos);
+                    renderer.highlight_wall(&mut d2, q, r, dir);
+                } else if mode == Mode::Stamp {
+                    let hex = renderer.hex_coords_at_pos(mouse_pos);
+                    draw_stamp(&mut d2, &renderer, &world, stamp.as_ref(), stamp_start, hex);
+                } else {
                     let (q, r) = renderer.hex_coords_at_pos(mouse_pos);
-                    hex_grid.set_hex_color(q, r, COLORS[color]);
+                    renderer.highlight_hex(&mut d2, q, r);
+                }
+            }
+
+            draw_hud(
+                &mut d,
+                mode,
+                tool,
+                brush_radius,
+                movement_points,
+                wall_kind,
+                color,
+            );
+            let status = format!(
+                "(O)pen world: {} chunks in memory, {} on disk",
+                world.loaded_chunks().count(),
+                world.evicted_chunks().count()
+            );
+            d.draw_text(status.as_str(), 5, 5, 20, Color::BLACK);
+            continue;
+        }
+
+        if let Some((automaton, life_color)) = &mut life {
+            life_time += rl.get_frame_time();
+            if life_time >= LIFE_INTERVAL {
+                life_time -= LIFE_INTERVAL;
+                step_life(automaton, &mut hex_grid, &mut history, *life_color);
+            }
+        }
+
+        if mode == Mode::Path {
+            // First click picks the start, second click the goal.
+            if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+                let (q, r) = renderer.hex_coords_at_pos(mouse_pos);
+                match path_start {
+                    Some(start) if path_result.is_none() => {
+                        path_result =
+                            Some(pathfinding::find_path(&hex_grid, start, (q, r), |q, r| {
+                                movement_cost(&hex_grid, q, r)
+                            }));
+                    }
+                    _ => {
+                        path_start = Some((q, r));
+                        path_result = None;
+                    }
                 }
-                _ => {
+            }
+        } else if mode == Mode::Range {
+            // Left click places the unit, right click takes it away.
+            if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+                let (q, r) = renderer.hex_coords_at_pos(mouse_pos);
+                unit = hex_grid.normalize(q, r);
+            } else if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_RIGHT) {
+                unit = None;
+            }
+        } else if mode == Mode::Elevation {
+            // Left click raises the hex, right click lowers it.
+            let (q, r) = renderer.hex_coords_at_pos(mouse_pos);
+            let elevation = hex_grid
+                .layer_mut::<HexLayer<u8>>(ELEVATION)
+                .and_then(|layer| layer.get_mut(q, r));
+            if let Some(elevation) = elevation {
+                if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+                    *elevation = (*elevation + 1).min(MAX_ELEVATION);
+                } else if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_RIGHT) {
+                    *elevation = elevation.saturating_sub(1);
+                }
+            }
+        } else if mode == Mode::Junction {
+            // Left click adds or removes the junction at a vertex, right click
+            // removes them all.
+            if let Some(junctions) = hex_grid.layer_mut::<VertexLayer<bool>>(JUNCTIONS) {
+                if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+                    let (q, r, corner) = renderer.vertex_at_pos(mouse_pos);
+                    let is_junction = junctions.get(q, r, corner) == Some(&true);
+                    junctions.set(q, r, corner, !is_junction);
+                } else if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_RIGHT) {
+                    junctions.as_mut_slice().fill(false);
+                }
+            }
+        } else if life.is_some() {
+            // Life paints the map while it plays, so the tools below wait for
+            // it to pause (they would end its step, and it wouldn't see what
+            // they paint anyway).
+        } else if mode == Mode::Stamp {
+            // Dragging copies the hexes between where the drag starts and
+            // ends (with the start as the origin of the stamp), and right
+            // click pastes them with the origin under the cursor.
+            let left = MouseButton::MOUSE_BUTTON_LEFT;
+            let hex = renderer.hex_coords_at_pos(mouse_pos);
+            if rl.is_mouse_button_pressed(left) {
+                stamp_start = Some(hex);
+            } else if rl.is_mouse_button_released(left) {
+                if let Some(start) = stamp_start.take() {
+                    let hexes = tools::hex_rectangle(&hex_grid, start, hex);
+                    stamp = Some(stamp::Stamp::copy(&hex_grid, &hexes, start));
+                }
+            } else if let (true, Some(stamp)) = (
+                rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_RIGHT),
+                &stamp,
+            ) {
+                paste_stamp(&mut hex_grid, &mut history, stamp, hex);
+            }
+        } else if mode == Mode::Hex {
+            let left = MouseButton::MOUSE_BUTTON_LEFT;
+            let hex = renderer.hex_coords_at_pos(mouse_pos);
+            if rl.is_mouse_button_pressed(left) {
+                tool_start = Some(hex);
+            }
+
+            if let (true, Some(start)) = (tool_paints(&rl, tool), tool_start) {
+                for (q, r) in tool_hexes(&hex_grid, tool, brush_radius, start, hex) {
+                    history.set_hex_color(&mut hex_grid, q, r, COLORS[color]);
+                }
+            }
+
+            if !rl.is_mouse_button_down(left) {
+                // Everything painted while the button was down is a single
+                // step.
+                tool_start = None;
+                history.end_step();
+            }
+        } else {
+            // Dragging from corner to corner sets the walls between them, and
+            // just clicking sets the wall under the cursor.
+            let left = MouseButton::MOUSE_BUTTON_LEFT;
+            if rl.is_mouse_button_pressed(left) {
+                wall_start = Some(renderer.vertex_at_pos(mouse_pos));
+            } else if rl.is_mouse_button_released(left) {
+                if let Some(start) = wall_start.take() {
                     let color = if mode == Mode::AddWall {
                         Some(COLORS[color])
                     } else {
                         None
                     };
 
-                    let (q, r, v1, _) = renderer.wall_at_pos(mouse_pos);
-
-                    match v1 {
-                        0 => hex_grid.set_e_wall(q, r, color),
-                        1 => hex_grid.set_se_wall(q, r, color),
-                        2 => hex_grid.set_sw_wall(q, r, color),
-                        3 => hex_grid.set_w_wall(q, r, color),
-                        4 => hex_grid.set_nw_wall(q, r, color),
-                        5 => hex_grid.set_ne_wall(q, r, color),
-                        _ => {}
+                    for (q, r, dir) in wall_tool_walls(&hex_grid, &renderer, start, mouse_pos) {
+                        history.set_wall(&mut hex_grid, q, r, dir, color);
+                        let kind = wall_kind_towards(wall_kind, dir);
+                        history.set_wall_kind(&mut hex_grid, q, r, dir, kind);
                     }
+                    history.end_step();
                 }
             }
         }
 
+        // What the current tool would paint: the brush under the cursor, or
+        // the shape being dragged. (Fills could be huge, so they don't show.)
+        let preview = match (tool, tool_start) {
+            _ if mode != Mode::Hex => Vec::new(),
+            (Tool::Brush, _) => {
+                let hex = renderer.hex_coords_at_pos(mouse_pos);
+                tools::hex_range(&hex_grid, hex, brush_radius)
+            }
+            (Tool::Line | Tool::Rectangle | Tool::Range, Some(start)) => {
+                let hex = renderer.hex_coords_at_pos(mouse_pos);
+                tool_hexes(&hex_grid, tool, brush_radius, start, hex)
+            }
+            _ => Vec::new(),
+        };
+
+        let range = if mode == Mode::Range {
+            let start = unit.unwrap_or_else(|| renderer.hex_coords_at_pos(mouse_pos));
+            pathfinding::movement_range(
+                &hex_grid,
+                start,
+                movement_points,
+                |q, r| movement_cost(&hex_grid, q, r),
+                |q, r| in_zone_of_control(&hex_grid, q, r),
+            )
+            .ok()
+        } else {
+            None
+        };
+
+        let heatmap = heatmap_labels.map(|labels| {
+            let sources = heatmap_sources(&hex_grid, renderer.hex_coords_at_pos(mouse_pos));
+            let field = pathfinding::distance_field(&hex_grid, &sources, |q, r| {
+                movement_cost(&hex_grid, q, r)
+            });
+            (field, labels)
+        });
+
+        let rooms = if show_rooms {
+            Some(rooms::find_rooms(&hex_grid))
+        } else {
+            None
+        };
+
         // Draw!
         let mut d = rl.begin_drawing(&thread);
         d.clear_background(Color::WHITE);
 
         {
-            let mut d2 = d.begin_mode2D(cam);
-            renderer.draw(&mut d2, &hex_grid);
-            if mode == Mode::Hex {
+            let mut mode_2d = d.begin_mode2D(cam);
+            let mut d2 = backend::RaylibBackend(&mut mode_2d);
+            match &heatmap {
+                Some((field, labels)) => renderer.draw_heatmap(&mut d2, &hex_grid, field, *labels),
+                None => renderer.draw(&mut d2, &hex_grid),
+            }
+            if let Some(rooms) = &rooms {
+                for (i, room) in rooms.rooms().iter().enumerate() {
+                    for (q, r) in &room.hexes {
+                        renderer.tint_hex(&mut d2, *q, *r, room_color(i));
+                    }
+                }
+            }
+            if show_visibility {
+                let (q, r) = renderer.hex_coords_at_pos(mouse_pos);
+                let visible: HashSet<_> =
+                    visibility::visible_hexes(&hex_grid, (q, r), SIGHT_RADIUS)
+                        .into_iter()
+                        .collect();
+                for (q, r) in renderer.visible_hexes(&hex_grid) {
+                    if !visible.contains(&(q, r)) {
+                        renderer.tint_hex(&mut d2, q, r, Color::BLACK.fade(0.6));
+                    }
+                }
+            }
+
+            if mode == Mode::Elevation {
+                if let Some(elevation) = hex_grid.layer::<HexLayer<u8>>(ELEVATION) {
+                    for (q, r) in renderer.visible_hexes(&hex_grid) {
+                        let e = *elevation.get(q, r).unwrap() as f32;
+                        let tint = Color::WHITE.fade(0.8 * e / MAX_ELEVATION as f32);
+                        renderer.tint_hex(&mut d2, q, r, tint);
+                    }
+                }
+            }
+
+            if let Some(junctions) = hex_grid.layer::<VertexLayer<bool>>(JUNCTIONS) {
+                // Neighboring hexes share vertices, so we'd see most of them
+                // more than once.
+                let mut drawn = HashSet::new();
+                for (q, r) in renderer.visible_hexes(&hex_grid) {
+                    for (q, r, corner) in hex_grid.hex_vertices(q, r).unwrap() {
+                        if junctions.get(q, r, corner) == Some(&true)
+                            && drawn.insert((q, r, corner))
+                        {
+                            renderer.draw_vertex(&mut d2, q, r, corner, JUNCTION_COLOR);
+                        }
+                    }
+                }
+            }
+
+            for (q, r) in &preview {
+                renderer.tint_hex(&mut d2, *q, *r, COLORS[color].fade(0.5));
+            }
+
+            // The more points left, the stronger the outline. With a unit
+            // placed, show the way to the hex under the cursor, too.
+            if let Some(range) = &range {
+                for reachable in range.hexes() {
+                    let (q, r) = reachable.hex;
+                    let strength = (reachable.remaining + 1) as f32 / (movement_points + 1) as f32;
+                    renderer.outline_hex(&mut d2, q, r, RANGE_COLOR.fade(strength));
+                }
+                let (q, r) = renderer.hex_coords_at_pos(mouse_pos);
+                let path = hex_grid
+                    .normalize(q, r)
+                    .and_then(|(q, r)| range.path_to(q, r));
+                if let (Some(_), Some(path)) = (unit, path) {
+                    renderer.draw_path(&mut d2, &hex_grid, &path.hexes);
+                }
+            }
+
+            if mode == Mode::Hex || mode == Mode::Range || mode == Mode::Elevation {
+                let (q, r) = renderer.hex_coords_at_pos(mouse_pos);
+                renderer.highlight_hex(&mut d2, q, r);
+            } else if mode == Mode::Path {
+                match (&path_result, path_start) {
+                    (Some(Ok(path)), _) => renderer.draw_path(&mut d2, &hex_grid, &path.hexes),
+                    (_, Some((q, r))) => renderer.highlight_hex(&mut d2, q, r),
+                    _ => {}
+                }
                 let (q, r) = renderer.hex_coords_at_pos(mouse_pos);
                 renderer.highlight_hex(&mut d2, q, r);
+            } else if mode == Mode::Junction {
+                // Show which hexes meet at the vertex.
+                let (q, r, corner) = renderer.vertex_at_pos(mouse_pos);
+                for (q, r) in hex_grid.vertex_hexes(q, r, corner) {
+                    renderer.tint_hex(&mut d2, q, r, JUNCTION_COLOR.fade(0.3));
+                }
+                renderer.highlight_vertex(&mut d2, q, r, corner);
+            } else if mode == Mode::Stamp {
+                let hex = renderer.hex_coords_at_pos(mouse_pos);
+                draw_stamp(
+                    &mut d2,
+                    &renderer,
+                    &hex_grid,
+                    stamp.as_ref(),
+                    stamp_start,
+                    hex,
+                );
+            } else if let Some(start) = wall_start {
+                for (q, r, dir) in wall_tool_walls(&hex_grid, &renderer, start, mouse_pos) {
+                    renderer.highlight_wall(&mut d2, q, r, dir);
+                }
+                renderer.highlight_vertex(&mut d2, start.0, start.1, start.2);
+                let (q, r, corner) = renderer.vertex_at_pos(mouse_pos);
+                renderer.highlight_vertex(&mut d2, q, r, corner);
             } else {
-                let (q, r, v1, v2) = renderer.wall_at_pos(mouse_pos);
-                renderer.highlight_wall(&mut d2, q, r, v1, v2);
+                // Drags can start at either end of the wall under the cursor.
+                let (q, r, dir) = renderer.wall_at_pos(mouse_pos);
+                renderer.highlight_wall(&mut d2, q, r, dir);
+                if let Some((a, b)) = hex_grid.wall_vertices(q, r, dir) {
+                    renderer.highlight_vertex(&mut d2, a.0, a.1, a.2);
+                    renderer.highlight_vertex(&mut d2, b.0, b.1, b.2);
+                }
+            }
+        }
+
+        draw_hud(
+            &mut d,
+            mode,
+            tool,
+            brush_radius,
+            movement_points,
+            wall_kind,
+            color,
+        );
+
+        if let Some(rooms) = &rooms {
+            let (q, r) = renderer.hex_coords_at_pos(mouse_pos);
+            if let (Some(i), Some(room)) = (rooms.room_index_at(q, r), rooms.room_at(q, r)) {
+                let room_string = format!(
+                    "Room {} of {}: {} hexes, {} walls",
+                    i + 1,
+                    rooms.rooms().len(),
+                    room.area(),
+                    room.boundary_walls.len()
+                );
+                d.draw_text(
+                    room_string.as_str(),
+                    5,
+                    SCREEN_HEIGHT - 50,
+                    20,
+                    Color::BLACK,
+                );
+            }
+        }
+
+        if mode == Mode::Junction {
+            if let Some(junctions) = hex_grid.layer::<VertexLayer<bool>>(JUNCTIONS) {
+                let count = junctions.as_slice().iter().filter(|&&j| j).count();
+                let text = format!("{} junctions (right click to clear)", count);
+                d.draw_text(text.as_str(), 5, 5, 20, Color::BLACK);
+            }
+        }
+
+        if let Some(range) = &range {
+            // The range only knows canonical coordinates, and the cursor may
+            // be over a ghost column.
+            let (q, r) = renderer.hex_coords_at_pos(mouse_pos);
+            let text = match hex_grid.normalize(q, r).and_then(|(q, r)| range.get(q, r)) {
+                Some(reachable) => format!("{} movement points left", reachable.remaining),
+                None => "Out of reach".to_string(),
+            };
+            d.draw_text(text.as_str(), 5, 5, 20, Color::BLACK);
+        }
+
+        if let Some(Err(err)) = &path_result {
+            d.draw_text(format!("No path: {}", err).as_str(), 5, 5, 20, Color::RED);
+        }
+    }
+}
+
+/// Returns the directory the open world evicts its chunks to: the one in
+/// `WORLD_DIR_VAR` if set, or else `WORLD_DIR` in the temporary directory (one
+/// per editor, so that two of them don't trip over each other's chunks).
+fn world_dir() -> PathBuf {
+    match std::env::var_os(WORLD_DIR_VAR) {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::temp_dir().join(format!("{}-{}", WORLD_DIR, std::process::id())),
+    }
+}
+
+/// Loads the evicted chunks of `world` near `view` back, and evicts the chunks
+/// far from it, so that only the part of the world around the view is kept in
+/// memory.
+fn update_world(world: &mut ChunkedHexGrid, renderer: &render::HexGridRenderer, view: Rectangle) {
+    // The chunks under the corners of the view bound those in between.
+    let corners = [
+        (view.x, view.y),
+        (view.x + view.width, view.y),
+        (view.x, view.y + view.height),
+        (view.x + view.width, view.y + view.height),
+    ]
+    .map(|(x, y)| {
+        let (q, r) = renderer.hex_coords_at_pos(Vector2::new(x, y));
+        ChunkedHexGrid::chunk_of(q, r)
+    });
+    let (q0, q1) = (
+        corners.iter().map(|c| c.0).min().unwrap(),
+        corners.iter().map(|c| c.0).max().unwrap(),
+    );
+    let (r0, r1) = (
+        corners.iter().map(|c| c.1).min().unwrap(),
+        corners.iter().map(|c| c.1).max().unwrap(),
+    );
+    let near = |(cq, cr): (i32, i32), margin: i32| {
+        (q0 - margin..=q1 + margin).contains(&cq) && (r0 - margin..=r1 + margin).contains(&cr)
+    };
+
+    let to_load: Vec<_> = world
+        .evicted_chunks()
+        .filter(|&chunk| near(chunk, WORLD_LOAD_MARGIN))
+        .collect();
+    for chunk in to_load {
+        if let Err(err) = world.load_chunk(chunk) {
+            eprintln!("Error loading chunk {:?}: {}", chunk, err);
+        }
+    }
+
+    let to_evict: Vec<_> = world
+        .loaded_chunks()
+        .filter(|&chunk| !near(chunk, WORLD_EVICT_MARGIN))
+        .collect();
+    for chunk in to_evict {
+        if let Err(err) = world.evict_chunk(chunk) {
+            eprintln!("Error evicting chunk {:?}: {}", chunk, err);
+        }
+    }
+}
+
+/// Creates a new, blank map for the editor, of the given kind.
+fn new_map(kind: NewMap) -> HexGrid {
+    let mut hex_grid = match kind {
+        NewMap::WrappingRectangle { width, height } => HexGrid::new_wrapping(width, height),
+        NewMap::Shaped(MapShape::Rectangle { width, height }, Orientation::PointyTop) => {
+            HexGrid::new(width, height)
+        }
+        NewMap::Shaped(shape, Orientation::PointyTop) => HexGrid::with_shape(shape),
+        NewMap::Shaped(shape, orientation) => HexGrid::with_orientation(shape, orientation),
+    };
+
+    // Paint all hexes the same color.
+    for (q, r) in hex_grid.axial_coords() {
+        hex_grid.set_hex_color(q, r, COLORS[0]);
+    }
+
+    add_editor_layers(&mut hex_grid);
+    hex_grid
+}
+
+/// Turns the hexes of `renderer` the same way as those of `hex_grid`, and
+/// moves `cam` back to 1:1 zoom, with the north-west corner of `hex_grid` at
+/// `MAP_OFFSET` on the screen. Only for the map: the open world has its own
+/// orientation and camera.
+fn fit_camera(cam: &mut Camera2D, renderer: &mut render::HexGridRenderer, hex_grid: &HexGrid) {
+    renderer.set_orientation(hex_grid.orientation());
+    let (x, y) = hex_grid
+        .axial_coords()
+        .map(|(q, r)| renderer.hex_center(q, r))
+        .fold((f32::MAX, f32::MAX), |(x, y), p| (x.min(p.x), y.min(p.y)));
+    cam.target = Vector2::new(x, y);
+    cam.offset = MAP_OFFSET;
+    cam.zoom = 1.0;
+}
+
+/// Adds a flat elevation layer and an empty junctions layer to `hex_grid`.
+/// These are just there to show off extra layers: they don't affect anything
+/// else.
+fn add_editor_layers(hex_grid: &mut HexGrid) {
+    hex_grid.add_layer(ELEVATION, HexLayer::new(hex_grid.layout(), 0u8));
+    hex_grid.add_layer(JUNCTIONS, VertexLayer::new(hex_grid.layout(), false));
+}
+
+/// A translucent color to tint the `i`-th room with. Stepping the hue by the
+/// golden angle keeps neighboring indices well apart.
+fn room_color(i: usize) -> Color {
+    let hue = (i as f32 * 137.5) % 360.0;
+    Color::color_from_hsv(hue, 0.8, 1.0).fade(0.6)
+}
+
+/// The cost of entering the hex at the given axial coordinates, based on its
+/// color. Just something to make paths more interesting in the editor.
+fn movement_cost(hex_grid: &HexGrid, q: i32, r: i32) -> Option<u32> {
+    let color = hex_grid.hex_color(q, r)?;
+    if color == Color::GRAY {
+        Some(3)
+    } else if color == Color::DARKGREEN || color == Color::BROWN {
+        Some(2)
+    } else {
+        Some(1)
+    }
+}
+
+/// Checks if `tool` paints this frame: the brush paints while dragging, the
+/// fills on click, and the others when the drag ends.
+fn tool_paints(rl: &RaylibHandle, tool: Tool) -> bool {
+    let left = MouseButton::MOUSE_BUTTON_LEFT;
+    match tool {
+        Tool::Brush => rl.is_mouse_button_down(left),
+        Tool::Fill | Tool::FillWithinWalls => rl.is_mouse_button_pressed(left),
+        Tool::Line | Tool::Rectangle | Tool::Range => rl.is_mouse_button_released(left),
+    }
+}
+
+/// Checks if the hex at the given axial coordinates is in a zone of control,
+/// for `Mode::Range`: the junctions of the editor are forts, or some such, and
+/// control the hexes around them.
+fn in_zone_of_control(hex_grid: &HexGrid, q: i32, r: i32) -> bool {
+    let (Some(junctions), Some(vertices)) = (
+        hex_grid.layer::<VertexLayer<bool>>(JUNCTIONS),
+        hex_grid.hex_vertices(q, r),
+    ) else {
+        return false;
+    };
+    vertices
+        .iter()
+        .any(|&(q, r, corner)| junctions.get(q, r, corner) == Some(&true))
+}
+
+/// The hexes the heatmap measures distances to: those touching junctions, or
+/// `cursor_hex` if there are none.
+fn heatmap_sources(hex_grid: &HexGrid, cursor_hex: (i32, i32)) -> Vec<(i32, i32)> {
+    let Some(junctions) = hex_grid.layer::<VertexLayer<bool>>(JUNCTIONS) else {
+        return vec![cursor_hex];
+    };
+    let sources: Vec<_> = hex_grid
+        .axial_coords()
+        .filter(|&(q, r)| {
+            hex_grid
+                .hex_vertices(q, r)
+                .unwrap()
+                .iter()
+                .any(|&(q, r, corner)| junctions.get(q, r, corner) == Some(&true))
+        })
+        .collect();
+    if sources.is_empty() {
+        vec![cursor_hex]
+    } else {
+        sources
+    }
+}
+
+/// Starts a Game of Life on the map, where hexes of `color` are alive and
+/// walls cut them off from each other.
+fn start_life(hex_grid: &HexGrid, color: Color) -> automaton::Automaton<bool> {
+    let mut cells = HexLayer::new(hex_grid.layout(), false);
+    for ((q, r), cell) in hex_grid.axial_coords().zip(cells.as_mut_slice()) {
+        *cell = hex_grid.hex_color(q, r) == Some(color);
+    }
+
+    let mut automaton = automaton::Automaton::new(cells);
+    automaton.set_cut_by_walls(true);
+    automaton
+}
+
+/// Plays a generation of `automaton` (started by `start_life()` with `color`)
+/// and paints it on the map: hexes coming alive with `color`, and those dying
+/// with the first color (or the second, when playing with the first).
+///
+/// The changes go into `history` without ending its step, so that the caller
+/// decides how many generations make a step.
+fn step_life(
+    automaton: &mut automaton::Automaton<bool>,
+    hex_grid: &mut HexGrid,
+    history: &mut history::History,
+    color: Color,
+) {
+    let mut rule = automaton::LifeRule::new(&LIFE_BIRTH, &LIFE_SURVIVAL);
+    automaton.step(hex_grid, &mut rule);
+
+    let dead_color = if color == COLORS[0] {
+        COLORS[1]
+    } else {
+        COLORS[0]
+    };
+    let changes: Vec<_> = hex_grid
+        .axial_coords()
+        .zip(automaton.cells().as_slice())
+        .filter(|&((q, r), &alive)| alive != (hex_grid.hex_color(q, r) == Some(color)))
+        .map(|(hex, &alive)| (hex, alive))
+        .collect();
+    for ((q, r), alive) in changes {
+        let color = if alive { color } else { dead_color };
+        history.set_hex_color(hex_grid, q, r, color);
+    }
+}
+
+/// Pastes `stamp` on the map with its origin at `at`, as a single step of
+/// `history`.
+fn paste_stamp(
+    hex_grid: &mut HexGrid,
+    history: &mut history::History,
+    stamp: &stamp::Stamp,
+    at: (i32, i32),
+) {
+    for ((q, r), color) in stamp.hexes_at(at) {
+        history.set_hex_color(hex_grid, q, r, color);
+    }
+    for (q, r, dir, wall) in stamp.walls_at(at) {
+        history.set_wall(hex_grid, q, r, dir, wall.map(|(color, _)| color));
+        if let Some((_, kind)) = wall {
+            history.set_wall_kind(hex_grid, q, r, dir, kind);
+        }
+    }
+    history.end_step();
+}
+
+/// Draws what `Mode::Stamp` would do with the cursor at `hex`: the hexes being
+/// copied while dragging, or else the stamp (if any) as it would be pasted.
+fn draw_stamp<B: DrawBackend, M: HexMap>(
+    d: &mut B,
+    renderer: &render::HexGridRenderer,
+    hex_grid: &M,
+    stamp: Option<&stamp::Stamp>,
+    stamp_start: Option<(i32, i32)>,
+    hex: (i32, i32),
+) {
+    match (stamp_start, stamp) {
+        (Some(start), _) => {
+            for (q, r) in tools::hex_rectangle(hex_grid, start, hex) {
+                renderer.tint_hex(d, q, r, Color::BLACK.fade(0.2));
+            }
+        }
+        (None, Some(stamp)) => {
+            for ((q, r), color) in stamp.hexes_at(hex) {
+                renderer.tint_hex(d, q, r, color.fade(0.5));
+            }
+            for (q, r, dir, wall) in stamp.walls_at(hex) {
+                if wall.is_some() {
+                    renderer.highlight_wall(d, q, r, dir);
+                }
             }
         }
+        (None, None) => {}
+    }
+    renderer.highlight_hex(d, hex.0, hex.1);
+}
 
-        draw_hud(&mut d, mode, color);
+/// Returns the hexes `tool` paints when dragged from `start` to `end` (or
+/// clicked at `end`, for those that don't drag).
+fn tool_hexes<M: HexMap>(
+    hex_grid: &M,
+    tool: Tool,
+    brush_radius: i32,
+    start: (i32, i32),
+    end: (i32, i32),
+) -> Vec<(i32, i32)> {
+    match tool {
+        Tool::Brush => tools::hex_range(hex_grid, end, brush_radius),
+        Tool::Line => tools::hex_line(hex_grid, start, end),
+        Tool::Rectangle => tools::hex_rectangle(hex_grid, start, end),
+        Tool::Range => {
+            let radius = hex_grid.distance(start, end) as i32;
+            tools::hex_range(hex_grid, start, radius)
+        }
+        Tool::Fill => tools::flood_fill(hex_grid, end, false),
+        Tool::FillWithinWalls => tools::flood_fill(hex_grid, end, true),
     }
 }
 
-fn draw_hud<D: RaylibDraw>(d: &mut D, mode: Mode, color: usize) {
+/// Returns the walls the wall modes set when dragged from the vertex `start`
+/// to `end_pos`: the chain of walls between `start` and the vertex under
+/// `end_pos`, or if they are the same, the wall under `end_pos`.
+fn wall_tool_walls<M: HexMap>(
+    hex_grid: &M,
+    renderer: &render::HexGridRenderer,
+    start: Vertex,
+    end_pos: Vector2,
+) -> Vec<(i32, i32, Direction)> {
+    let (q, r, corner) = renderer.vertex_at_pos(end_pos);
+    let end = hex_grid.normalize_vertex(q, r, corner);
+    if end.is_some() && end == hex_grid.normalize_vertex(start.0, start.1, start.2) {
+        vec![renderer.wall_at_pos(end_pos)]
+    } else {
+        tools::edge_path(hex_grid, start, (q, r, corner)).unwrap_or_default()
+    }
+}
+
+/// The kind of the walls the wall modes add, for a wall picked on the side
+/// `dir` of a hex: one-way passages go out of that hex.
+fn wall_kind_towards(kind: WallKind, dir: Direction) -> WallKind {
+    match kind {
+        WallKind::OneWay(_) => WallKind::OneWay(dir),
+        kind => kind,
+    }
+}
+
+fn draw_hud<D: RaylibDraw>(
+    d: &mut D,
+    mode: Mode,
+    tool: Tool,
+    brush_radius: i32,
+    movement_points: u32,
+    wall_kind: WallKind,
+    color: usize,
+) {
     let w = 30;
     let h = 20;
 
@@ -120,6 +1193,11 @@ fn draw_hud<D: RaylibDraw>(d: &mut D, mode: Mode, color: usize) {
             Mode::Hex => "Hex",
             Mode::AddWall => "Add Wall",
             Mode::RemoveWall => "Remove Wall",
+            Mode::Path => "Path",
+            Mode::Range => "Movement Range",
+            Mode::Elevation => "Elevation",
+            Mode::Junction => "Junctions",
+            Mode::Stamp => "Stamp",
         }
     );
     let font_size = 20;
@@ -142,4 +1220,61 @@ fn draw_hud<D: RaylibDraw>(d: &mut D, mode: Mode, color: usize) {
     let y = SCREEN_HEIGHT - h - 5;
     d.draw_rectangle(x, y, w, h, COLORS[color]);
     d.draw_rectangle_lines(x, y, w, h, Color::BLACK);
+
+    if mode == Mode::Hex {
+        let tool_string = match tool {
+            Tool::Brush => format!("(T)ool: Brush ([ ] radius: {})", brush_radius),
+            Tool::Line => "(T)ool: Line".to_string(),
+            Tool::Rectangle => "(T)ool: Rectangle".to_string(),
+            Tool::Range => "(T)ool: Range".to_string(),
+            Tool::Fill => "(T)ool: Fill".to_string(),
+            Tool::FillWithinWalls => "(T)ool: Fill within walls".to_string(),
+        };
+        d.draw_text(
+            tool_string.as_str(),
+            5,
+            SCREEN_HEIGHT - 3 * font_size - 15,
+            font_size,
+            Color::BLACK,
+        );
+    } else if mode == Mode::Range {
+        d.draw_text(
+            format!("([ ] points: {})", movement_points).as_str(),
+            5,
+            SCREEN_HEIGHT - 3 * font_size - 15,
+            font_size,
+            Color::BLACK,
+        );
+    } else if mode == Mode::Stamp {
+        d.draw_text(
+            "Drag to copy, right click to paste, [ ] rotate, (X) mirror",
+            5,
+            SCREEN_HEIGHT - 3 * font_size - 15,
+            font_size,
+            Color::BLACK,
+        );
+    } else if mode == Mode::AddWall {
+        d.draw_text(
+            format!("(K)ind: {}", wall_kind.name()).as_str(),
+            5,
+            SCREEN_HEIGHT - 3 * font_size - 15,
+            font_size,
+            Color::BLACK,
+        );
+    }
+
+    d.draw_text(
+        "(N)ew / (S)ave / (L)oad / (Z) Undo / (Y) Redo / (O)pen world / (Space) Life / (.) Step",
+        420,
+        SCREEN_HEIGHT - font_size - 5,
+        font_size,
+        Color::BLACK,
+    );
+    d.draw_text(
+        "(G)enerate maze / (B)iomes / (E)xport SVG / (P)NG screenshot / (R)ooms / (V)isibility / (H)eatmap",
+        420,
+        SCREEN_HEIGHT - 2 * font_size - 10,
+        font_size,
+        Color::BLACK,
+    );
 }
@@ -0,0 +1,313 @@
+use crate::hex_grid::*;
+use crate::hex_map::HexMap;
+use pcg64s::Rand;
+use raylib::prelude::*;
+
+use std::collections::{HashMap, HashSet};
+
+/// The algorithms `generate_maze()` knows about. They all generate perfect
+/// mazes, but each has its own look.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MazeAlgorithm {
+    /// Random depth-first search. Long, winding corridors with few dead ends.
+    RecursiveBacktracker,
+
+    /// Randomized Kruskal's algorithm. Lots of short dead ends.
+    Kruskal,
+
+    /// Wilson's algorithm, based on loop-erased random walks. Generates a
+    /// uniformly random maze out of all the possible ones.
+    Wilson,
+}
+
+impl MazeAlgorithm {
+    /// All algorithms.
+    pub const ALL: [MazeAlgorithm; 3] = [
+        MazeAlgorithm::RecursiveBacktracker,
+        MazeAlgorithm::Kruskal,
+        MazeAlgorithm::Wilson,
+    ];
+}
+
+/// Turns the grid into a perfect maze: one in which there is exactly one way
+/// (without going back and forth) between any two hexes.
+///
+/// Every wall touching the grid is first set to a solid wall of `wall_color`
+/// (replacing any walls already there, doors included), and then passages are
+/// carved between hexes using the given algorithm. Hex colors are left alone. On grids that wrap around
+/// horizontally, passages may cross the seam.
+///
+/// All randomness comes from `rng`, so the same seed always gives the same
+/// maze.
+pub fn generate_maze(
+    hex_grid: &mut HexGrid,
+    algorithm: MazeAlgorithm,
+    wall_color: Color,
+    rng: &mut Rand,
+) {
+    for (q, r) in hex_grid.axial_coords() {
+        for dir in Direction::ALL {
+            hex_grid.set_wall(q, r, dir, Some(wall_color));
+            hex_grid.set_wall_kind(q, r, dir, WallKind::Solid);
+        }
+    }
+
+    match algorithm {
+        MazeAlgorithm::RecursiveBacktracker => recursive_backtracker(hex_grid, rng),
+        MazeAlgorithm::Kruskal => kruskal(hex_grid, rng),
+        MazeAlgorithm::Wilson => wilson(hex_grid, rng),
+    }
+}
+
+//
+// Internal helpers
+//
+
+fn recursive_backtracker(hex_grid: &mut HexGrid, rng: &mut Rand) {
+    let hexes: Vec<_> = hex_grid.axial_coords().collect();
+    let start = hexes[random_below(rng, hexes.len())];
+
+    // Iterative, because the recursion could get as deep as the number of
+    // hexes.
+    let mut visited = HashSet::from([start]);
+    let mut stack = vec![start];
+
+    while let Some(&(q, r)) = stack.last() {
+        let unvisited: Vec<_> = hex_grid
+            .neighbors(q, r)
+            .filter(|(_, hex)| !visited.contains(hex))
+            .collect();
+
+        if unvisited.is_empty() {
+            stack.pop();
+            continue;
+        }
+
+        let (dir, next) = unvisited[random_below(rng, unvisited.len())];
+        hex_grid.set_wall(q, r, dir, None);
+        visited.insert(next);
+        stack.push(next);
+    }
+}
+
+fn kruskal(hex_grid: &mut HexGrid, rng: &mut Rand) {
+    let index: HashMap<_, _> = hex_grid
+        .axial_coords()
+        .enumerate()
+        .map(|(i, hex)| (hex, i))
+        .collect();
+
+    // Every wall between two hexes, once. (Each is the east, south-east or
+    // south-west wall of some hex.)
+    let mut walls = Vec::new();
+    for (q, r) in hex_grid.axial_coords() {
+        for dir in [Direction::East, Direction::SouthEast, Direction::SouthWest] {
+            if let Some(next) = hex_grid.neighbor(q, r, dir) {
+                walls.push((q, r, dir, next));
+            }
+        }
+    }
+    shuffle(rng, &mut walls);
+
+    // Knock down every wall between two hexes not yet connected, tracking the
+    // connected sets with a union-find.
+    let mut parent: Vec<_> = (0..index.len()).collect();
+    for (q, r, dir, next) in walls {
+        let a = find_root(&mut parent, index[&(q, r)]);
+        let b = find_root(&mut parent, index[&next]);
+        if a != b {
+            parent[a] = b;
+            hex_grid.set_wall(q, r, dir, None);
+        }
+    }
+}
+
+fn wilson(hex_grid: &mut HexGrid, rng: &mut Rand) {
+    let mut hexes: Vec<_> = hex_grid.axial_coords().collect();
+    shuffle(rng, &mut hexes);
+
+    let mut in_maze = HashSet::from([hexes[0]]);
+
+    for &start in &hexes[1..] {
+        if in_maze.contains(&start) {
+            continue;
+        }
+
+        // Walk randomly until we hit the maze, remembering the last way we
+        // left each hex. That erases any loops the walk made.
+        let mut exits = HashMap::new();
+        let mut current = start;
+        while !in_maze.contains(&current) {
+            let neighbors: Vec<_> = hex_grid.neighbors(current.0, current.1).collect();
+            let (dir, next) = neighbors[random_below(rng, neighbors.len())];
+            exits.insert(current, (dir, next));
+            current = next;
+        }
+
+        // Then retrace the loop-erased walk, carving it into the maze.
+        let mut current = start;
+        while !in_maze.contains(&current) {
+            let (dir, next) = exits[&current];
+            hex_grid.set_wall(current.0, current.1, dir, None);
+            in_maze.insert(current);
+            current = next;
+        }
+    }
+}
+
+/// Returns a random number in `0..n`, without modulo bias.
+fn random_below(rng: &mut Rand, n: usize) -> usize {
+    let n = n as u64;
+    let threshold = n.wrapping_neg() % n;
+    loop {
+        let r = rng.random();
+        if r >= threshold {
+            return (r % n) as usize;
+        }
+    }
+}
+
+/// Fisher-Yates shuffle.
+fn shuffle<T>(rng: &mut Rand, items: &mut [T]) {
+    for i in (1..items.len()).rev() {
+        items.swap(i, random_below(rng, i + 1));
+    }
+}
+
+/// Finds the root of the set `i` is in, flattening the path along the way.
+fn find_root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rooms::find_rooms;
+
+    /// Counts the open passages between neighboring hexes, each once.
+    fn count_passages(hex_grid: &HexGrid) -> usize {
+        let mut count = 0;
+        for (q, r) in hex_grid.axial_coords() {
+            for dir in [Direction::East, Direction::SouthEast, Direction::SouthWest] {
+                if hex_grid.neighbor(q, r, dir).is_some() && hex_grid.wall(q, r, dir).is_none() {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    fn walls(hex_grid: &HexGrid) -> Vec<Option<Color>> {
+        hex_grid
+            .axial_coords()
+            .flat_map(|(q, r)| Direction::ALL.map(|dir| hex_grid.wall(q, r, dir)))
+            .collect()
+    }
+
+    fn assert_perfect_maze(hex_grid: &HexGrid) {
+        // Connected: a single room...
+        let rooms = find_rooms(hex_grid);
+        assert_eq!(rooms.rooms().len(), 1);
+
+        // ...and without loops: a spanning tree has one passage less than it
+        // has hexes.
+        let hex_count = (hex_grid.width() * hex_grid.height()) as usize;
+        assert_eq!(count_passages(hex_grid), hex_count - 1);
+
+        // The walls around the grid stay put.
+        for (q, r) in hex_grid.axial_coords() {
+            for dir in Direction::ALL {
+                if hex_grid.neighbor(q, r, dir).is_none() {
+                    assert_eq!(hex_grid.wall(q, r, dir), Some(Color::BLACK));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mazes_are_perfect() {
+        for algorithm in MazeAlgorithm::ALL {
+            for seed in 0..5 {
+                let mut hex_grid = HexGrid::new(9, 7);
+                let mut rng = Rand::new(seed);
+                generate_maze(&mut hex_grid, algorithm, Color::BLACK, &mut rng);
+                assert_perfect_maze(&hex_grid);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wrapping_mazes_are_perfect() {
+        for algorithm in MazeAlgorithm::ALL {
+            let mut hex_grid = HexGrid::new_wrapping(8, 5);
+            let mut rng = Rand::new(42);
+            generate_maze(&mut hex_grid, algorithm, Color::BLACK, &mut rng);
+            assert_perfect_maze(&hex_grid);
+        }
+    }
+
+    #[test]
+    fn test_single_hex_maze() {
+        let mut hex_grid = HexGrid::new(1, 1);
+        for algorithm in MazeAlgorithm::ALL {
+            generate_maze(&mut hex_grid, algorithm, Color::BLACK, &mut Rand::new(1));
+            assert_perfect_maze(&hex_grid);
+        }
+    }
+
+    #[test]
+    fn test_doors_are_walled_up() {
+        // Open doors left over from before would be shortcuts through the
+        // maze.
+        for algorithm in MazeAlgorithm::ALL {
+            let mut hex_grid = HexGrid::new(6, 6);
+            for (q, r) in hex_grid.axial_coords() {
+                for dir in Direction::ALL {
+                    hex_grid.set_wall(q, r, dir, Some(Color::RED));
+                    hex_grid.set_wall_kind(q, r, dir, WallKind::OpenDoor);
+                }
+            }
+            generate_maze(&mut hex_grid, algorithm, Color::BLACK, &mut Rand::new(3));
+            assert_perfect_maze(&hex_grid);
+
+            let mut crossings = 0;
+            for (q, r) in hex_grid.axial_coords() {
+                for (dir, _) in hex_grid.neighbors(q, r) {
+                    if hex_grid.can_cross(q, r, dir) {
+                        crossings += 1;
+                    }
+                }
+            }
+            assert_eq!(crossings, 2 * (6 * 6 - 1));
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_maze() {
+        for algorithm in MazeAlgorithm::ALL {
+            let mut a = HexGrid::new(9, 7);
+            let mut b = HexGrid::new(9, 7);
+            let mut c = HexGrid::new(9, 7);
+            generate_maze(&mut a, algorithm, Color::BLACK, &mut Rand::new(1234));
+            generate_maze(&mut b, algorithm, Color::BLACK, &mut Rand::new(1234));
+            generate_maze(&mut c, algorithm, Color::BLACK, &mut Rand::new(4321));
+            assert_eq!(walls(&a), walls(&b));
+            assert_ne!(walls(&a), walls(&c));
+        }
+    }
+
+    #[test]
+    fn test_random_below() {
+        let mut rng = Rand::new(7);
+        let mut seen = [false; 6];
+        for _ in 0..200 {
+            seen[random_below(&mut rng, 6)] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+        assert_eq!(random_below(&mut rng, 1), 0);
+    }
+}
@@ -0,0 +1,612 @@
+use crate::hex_grid::HexGrid;
+use crate::hex_map::HexMap;
+use crate::layers::HexLayer;
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+
+/// For every hex reached by a search: the cost of the cheapest known way to get
+/// there, and the hex we came from.
+type Visited = HashMap<(i32, i32), (u32, Option<(i32, i32)>)>;
+
+/// A path found by `find_path()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path {
+    /// The hexes along the path, in axial coordinates. Starts with the start
+    /// hex and ends with the goal hex.
+    pub hexes: Vec<(i32, i32)>,
+
+    /// The total cost of the path, that is, the sum of the costs of entering
+    /// each hex along it (not counting the start hex).
+    pub cost: u32,
+}
+
+/// The reasons why `find_path()` may fail to find a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathError {
+    /// The hex at these axial coordinates is not in the grid.
+    OutOfBounds(i32, i32),
+
+    /// There is no way to go from the start to the goal.
+    Unreachable,
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathError::OutOfBounds(q, r) => write!(f, "hex ({}, {}) is out of bounds", q, r),
+            PathError::Unreachable => write!(f, "goal is unreachable"),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// A hex reached by `movement_range()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reachable {
+    /// The hex, in canonical axial coordinates.
+    pub hex: (i32, i32),
+
+    /// The movement points left after the cheapest way there.
+    pub remaining: u32,
+
+    /// The hex before this one along the cheapest way there, or `None` for the
+    /// start hex.
+    pub from: Option<(i32, i32)>,
+}
+
+/// The hexes a unit can reach with some movement points, as found by
+/// `movement_range()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovementRange {
+    /// The movement points the unit started with.
+    points: u32,
+
+    /// Every hex reached, with the points spent to get there.
+    visited: Visited,
+}
+
+impl MovementRange {
+    /// Returns all reachable hexes, the start hex included, those with the
+    /// most points left first.
+    pub fn hexes(&self) -> Vec<Reachable> {
+        let mut hexes: Vec<_> = self
+            .visited
+            .iter()
+            .map(|(&hex, &(spent, from))| Reachable {
+                hex,
+                remaining: self.points - spent,
+                from,
+            })
+            .collect();
+        hexes.sort_by_key(|reachable| (Reverse(reachable.remaining), reachable.hex));
+        hexes
+    }
+
+    /// Returns the hex at the given axial coordinates, if it is reachable.
+    /// Coordinates must be canonical.
+    pub fn get(&self, q: i32, r: i32) -> Option<Reachable> {
+        let &(spent, from) = self.visited.get(&(q, r))?;
+        Some(Reachable {
+            hex: (q, r),
+            remaining: self.points - spent,
+            from,
+        })
+    }
+
+    /// Returns the cheapest path from the start to the hex at the given axial
+    /// coordinates, if it is reachable. Coordinates must be canonical.
+    pub fn path_to(&self, q: i32, r: i32) -> Option<Path> {
+        let goal = self.get(q, r)?;
+        let mut hexes = vec![goal.hex];
+        let mut current = goal;
+        while let Some((q, r)) = current.from {
+            hexes.push((q, r));
+            current = self.get(q, r).unwrap();
+        }
+        hexes.reverse();
+
+        Some(Path {
+            hexes,
+            cost: self.points - goal.remaining,
+        })
+    }
+}
+
+/// Finds the cheapest path between two hexes using A*.
+///
+/// Walls are impassable, unless their kind lets us through (open doors, and
+/// one-way passages going their way: see `WallKind::blocks_movement()`).
+/// `cost` returns the cost of entering the hex at the given
+/// axial coordinates, or `None` if the hex cannot be entered at all. Costs are
+/// expected to be at least 1 (zero costs are treated as 1, to keep the
+/// heuristic admissible). The cost of the start hex is never queried.
+///
+/// On grids that wrap around horizontally, paths may cross the seam. The hexes
+/// in the returned path are always in canonical coordinates (see
+/// `HexGrid::normalize()`).
+pub fn find_path<M, F>(
+    hex_grid: &M,
+    start: (i32, i32),
+    goal: (i32, i32),
+    mut cost: F,
+) -> Result<Path, PathError>
+where
+    M: HexMap,
+    F: FnMut(i32, i32) -> Option<u32>,
+{
+    let start = hex_grid
+        .normalize(start.0, start.1)
+        .ok_or(PathError::OutOfBounds(start.0, start.1))?;
+    let goal = hex_grid
+        .normalize(goal.0, goal.1)
+        .ok_or(PathError::OutOfBounds(goal.0, goal.1))?;
+
+    let mut visited = Visited::new();
+    visited.insert(start, (0, None));
+
+    // Using the heuristic as a tie-breaker makes the search prefer hexes
+    // closer to the goal, and the coordinates keep things deterministic.
+    let mut open = BinaryHeap::new();
+    let h = hex_grid.distance(start, goal);
+    open.push(Reverse((h, h, start)));
+
+    while let Some(Reverse((f, h, current))) = open.pop() {
+        if current == goal {
+            return Ok(rebuild_path(&visited, goal));
+        }
+
+        // Skip entries made obsolete by a cheaper way to the same hex.
+        let current_cost = visited[&current].0;
+        if f - h > current_cost {
+            continue;
+        }
+
+        let (q, r) = current;
+
+        for (dir, next) in hex_grid.neighbors(q, r) {
+            if !hex_grid.can_cross(q, r, dir) {
+                continue;
+            }
+
+            let step_cost = match cost(next.0, next.1) {
+                Some(c) => c.max(1),
+                None => continue,
+            };
+
+            let next_cost = current_cost + step_cost;
+            let improved = match visited.get(&next) {
+                Some((known_cost, _)) => next_cost < *known_cost,
+                None => true,
+            };
+
+            if improved {
+                visited.insert(next, (next_cost, Some(current)));
+                let h = hex_grid.distance(next, goal);
+                open.push(Reverse((next_cost + h, h, next)));
+            }
+        }
+    }
+
+    Err(PathError::Unreachable)
+}
+
+/// Finds every hex a unit standing at `start` can reach with `points`
+/// movement points, using Dijkstra's algorithm.
+///
+/// `cost` and walls work the same as in `find_path()`: entering a hex costs
+/// `cost` points (at least 1), and hexes where it returns `None` can't be
+/// entered. Hexes where `stops` returns `true` can be entered, but not left:
+/// those in the zone of control of an enemy unit, say. (The start hex is never
+/// checked, so units can always move out of a zone of control they start
+/// in.) Pass `|_, _| false` to ignore zones of control.
+pub fn movement_range<M, F, Z>(
+    hex_grid: &M,
+    start: (i32, i32),
+    points: u32,
+    mut cost: F,
+    mut stops: Z,
+) -> Result<MovementRange, PathError>
+where
+    M: HexMap,
+    F: FnMut(i32, i32) -> Option<u32>,
+    Z: FnMut(i32, i32) -> bool,
+{
+    let start = hex_grid
+        .normalize(start.0, start.1)
+        .ok_or(PathError::OutOfBounds(start.0, start.1))?;
+
+    let mut visited = Visited::new();
+    visited.insert(start, (0, None));
+
+    let mut open = BinaryHeap::new();
+    open.push(Reverse((0, start)));
+
+    while let Some(Reverse((spent, current))) = open.pop() {
+        // Skip entries made obsolete by a cheaper way to the same hex.
+        if spent > visited[&current].0 {
+            continue;
+        }
+
+        let (q, r) = current;
+        if current != start && stops(q, r) {
+            continue;
+        }
+
+        for (dir, next) in hex_grid.neighbors(q, r) {
+            if !hex_grid.can_cross(q, r, dir) {
+                continue;
+            }
+
+            let step_cost = match cost(next.0, next.1) {
+                Some(c) => c.max(1),
+                None => continue,
+            };
+
+            let next_spent = spent + step_cost;
+            if next_spent > points {
+                continue;
+            }
+            let improved = match visited.get(&next) {
+                Some((known_spent, _)) => next_spent < *known_spent,
+                None => true,
+            };
+
+            if improved {
+                visited.insert(next, (next_spent, Some(current)));
+                open.push(Reverse((next_spent, next)));
+            }
+        }
+    }
+
+    Ok(MovementRange { points, visited })
+}
+
+/// Computes, for every hex of the grid, the cost of the cheapest way from it to
+/// the nearest of `sources`, using Dijkstra's algorithm from all the sources at
+/// once. Hexes that can't reach any source are left at `None`.
+///
+/// `cost` and walls work the same as in `find_path()`, going from each hex
+/// towards the sources (which matters for one-way passages). So a unit
+/// following the field downhill, always stepping to the neighbor with the
+/// lowest value, takes the cheapest way to the nearest source. Sources out of
+/// the grid are ignored.
+pub fn distance_field<F>(
+    hex_grid: &HexGrid,
+    sources: &[(i32, i32)],
+    mut cost: F,
+) -> HexLayer<Option<u32>>
+where
+    F: FnMut(i32, i32) -> Option<u32>,
+{
+    let mut field = HexLayer::new(hex_grid.layout(), None);
+    let mut open = BinaryHeap::new();
+    for &(q, r) in sources {
+        if let Some(source) = hex_grid.normalize(q, r) {
+            field.set(source.0, source.1, Some(0));
+            open.push(Reverse((0, source)));
+        }
+    }
+
+    while let Some(Reverse((distance, current))) = open.pop() {
+        // Skip entries made obsolete by a cheaper way to the same hex.
+        let (q, r) = current;
+        if field.get(q, r) != Some(&Some(distance)) {
+            continue;
+        }
+
+        // We are going backwards: from each neighbor into this hex.
+        let Some(step_cost) = cost(q, r) else {
+            continue;
+        };
+        let next_distance = distance + step_cost.max(1);
+
+        for (dir, prev) in hex_grid.neighbors(q, r) {
+            if !hex_grid.can_cross(prev.0, prev.1, dir.opposite()) {
+                continue;
+            }
+
+            let known = field.get_mut(prev.0, prev.1).unwrap();
+            let improved = match known {
+                Some(known_distance) => next_distance < *known_distance,
+                None => true,
+            };
+
+            if improved {
+                *known = Some(next_distance);
+                open.push(Reverse((next_distance, prev)));
+            }
+        }
+    }
+
+    field
+}
+
+//
+// Internal helpers
+//
+
+fn rebuild_path(visited: &Visited, goal: (i32, i32)) -> Path {
+    let mut hexes = vec![goal];
+    let mut current = goal;
+    while let Some(prev) = visited[&current].1 {
+        hexes.push(prev);
+        current = prev;
+    }
+    hexes.reverse();
+
+    Path {
+        hexes,
+        cost: visited[&goal].0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex_grid::*;
+    use raylib::prelude::Color;
+
+    fn uniform(_q: i32, _r: i32) -> Option<u32> {
+        Some(1)
+    }
+
+    fn assert_path_is_connected(hex_grid: &HexGrid, path: &Path) {
+        for pair in path.hexes.windows(2) {
+            assert_eq!(hex_grid.distance(pair[0], pair[1]), 1, "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn test_straight_path() {
+        let hex_grid = HexGrid::new(10, 10);
+        let path = find_path(&hex_grid, (0, 0), (5, 0), uniform).unwrap();
+        assert_eq!(path.cost, 5);
+        assert_eq!(path.hexes.len(), 6);
+        assert_eq!(path.hexes[0], (0, 0));
+        assert_eq!(path.hexes[5], (5, 0));
+        assert_path_is_connected(&hex_grid, &path);
+    }
+
+    #[test]
+    fn test_path_to_self() {
+        let hex_grid = HexGrid::new(3, 3);
+        let path = find_path(&hex_grid, (1, 1), (1, 1), uniform).unwrap();
+        assert_eq!(path.hexes, vec![(1, 1)]);
+        assert_eq!(path.cost, 0);
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let hex_grid = HexGrid::new(3, 3);
+        assert_eq!(
+            find_path(&hex_grid, (0, 0), (7, 0), uniform),
+            Err(PathError::OutOfBounds(7, 0))
+        );
+        assert_eq!(
+            find_path(&hex_grid, (-1, 0), (0, 0), uniform),
+            Err(PathError::OutOfBounds(-1, 0))
+        );
+    }
+
+    #[test]
+    fn test_walls_block() {
+        // Two hexes side by side; with the wall between them, there's no other
+        // way around.
+        let mut hex_grid = HexGrid::new(2, 1);
+        assert!(find_path(&hex_grid, (0, 0), (1, 0), uniform).is_ok());
+
+        hex_grid.set_wall(0, 0, Direction::East, Some(Color::BLACK));
+        assert_eq!(
+            find_path(&hex_grid, (0, 0), (1, 0), uniform),
+            Err(PathError::Unreachable)
+        );
+        assert_eq!(
+            find_path(&hex_grid, (1, 0), (0, 0), uniform),
+            Err(PathError::Unreachable)
+        );
+    }
+
+    #[test]
+    fn test_wall_kinds() {
+        let mut hex_grid = HexGrid::new(2, 1);
+        hex_grid.set_wall(0, 0, Direction::East, Some(Color::BLACK));
+
+        hex_grid.set_wall_kind(0, 0, Direction::East, WallKind::OpenDoor);
+        assert!(find_path(&hex_grid, (0, 0), (1, 0), uniform).is_ok());
+        assert!(find_path(&hex_grid, (1, 0), (0, 0), uniform).is_ok());
+
+        for kind in [WallKind::LockedDoor, WallKind::Window] {
+            hex_grid.set_wall_kind(0, 0, Direction::East, kind);
+            assert_eq!(
+                find_path(&hex_grid, (0, 0), (1, 0), uniform),
+                Err(PathError::Unreachable)
+            );
+        }
+
+        // One-way passages only let us through one way.
+        let one_way = WallKind::OneWay(Direction::West);
+        hex_grid.set_wall_kind(0, 0, Direction::East, one_way);
+        assert!(find_path(&hex_grid, (1, 0), (0, 0), uniform).is_ok());
+        assert_eq!(
+            find_path(&hex_grid, (0, 0), (1, 0), uniform),
+            Err(PathError::Unreachable)
+        );
+    }
+
+    #[test]
+    fn test_walls_force_detour() {
+        // Wall off the whole east side of the start hex; the path must go
+        // around it.
+        let mut hex_grid = HexGrid::new(5, 5);
+        hex_grid.set_wall(1, 2, Direction::NorthEast, Some(Color::BLACK));
+        hex_grid.set_wall(1, 2, Direction::East, Some(Color::BLACK));
+        hex_grid.set_wall(1, 2, Direction::SouthEast, Some(Color::BLACK));
+
+        let path = find_path(&hex_grid, (1, 2), (2, 2), uniform).unwrap();
+        assert_path_is_connected(&hex_grid, &path);
+        assert_eq!(path.cost, 3);
+        assert_eq!(path.hexes.first(), Some(&(1, 2)));
+        assert_eq!(path.hexes.last(), Some(&(2, 2)));
+    }
+
+    #[test]
+    fn test_wrapping() {
+        // Going west from the westernmost hex is the shortest way to the
+        // easternmost one.
+        let hex_grid = HexGrid::new_wrapping(10, 3);
+        let path = find_path(&hex_grid, (0, 1), (-1, 1), uniform).unwrap();
+        assert_eq!(path.hexes, vec![(0, 1), (9, 1)]);
+
+        let path = find_path(&hex_grid, (1, 0), (8, 0), uniform).unwrap();
+        assert_eq!(path.cost, 3);
+        assert_path_is_connected(&hex_grid, &path);
+
+        // Unless there's a wall on the way, of course.
+        let mut hex_grid = hex_grid;
+        for r in 0..3 {
+            hex_grid.set_wall(0, r, Direction::West, Some(Color::BLACK));
+            hex_grid.set_wall(0, r, Direction::SouthWest, Some(Color::BLACK));
+        }
+        let path = find_path(&hex_grid, (0, 1), (9, 1), uniform).unwrap();
+        assert_eq!(path.cost, 9);
+    }
+
+    #[test]
+    fn test_costs() {
+        // A cheap detour beats an expensive shortcut.
+        let hex_grid = HexGrid::new(5, 5);
+        let cost = |q, r| if (q, r) == (2, 2) { Some(10) } else { Some(1) };
+        let path = find_path(&hex_grid, (1, 2), (3, 2), cost).unwrap();
+        assert_eq!(path.cost, 3);
+        assert!(!path.hexes.contains(&(2, 2)));
+
+        // And impassable hexes are avoided altogether.
+        let cost = |q, _r| if q == 2 { None } else { Some(1) };
+        assert_eq!(
+            find_path(&hex_grid, (1, 2), (3, 2), cost),
+            Err(PathError::Unreachable)
+        );
+    }
+
+    #[test]
+    fn test_movement_range() {
+        let hex_grid = HexGrid::new(10, 10);
+        let never = |_, _| false;
+
+        // With uniform costs, the range is a big hex.
+        let range = movement_range(&hex_grid, (4, 4), 2, uniform, never).unwrap();
+        let hexes = range.hexes();
+        assert_eq!(hexes.len(), 19);
+        assert_eq!(
+            hexes[0],
+            Reachable {
+                hex: (4, 4),
+                remaining: 2,
+                from: None
+            }
+        );
+        for reachable in &hexes {
+            let (q, r) = reachable.hex;
+            assert_eq!(reachable.remaining, 2 - hex_grid.distance((4, 4), (q, r)));
+            assert_eq!(range.get(q, r), Some(*reachable));
+        }
+        assert_eq!(range.get(7, 4), None);
+
+        // Paths can be rebuilt from the predecessors.
+        let path = range.path_to(6, 4).unwrap();
+        assert_eq!(path.hexes, vec![(4, 4), (5, 4), (6, 4)]);
+        assert_eq!(path.cost, 2);
+        assert_eq!(range.path_to(7, 4), None);
+
+        assert_eq!(
+            movement_range(&hex_grid, (-1, 0), 2, uniform, never),
+            Err(PathError::OutOfBounds(-1, 0))
+        );
+    }
+
+    #[test]
+    fn test_movement_range_costs_and_walls() {
+        let mut hex_grid = HexGrid::new(10, 10);
+        let never = |_, _| false;
+        let cost = |q, _r| if q == 5 { Some(3) } else { Some(1) };
+
+        let range = movement_range(&hex_grid, (4, 4), 3, cost, never).unwrap();
+        assert_eq!(range.get(5, 4).map(|r| r.remaining), Some(0));
+        assert_eq!(range.get(6, 4), None);
+        assert_eq!(range.get(3, 4).map(|r| r.remaining), Some(2));
+
+        // Walls block, so the way east goes around.
+        hex_grid.set_wall(4, 4, Direction::East, Some(Color::BLACK));
+        let range = movement_range(&hex_grid, (4, 4), 3, uniform, never).unwrap();
+        let reachable = range.get(5, 4).unwrap();
+        assert_eq!(reachable.remaining, 1);
+        assert_ne!(reachable.from, Some((4, 4)));
+    }
+
+    #[test]
+    fn test_zones_of_control() {
+        // Hexes next to an enemy at (6, 4) can be entered, but not left.
+        let hex_grid = HexGrid::new(10, 10);
+        let enemy = (6, 4);
+        let zone = |q, r| hex_grid.distance((q, r), enemy) == 1;
+
+        let free = movement_range(&hex_grid, (4, 4), 4, uniform, |_, _| false).unwrap();
+        let controlled = movement_range(&hex_grid, (4, 4), 4, uniform, zone).unwrap();
+        assert!(free.get(7, 4).is_some());
+        assert!(controlled.get(5, 4).is_some());
+        assert_eq!(controlled.get(7, 4), None);
+        assert!(controlled.hexes().len() < free.hexes().len());
+
+        // Starting in a zone of control doesn't stop us.
+        let range = movement_range(&hex_grid, (5, 4), 1, uniform, zone).unwrap();
+        assert_eq!(range.hexes().len(), 7);
+    }
+
+    #[test]
+    fn test_distance_field() {
+        let mut hex_grid = HexGrid::new(10, 5);
+        let field = distance_field(&hex_grid, &[(0, 0), (7, 4), (20, 0)], uniform);
+        for (q, r) in hex_grid.axial_coords() {
+            let nearest = hex_grid
+                .distance((q, r), (0, 0))
+                .min(hex_grid.distance((q, r), (7, 4)));
+            assert_eq!(field.get(q, r), Some(&Some(nearest)));
+        }
+
+        // The values are the costs of the cheapest paths to the sources.
+        let cost = |q, _r| if q == 3 { Some(5) } else { Some(1) };
+        let field = distance_field(&hex_grid, &[(6, 2)], cost);
+        for (q, r) in hex_grid.axial_coords() {
+            let path = find_path(&hex_grid, (q, r), (6, 2), cost).unwrap();
+            assert_eq!(field.get(q, r), Some(&Some(path.cost)));
+        }
+
+        // Walls out one hex: it can't get anywhere. A one-way passage lets it
+        // out, but still keeps others from coming in.
+        for dir in Direction::ALL {
+            hex_grid.set_wall(2, 2, dir, Some(Color::BLACK));
+        }
+        let field = distance_field(&hex_grid, &[(0, 0)], uniform);
+        assert_eq!(field.get(2, 2), Some(&None));
+        assert_eq!(field.get(3, 2), Some(&Some(5)));
+        hex_grid.set_wall_kind(2, 2, Direction::West, WallKind::OneWay(Direction::West));
+        let field = distance_field(&hex_grid, &[(0, 0)], uniform);
+        assert_eq!(field.get(2, 2), Some(&Some(4)));
+        let field = distance_field(&hex_grid, &[(2, 2)], uniform);
+        assert_eq!(field.get(1, 2), Some(&None));
+
+        let field = distance_field(&hex_grid, &[], uniform);
+        assert!(field.as_slice().iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_distance_field_wrapping() {
+        let hex_grid = HexGrid::new_wrapping(10, 3);
+        let field = distance_field(&hex_grid, &[(0, 1)], uniform);
+        assert_eq!(field.get(9, 1), Some(&Some(1)));
+        assert_eq!(field.get(5, 1), Some(&Some(5)));
+    }
+}
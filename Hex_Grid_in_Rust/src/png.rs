@@ -0,0 +1,223 @@
+// A minimal PNG encoder: 8-bit RGBA, no filtering, and no compression either
+// (the image data is wrapped in "stored" deflate blocks). The files are big,
+// but any PNG reader can read them, and we don't need any dependencies.
+//
+// See https://www.w3.org/TR/png/ and RFCs 1950 (zlib) and 1951 (deflate).
+
+use std::io::{self, Write};
+
+/// The 8 bytes every PNG file starts with.
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// The largest amount of data a stored deflate block can hold.
+const MAX_STORED_BLOCK: usize = 65535;
+
+/// Writes an image as PNG to `writer`. `rgba` has 4 bytes for each pixel, row
+/// after row, starting from the top-left corner.
+pub fn write_png<W: Write>(writer: &mut W, width: u32, height: u32, rgba: &[u8]) -> io::Result<()> {
+    assert_eq!(
+        rgba.len(),
+        width as usize * height as usize * 4,
+        "image data doesn't match the {}x{} size",
+        width,
+        height
+    );
+
+    writer.write_all(&SIGNATURE)?;
+
+    // Header: size, 8 bits per channel, color type 6 (RGBA), and then the
+    // default compression, filtering and (lack of) interlacing.
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_chunk(writer, b"IHDR", &header)?;
+
+    // Each row starts with its filter type; 0 means no filtering.
+    let row_len = width as usize * 4;
+    let mut raw = Vec::with_capacity((row_len + 1) * height as usize);
+    for row in rgba.chunks(row_len.max(1)).take(height as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    write_chunk(writer, b"IDAT", &zlib_stored(&raw))?;
+
+    write_chunk(writer, b"IEND", &[])
+}
+
+//
+// Internal helpers
+//
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+
+    // The CRC covers the chunk type, too.
+    let crc = !crc32_update(crc32_update(!0, kind), data);
+    writer.write_all(&crc.to_be_bytes())
+}
+
+/// Wraps `data` in a zlib stream, without compressing it.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let block_count = data.len().div_ceil(MAX_STORED_BLOCK).max(1);
+    let mut out = Vec::with_capacity(data.len() + 5 * block_count + 6);
+
+    // Deflate, 32K window, no preset dictionary, fastest "compression" (with
+    // the check bits making the header a multiple of 31).
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        // Even no data needs a (final, empty) block.
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(is_final as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// Feeds `data` to a running CRC-32 (the one used by PNG, zlib, etc). Start
+/// with all bits set, and flip them all at the end.
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb88320 & mask);
+        }
+    }
+    crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+
+    // Taking the modulo only every few thousand bytes is fine, as the sums
+    // can't overflow that quickly.
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crc32(data: &[u8]) -> u32 {
+        !crc32_update(!0, data)
+    }
+
+    // Reads the chunks back, checking their CRCs, and undoes the stored
+    // deflate blocks. Returns the header and the raw image data.
+    fn decode(png: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        assert_eq!(png[..8], SIGNATURE);
+        let mut pos = 8;
+        let mut header = Vec::new();
+        let mut idat = Vec::new();
+        while pos < png.len() {
+            let len = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+            let kind = &png[pos + 4..pos + 8];
+            let data = &png[pos + 8..pos + 8 + len];
+            let crc = u32::from_be_bytes(png[pos + 8 + len..pos + 12 + len].try_into().unwrap());
+            assert_eq!(crc, crc32(&png[pos + 4..pos + 8 + len]));
+            match kind {
+                b"IHDR" => header = data.to_vec(),
+                b"IDAT" => idat.extend_from_slice(data),
+                b"IEND" => assert_eq!(pos + 12 + len, png.len()),
+                _ => panic!("unexpected chunk {:?}", kind),
+            }
+            pos += 12 + len;
+        }
+
+        assert_eq!(idat[..2], [0x78, 0x01]);
+        assert_eq!((u16::from_be_bytes([idat[0], idat[1]])) % 31, 0);
+        let mut raw = Vec::new();
+        let mut pos = 2;
+        loop {
+            let is_final = idat[pos] == 1;
+            let len = u16::from_le_bytes([idat[pos + 1], idat[pos + 2]]) as usize;
+            let nlen = u16::from_le_bytes([idat[pos + 3], idat[pos + 4]]) as usize;
+            assert_eq!(len, !nlen & 0xffff);
+            raw.extend_from_slice(&idat[pos + 5..pos + 5 + len]);
+            pos += 5 + len;
+            if is_final {
+                break;
+            }
+        }
+        let adler = u32::from_be_bytes(idat[pos..pos + 4].try_into().unwrap());
+        assert_eq!(adler, adler32(&raw));
+        assert_eq!(pos + 4, idat.len());
+
+        (header, raw)
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(&[0xff; 100_000]), 0x149a_302c);
+    }
+
+    #[test]
+    fn test_write_png() {
+        let rgba = [
+            255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, //
+            0, 0, 0, 0, 1, 2, 3, 4, 255, 255, 255, 255,
+        ];
+        let mut png = Vec::new();
+        write_png(&mut png, 3, 2, &rgba).unwrap();
+
+        let (header, raw) = decode(&png);
+        assert_eq!(header, [0, 0, 0, 3, 0, 0, 0, 2, 8, 6, 0, 0, 0]);
+        assert_eq!(raw.len(), 2 * (1 + 12));
+        assert_eq!(raw[0], 0);
+        assert_eq!(raw[1..13], rgba[..12]);
+        assert_eq!(raw[13], 0);
+        assert_eq!(raw[14..], rgba[12..]);
+    }
+
+    #[test]
+    fn test_write_big_png() {
+        // Big enough to need several deflate blocks.
+        let (width, height) = (300, 200);
+        let rgba: Vec<u8> = (0..width * height * 4).map(|i| (i % 251) as u8).collect();
+        let mut png = Vec::new();
+        write_png(&mut png, width as u32, height as u32, &rgba).unwrap();
+
+        let (_, raw) = decode(&png);
+        let rows: Vec<_> = raw.chunks(width * 4 + 1).collect();
+        assert_eq!(rows.len(), height);
+        for (y, row) in rows.iter().enumerate() {
+            assert_eq!(row[0], 0);
+            assert_eq!(row[1..], rgba[y * width * 4..(y + 1) * width * 4]);
+        }
+    }
+
+    #[test]
+    fn test_write_empty_png() {
+        let mut png = Vec::new();
+        write_png(&mut png, 0, 0, &[]).unwrap();
+        let (header, raw) = decode(&png);
+        assert_eq!(header[..8], [0; 8]);
+        assert!(raw.is_empty());
+    }
+}
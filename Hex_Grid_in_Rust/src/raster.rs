@@ -0,0 +1,371 @@
+use crate::backend::*;
+use crate::hex_map::HexMap;
+use crate::png;
+use crate::render::HexGridRenderer;
+use raylib::prelude::*;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Pixels are anti-aliased by sampling them at `SUBSAMPLES` x `SUBSAMPLES`
+/// points.
+const SUBSAMPLES: u32 = 4;
+
+/// An image in memory, which can be drawn on like any other `DrawBackend`.
+///
+/// Everything is rasterized on the CPU, anti-aliased, and blended over what was
+/// already there. Text is not drawn at all, though: we have no font to draw it
+/// with.
+pub struct Canvas {
+    width: u32,
+    height: u32,
+
+    /// The color of each pixel, row after row, starting from the top-left.
+    pixels: Vec<Color>,
+
+    /// The drawing position that lands on the top-left corner of the canvas.
+    origin: Vector2,
+
+    /// How many pixels in the canvas for each unit of drawing positions.
+    scale: f32,
+}
+
+impl Canvas {
+    /// Creates a new canvas, filled with `background`.
+    pub fn new(width: u32, height: u32, background: Color) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![background; width as usize * height as usize],
+            origin: Vector2::new(0.0, 0.0),
+            scale: 1.0,
+        }
+    }
+
+    /// Sets how drawing positions map to the canvas: `origin` lands on the
+    /// top-left corner of the canvas, and each unit is `scale` pixels. (Line
+    /// thicknesses and radii are scaled, too.)
+    pub fn set_transform(&mut self, origin: Vector2, scale: f32) {
+        self.origin = origin;
+        self.scale = scale;
+    }
+
+    /// Writes the canvas as PNG to `writer`.
+    pub fn write_png<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let rgba: Vec<u8> = self
+            .pixels
+            .iter()
+            .flat_map(|c| [c.r, c.g, c.b, c.a])
+            .collect();
+        png::write_png(writer, self.width, self.height, &rgba)
+    }
+
+    //
+    // Internal helpers
+    //
+
+    /// Converts a drawing position to canvas coordinates (in pixels, but not
+    /// rounded).
+    fn to_canvas(&self, p: Vector2) -> Vector2 {
+        (p - self.origin) * self.scale
+    }
+
+    /// Fills the area covered by the shape `inside` (a test taking points in
+    /// canvas coordinates) within the given bounding box.
+    fn fill_shape<F>(&mut self, min: Vector2, max: Vector2, color: Color, inside: F)
+    where
+        F: Fn(f32, f32) -> bool,
+    {
+        let x0 = min.x.floor().max(0.0) as u32;
+        let y0 = min.y.floor().max(0.0) as u32;
+        let x1 = (max.x.ceil().max(0.0) as u32).min(self.width);
+        let y1 = (max.y.ceil().max(0.0) as u32).min(self.height);
+
+        let step = 1.0 / SUBSAMPLES as f32;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let (fx, fy) = (x as f32, y as f32);
+
+                // Pixels well inside the shape (as most are) don't need all
+                // the samples.
+                let coverage = if inside(fx, fy)
+                    && inside(fx + 1.0, fy)
+                    && inside(fx, fy + 1.0)
+                    && inside(fx + 1.0, fy + 1.0)
+                {
+                    1.0
+                } else {
+                    let mut count = 0;
+                    for sy in 0..SUBSAMPLES {
+                        for sx in 0..SUBSAMPLES {
+                            let px = fx + (sx as f32 + 0.5) * step;
+                            let py = fy + (sy as f32 + 0.5) * step;
+                            if inside(px, py) {
+                                count += 1;
+                            }
+                        }
+                    }
+                    count as f32 / (SUBSAMPLES * SUBSAMPLES) as f32
+                };
+
+                if coverage > 0.0 {
+                    self.blend(x, y, color, coverage);
+                }
+            }
+        }
+    }
+
+    /// Blends `color` over the pixel at (`x`, `y`), as if it covered only
+    /// `coverage` (between 0 and 1) of it.
+    fn blend(&mut self, x: u32, y: u32, color: Color, coverage: f32) {
+        let index = (y * self.width + x) as usize;
+        let dst = self.pixels[index];
+
+        let a = color.a as f32 / 255.0 * coverage;
+        let dst_a = dst.a as f32 / 255.0;
+        let out_a = a + dst_a * (1.0 - a);
+        if out_a <= 0.0 {
+            return;
+        }
+
+        let mix = |s: u8, d: u8| {
+            let v = (s as f32 * a + d as f32 * dst_a * (1.0 - a)) / out_a;
+            v.round().clamp(0.0, 255.0) as u8
+        };
+
+        self.pixels[index] = Color::new(
+            mix(color.r, dst.r),
+            mix(color.g, dst.g),
+            mix(color.b, dst.b),
+            (out_a * 255.0).round() as u8,
+        );
+    }
+}
+
+impl DrawBackend for Canvas {
+    fn fill_polygon(&mut self, points: &[Vector2], color: Color) {
+        if points.len() < 3 {
+            return;
+        }
+
+        let points: Vec<_> = points.iter().map(|&p| self.to_canvas(p)).collect();
+        let mut min = Vector2::new(f32::MAX, f32::MAX);
+        let mut max = Vector2::new(f32::MIN, f32::MIN);
+        for p in &points {
+            min = Vector2::new(min.x.min(p.x), min.y.min(p.y));
+            max = Vector2::new(max.x.max(p.x), max.y.max(p.y));
+        }
+
+        // A point is inside a convex polygon if it is on the same side of all
+        // of its edges; which side depends on the winding order.
+        let cross = |a: Vector2, b: Vector2, x: f32, y: f32| {
+            (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+        };
+        let area: f32 = (0..points.len())
+            .map(|i| {
+                let (a, b) = (points[i], points[(i + 1) % points.len()]);
+                a.x * b.y - b.x * a.y
+            })
+            .sum();
+        let sign = if area < 0.0 { -1.0 } else { 1.0 };
+
+        self.fill_shape(min, max, color, |x, y| {
+            (0..points.len()).all(|i| {
+                let (a, b) = (points[i], points[(i + 1) % points.len()]);
+                cross(a, b, x, y) * sign >= 0.0
+            })
+        });
+    }
+
+    fn stroke_polygon(&mut self, points: &[Vector2], thickness: f32, color: Color) {
+        for i in 0..points.len() {
+            let (start, end) = (points[i], points[(i + 1) % points.len()]);
+            self.draw_line(start, end, thickness, color);
+        }
+    }
+
+    fn draw_line(&mut self, start: Vector2, end: Vector2, thickness: f32, color: Color) {
+        // A thick line is just a thin rectangle.
+        let d = end - start;
+        let len = d.x.hypot(d.y);
+        if len == 0.0 {
+            return;
+        }
+
+        let n = Vector2::new(-d.y, d.x) * (thickness / 2.0 / len);
+        self.fill_polygon(&[start + n, end + n, end - n, start - n], color);
+    }
+
+    fn fill_circle(&mut self, center: Vector2, radius: f32, color: Color) {
+        let center = self.to_canvas(center);
+        let radius = radius * self.scale;
+        let extent = Vector2::new(radius, radius);
+
+        self.fill_shape(center - extent, center + extent, color, |x, y| {
+            (x - center.x).hypot(y - center.y) <= radius
+        });
+    }
+
+    fn draw_text(&mut self, _text: &str, _position: Vector2, _font_size: i32, _color: Color) {}
+}
+
+// Rasterizing grids, without needing a window (or a display) like drawing
+// with raylib does.
+impl HexGridRenderer {
+    /// Saves a PNG image of `hex_grid` to the file at `path`. See
+    /// `rasterize()` for details.
+    pub fn save_png_to_file<M: HexMap, P: AsRef<Path>>(
+        &self,
+        hex_grid: &M,
+        scale: f32,
+        path: P,
+    ) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.rasterize(hex_grid, scale).write_png(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Draws `hex_grid` on a new canvas, just big enough to fit it, over a
+    /// white background. This is the same picture `draw_all()` draws, minus
+    /// the text, scaled by `scale`.
+    pub fn rasterize<M: HexMap>(&self, hex_grid: &M, scale: f32) -> Canvas {
+        let mut recording = RecordingBackend::new();
+        self.draw_all(&mut recording, hex_grid);
+
+        let (min, max) = bounds(&recording.calls).unwrap_or_default();
+        let size = (max - min) * scale;
+        let mut canvas = Canvas::new(size.x.ceil() as u32, size.y.ceil() as u32, Color::WHITE);
+        canvas.set_transform(min, scale);
+
+        for call in &recording.calls {
+            call.replay(&mut canvas);
+        }
+
+        canvas
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex_grid::*;
+
+    impl Canvas {
+        fn pixel(&self, x: u32, y: u32) -> Option<Color> {
+            if x < self.width && y < self.height {
+                Some(self.pixels[(y * self.width + x) as usize])
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn test_fill_polygon() {
+        let mut canvas = Canvas::new(10, 10, Color::WHITE);
+        let square = [
+            Vector2::new(2.0, 2.0),
+            Vector2::new(6.0, 2.0),
+            Vector2::new(6.0, 6.5),
+            Vector2::new(2.0, 6.5),
+        ];
+        canvas.fill_polygon(&square, Color::BLUE);
+
+        assert_eq!(canvas.pixel(3, 3), Some(Color::BLUE));
+        assert_eq!(canvas.pixel(1, 1), Some(Color::WHITE));
+        assert_eq!(canvas.pixel(7, 3), Some(Color::WHITE));
+
+        // Half covered.
+        let edge = canvas.pixel(3, 6).unwrap();
+        assert_eq!(edge, Color::new(128, 188, 248, 255));
+
+        // The winding order doesn't matter.
+        let mut reversed = Canvas::new(10, 10, Color::WHITE);
+        let mut points = square;
+        points.reverse();
+        reversed.fill_polygon(&points, Color::BLUE);
+        assert_eq!(reversed.pixels, canvas.pixels);
+    }
+
+    #[test]
+    fn test_transform_and_blending() {
+        let mut canvas = Canvas::new(10, 10, Color::WHITE);
+        canvas.set_transform(Vector2::new(100.0, 100.0), 2.0);
+        canvas.draw_line(
+            Vector2::new(100.0, 102.5),
+            Vector2::new(105.0, 102.5),
+            1.0,
+            Color::BLACK.fade(0.5),
+        );
+
+        // The line goes along y = 5 (in pixels), 2 pixels thick.
+        for x in 0..10 {
+            assert_eq!(canvas.pixel(x, 3), Some(Color::WHITE));
+            assert_eq!(canvas.pixel(x, 4), Some(Color::new(128, 128, 128, 255)));
+            assert_eq!(canvas.pixel(x, 5), Some(Color::new(128, 128, 128, 255)));
+            assert_eq!(canvas.pixel(x, 6), Some(Color::WHITE));
+        }
+    }
+
+    #[test]
+    fn test_fill_circle() {
+        let mut canvas = Canvas::new(20, 20, Color::WHITE);
+        canvas.fill_circle(Vector2::new(10.0, 10.0), 5.0, Color::RED);
+        assert_eq!(canvas.pixel(10, 10), Some(Color::RED));
+        assert_eq!(canvas.pixel(12, 12), Some(Color::RED));
+        assert_eq!(canvas.pixel(14, 14), Some(Color::WHITE));
+        assert_eq!(canvas.pixel(0, 0), Some(Color::WHITE));
+        assert_eq!(canvas.pixel(20, 0), None);
+    }
+
+    #[test]
+    fn test_rasterize() {
+        let renderer = HexGridRenderer::new(10.0);
+        let mut hex_grid = HexGrid::new(3, 2);
+        for (q, r) in hex_grid.axial_coords() {
+            hex_grid.set_hex_color(q, r, Color::BLUE);
+        }
+        hex_grid.set_hex_color(1, 1, Color::RED);
+
+        let canvas = renderer.rasterize(&hex_grid, 2.0);
+
+        // The drawing bounds, scaled: three and a half hexes wide, plus the
+        // outlines.
+        let width = (3.5 * 3f32.sqrt() * 10.0 + 1.0) * 2.0;
+        assert_eq!(canvas.width, width.ceil() as u32);
+        assert_eq!(canvas.height, 72);
+
+        // The centers of the hexes (the renderer puts the center of (0, 0) at
+        // the origin).
+        let at = |q: i32, r: i32| {
+            let x =
+                (3f32.sqrt() * 10.0 * (q as f32 + r as f32 / 2.0) + 3f32.sqrt() * 5.0 + 0.5) * 2.0;
+            let y = (15.0 * r as f32 + 10.5) * 2.0;
+            canvas.pixel(x as u32, y as u32)
+        };
+        assert_eq!(at(0, 0), Some(Color::BLUE));
+        assert_eq!(at(1, 1), Some(Color::RED));
+        assert_eq!(at(2, 1), Some(Color::BLUE));
+
+        // Outside the hexes.
+        assert_eq!(canvas.pixel(0, 0), Some(Color::WHITE));
+
+        // The view doesn't crop it.
+        let mut renderer = renderer;
+        renderer.set_view(Some(Rectangle::new(0.0, 0.0, 1.0, 1.0)));
+        assert_eq!(renderer.rasterize(&hex_grid, 2.0).pixels, canvas.pixels);
+    }
+
+    #[test]
+    fn test_write_png() {
+        let canvas = Canvas::new(3, 2, Color::RED);
+        let mut data = Vec::new();
+        canvas.write_png(&mut data).unwrap();
+        assert_eq!(
+            data[..8],
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n']
+        );
+        assert_eq!(data[16..24], [0, 0, 0, 3, 0, 0, 0, 2]);
+    }
+}
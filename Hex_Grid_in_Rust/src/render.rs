@@ -1,29 +1,148 @@
+use crate::backend::*;
 use crate::hex_grid::*;
+use crate::hex_map::HexMap;
+use crate::layers::{HexLayer, Orientation};
 
 use raylib::prelude::*;
 
 use std::time::{SystemTime, UNIX_EPOCH};
 
+// The colors of the heatmap overlay at the lowest and the highest values.
+const HEAT_LOW: Color = Color::YELLOW;
+const HEAT_HIGH: Color = Color::DARKPURPLE;
+
 // Not just a renderer. Also a picker.
+//
+// Draws through a `DrawBackend`; wrap raylib's drawing handles in a
+// `RaylibBackend` to draw on screen.
+#[derive(Clone)]
 pub struct HexGridRenderer {
     hex_size: f32,
+
+    /// Which way the hexes are turned. Must match the grids being drawn.
+    orientation: Orientation,
+
+    /// How many extra columns of hexes to draw past the east and west edges of
+    /// grids that wrap around horizontally.
+    ghost_columns: i32,
+
+    /// The area that is actually visible, if known. Nothing outside of it gets
+    /// drawn.
+    view: Option<Rectangle>,
 }
 
-impl<'a> HexGridRenderer {
+impl HexGridRenderer {
     pub fn new(hex_size: f32) -> Self {
-        Self { hex_size }
+        Self {
+            hex_size,
+            orientation: Orientation::PointyTop,
+            ghost_columns: 0,
+            view: None,
+        }
+    }
+
+    /// Sets which way the hexes are turned, which must be the same as for the
+    /// grids drawn (see `HexGrid::orientation()`). Everything, picking
+    /// included, follows it.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
+    /// Sets how many "ghost" columns of hexes to draw past the east and west
+    /// edges of grids that wrap around horizontally. These show (faded) the
+    /// hexes from the other side of the grid, making the seam visible.
+    pub fn set_ghost_columns(&mut self, ghost_columns: i32) {
+        self.ghost_columns = ghost_columns;
+    }
+
+    /// Sets the area (in the same coordinates everything is drawn in) that is
+    /// actually visible, so that drawing can skip everything outside of it.
+    /// With `None`, everything is drawn.
+    ///
+    /// See `camera_view()` to get the area visible through a camera.
+    pub fn set_view(&mut self, view: Option<Rectangle>) {
+        self.view = view;
     }
 
     // I don't like that here we are computing the coords manually. This is
     // client code...
-    pub fn draw<D: RaylibDraw>(&self, d: &mut D, hex_grid: &HexGrid) {
-        for (q, r) in hex_grid.axial_coords() {
-            self.draw_hex(d, hex_grid, q, r);
+    pub fn draw<B: DrawBackend, M: HexMap>(&self, d: &mut B, hex_grid: &M) {
+        self.draw_with_heatmap(d, hex_grid, None);
+    }
+
+    /// Like `draw()`, but drawing the whole grid whatever the view, for
+    /// exports.
+    pub fn draw_all<B: DrawBackend, M: HexMap>(&self, d: &mut B, hex_grid: &M) {
+        let mut renderer = self.clone();
+        renderer.set_view(None);
+        renderer.draw(d, hex_grid);
+    }
+
+    /// Like `draw()`, but coloring each hex by its value in `field` (such as a
+    /// `pathfinding::distance_field()`), with a gradient from the lowest value
+    /// to the highest. Hexes without a value keep their own color. With
+    /// `labels`, the values are drawn in place of the usual labels.
+    pub fn draw_heatmap<B: DrawBackend, M: HexMap>(
+        &self,
+        d: &mut B,
+        hex_grid: &M,
+        field: &HexLayer<Option<u32>>,
+        labels: bool,
+    ) {
+        let max = field
+            .as_slice()
+            .iter()
+            .flatten()
+            .copied()
+            .max()
+            .unwrap_or(0);
+        let heatmap = Heatmap { field, max, labels };
+        self.draw_with_heatmap(d, hex_grid, Some(&heatmap));
+    }
+
+    fn draw_with_heatmap<B: DrawBackend, M: HexMap>(
+        &self,
+        d: &mut B,
+        hex_grid: &M,
+        heatmap: Option<&Heatmap>,
+    ) {
+        debug_assert_eq!(
+            hex_grid.orientation(),
+            self.orientation,
+            "the renderer is turned the wrong way"
+        );
+
+        for (q, r) in self.visible_hexes(hex_grid) {
+            self.draw_hex(d, hex_grid, heatmap, q, r);
+        }
+
+        let ghosts = self.ghost_coords(hex_grid);
+        for &(q, r) in &ghosts {
+            self.draw_hex(d, hex_grid, heatmap, q, r);
+            self.tint_hex(d, q, r, Color::WHITE.fade(0.5));
         }
 
-        for (q, r) in hex_grid.axial_coords_ext() {
+        for (q, r) in self.coords_in_view(hex_grid, true) {
             self.draw_extras(d, hex_grid, q, r);
         }
+
+        for &(q, r) in &ghosts {
+            self.draw_extras(d, hex_grid, q, r);
+        }
+    }
+
+    /// Iterates over the axial coordinates of the hexes in `hex_grid` that are
+    /// (at least partially) in view, in the same order as `axial_coords()`.
+    /// See `set_view()`.
+    ///
+    /// Only the hexes in view are visited, so this is cheap even for huge
+    /// grids.
+    pub fn visible_hexes<'m, M: HexMap>(
+        &self,
+        hex_grid: &'m M,
+    ) -> impl Iterator<Item = (i32, i32)> + 'm {
+        self.coords_in_view(hex_grid, false)
+            .filter(|&(q, r)| hex_grid.are_coords_valid(q, r))
     }
 
     /// Returns the axial coordinates of the hex that is under the given
@@ -35,6 +154,15 @@ impl<'a> HexGridRenderer {
     pub fn hex_coords_at_pos(&self, p: Vector2) -> (i32, i32) {
         let sqrt3 = 3.0f32.sqrt();
 
+        // Flat-top hexes are pointy-top ones turned 30 degrees clockwise, so
+        // turn the position back.
+        let p = match self.orientation {
+            Orientation::PointyTop => p,
+            Orientation::FlatTop => {
+                Vector2::new(p.x * sqrt3 / 2.0 + p.y / 2.0, p.y * sqrt3 / 2.0 - p.x / 2.0)
+            }
+        };
+
         // Convert to a Cartesian coordinate system in which (0,0) is the center
         // of the hex at axial coordinates (0,0), and each unit in size is equal
         // to the hex size.
@@ -54,13 +182,11 @@ impl<'a> HexGridRenderer {
     }
 
     /// Returns the axial coordinates of the hex that is under the given
-    /// position, plus the indices of the wall closest to that same position.
-    /// These indices can be passed to `self.hex_corner_position()` to obtain
-    /// the wall coordinates. The indices are sorted so that the wall segment is
-    /// drawn in the clockwise direction.
+    /// position, plus the direction of the wall of that hex closest to that
+    /// same position.
     ///
     /// AKA wall-picking.
-    pub fn wall_at_pos(&self, p: Vector2) -> (i32, i32, u8, u8) {
+    pub fn wall_at_pos(&self, p: Vector2) -> (i32, i32, Direction) {
         let (q, r) = self.hex_coords_at_pos(p);
         let center = self.hex_center(q, r);
 
@@ -83,86 +209,296 @@ impl<'a> HexGridRenderer {
             }
         }
 
-        if closest_1 == 0 && closest_2 == 5 {
-            (q, r, 5, 0)
-        } else if closest_1 > closest_2 {
-            (q, r, closest_2, closest_1)
+        // The wall between corners `i` and `i + 1` is in the `i`-th direction.
+        let first_corner = if closest_1.min(closest_2) == 0 && closest_1.max(closest_2) == 5 {
+            5
         } else {
-            (q, r, closest_1, closest_2)
-        }
+            closest_1.min(closest_2)
+        };
+
+        (q, r, Direction::ALL[first_corner as usize])
     }
 
-    /// Highlights the hex at the given axial coordinates.
-    pub fn highlight_hex<D: RaylibDraw>(&self, d: &mut D, q: i32, r: i32) {
+    /// Returns the axial coordinates of the hex that is under the given
+    /// position, plus the index of the corner of that hex closest to that same
+    /// position. See `HexGrid::normalize_vertex()`.
+    ///
+    /// AKA vertex-picking.
+    pub fn vertex_at_pos(&self, p: Vector2) -> Vertex {
+        let (q, r) = self.hex_coords_at_pos(p);
         let center = self.hex_center(q, r);
+        let corner = (0..6)
+            .min_by(|&a, &b| {
+                let dist_a = p.distance_to(self.hex_corner_position(center, a));
+                let dist_b = p.distance_to(self.hex_corner_position(center, b));
+                dist_a.total_cmp(&dist_b)
+            })
+            .unwrap();
+        (q, r, corner)
+    }
 
-        let magenta = Color::MAGENTA.color_to_hsv();
-        let cyan = Color::CYAN.color_to_hsv();
-        let target_hue = magenta.lerp(cyan, get_pulse(10.0)).x;
-        let color = Color::color_from_hsv(target_hue, 1.0, 1.0);
-        let hex_radius = self.hex_height() / 2.0;
+    /// Highlights the hex at the given axial coordinates.
+    pub fn highlight_hex<B: DrawBackend>(&self, d: &mut B, q: i32, r: i32) {
+        let center = self.hex_center(q, r);
+        let color = highlight_color();
+        let hex_radius = self.hex_size();
         let highlight_radius = hex_radius + (hex_radius * 0.2 * get_pulse(5.0));
-        d.draw_poly_lines(center, 6, highlight_radius, 0.0, color);
+        d.stroke_polygon(&self.hex_corners(center, highlight_radius), 1.0, color);
     }
 
-    /// Highlights a wall of a hex. It will be from the hex at the given axial
-    /// coordinates. And the wall will be the one from vertex `v1` to vertex
-    /// `v2`.
-    pub fn highlight_wall<D: RaylibDraw>(&self, d: &mut D, q: i32, r: i32, v1: u8, v2: u8) {
+    /// Outlines the hex at the given axial coordinates, like `highlight_hex()`,
+    /// but in the given color, and a bit inside the hex, so that outlining a
+    /// bunch of hexes marks out an area.
+    pub fn outline_hex<B: DrawBackend>(&self, d: &mut B, q: i32, r: i32, color: Color) {
+        let center = self.hex_center(q, r);
+        d.stroke_polygon(
+            &self.hex_corners(center, self.hex_size() * 0.85),
+            2.0,
+            color,
+        );
+    }
+
+    /// Highlights the wall of the hex at the given axial coordinates in the
+    /// given direction.
+    pub fn highlight_wall<B: DrawBackend>(&self, d: &mut B, q: i32, r: i32, dir: Direction) {
+        let (v1, v2) = dir.corners();
         let v1_pos = self.hex_corner_position(self.hex_center(q, r), v1);
         let v2_pos = self.hex_corner_position(self.hex_center(q, r), v2);
 
         let thickness = 7.0 + 5.0 * get_pulse(5.0);
+        let color = highlight_color().fade(0.5);
+
+        d.draw_line(v1_pos, v2_pos, thickness, color);
+    }
+
+    /// Draws a marker over the vertex at the given corner of the hex at the
+    /// given axial coordinates, for whatever is there: a settlement, a road
+    /// junction, the source of a river...
+    pub fn draw_vertex<B: DrawBackend>(&self, d: &mut B, q: i32, r: i32, corner: u8, color: Color) {
+        let position = self.hex_corner_position(self.hex_center(q, r), corner);
+        d.fill_circle(position, self.hex_size() / 5.0, color);
+        d.fill_circle(position, self.hex_size() / 10.0, Color::BLACK);
+    }
 
-        let magenta = Color::MAGENTA.color_to_hsv();
-        let cyan = Color::CYAN.color_to_hsv();
-        let target_hue = magenta.lerp(cyan, get_pulse(10.0)).x;
-        let color = Color::color_from_hsv(target_hue, 1.0, 1.0).fade(0.5);
+    /// Highlights the vertex at the given corner of the hex at the given axial
+    /// coordinates.
+    pub fn highlight_vertex<B: DrawBackend>(&self, d: &mut B, q: i32, r: i32, corner: u8) {
+        let position = self.hex_corner_position(self.hex_center(q, r), corner);
+        let radius = self.hex_size() * (0.15 + 0.1 * get_pulse(5.0));
+        d.fill_circle(position, radius, highlight_color().fade(0.8));
+    }
 
-        d.draw_line_ex(v1_pos, v2_pos, thickness, color);
+    /// Draws the hex at the given axial coordinates over with `color`. Use a
+    /// translucent color to tint it.
+    pub fn tint_hex<B: DrawBackend>(&self, d: &mut B, q: i32, r: i32, color: Color) {
+        let center = self.hex_center(q, r);
+        d.fill_polygon(&self.hex_corners(center, self.hex_size()), color);
+    }
+
+    /// Draws a path through the hexes at the given axial coordinates, like the
+    /// ones returned by `pathfinding::find_path()`.
+    ///
+    /// Paths crossing the seam of grids that wrap around horizontally are drawn
+    /// continuously, going past the edge of the grid.
+    pub fn draw_path<B: DrawBackend, M: HexMap>(
+        &self,
+        d: &mut B,
+        hex_grid: &M,
+        hexes: &[(i32, i32)],
+    ) {
+        let Some(&first) = hexes.first() else {
+            return;
+        };
+
+        let radius = self.hex_size() / 5.0;
+        let mut prev = first;
+
+        for &hex in &hexes[1..] {
+            let hex = hex_grid.unwrap_towards(prev, hex);
+            let start = self.hex_center(prev.0, prev.1);
+            let end = self.hex_center(hex.0, hex.1);
+            d.draw_line(start, end, 5.0, Color::RED);
+            prev = hex;
+        }
+
+        d.fill_circle(self.hex_center(first.0, first.1), radius, Color::RED);
+        d.fill_circle(self.hex_center(prev.0, prev.1), radius, Color::MAROON);
     }
 
     //
     // Rendering helpers
     //
 
-    fn draw_hex<D: RaylibDraw>(&self, d: &mut D, hex_grid: &HexGrid, q: i32, r: i32) {
-        let center = self.hex_center(q, r);
-        let color = hex_grid.hex_color(q, r).unwrap_or(Color::MAGENTA);
-        let int = hex_grid.hex_int(q, r).unwrap_or(-1);
-
-        let radius = self.hex_height() / 2.0;
-
-        d.draw_poly(center, 6, radius, 0.0, color);
-        d.draw_poly_lines(center, 6, radius, 0.0, Color::DARKGRAY);
-        d.draw_text(
-            format!("{}", int).as_str(),
-            center.x as i32,
-            center.y as i32,
-            20,
-            Color::BLACK,
-        );
+    /// The (non-canonical) axial coordinates of the ghost hexes to draw for
+    /// `hex_grid`. See `set_ghost_columns()`.
+    fn ghost_coords<M: HexMap>(&self, hex_grid: &M) -> Vec<(i32, i32)> {
+        let mut ghosts = Vec::new();
+
+        // Hexes past the ends of each row that still are in the grid are the
+        // ones from the other side.
+        let n = self.ghost_columns;
+        for r in hex_grid.rows(false) {
+            let span = hex_grid.row_span(r, false);
+            for x in (span.start - n..span.start).chain(span.end..span.end + n) {
+                let (q, r) = hex_grid.row_hex(r, x);
+                if hex_grid.are_coords_valid(q, r) && self.is_in_view(self.hex_center(q, r)) {
+                    ghosts.push((q, r));
+                }
+            }
+        }
+
+        ghosts
+    }
+
+    /// Like `axial_coords()` (or `axial_coords_ext()`, with `ext`), but
+    /// skipping the hexes out of view. Rather than checking every hex, this
+    /// works out which rows and columns can be in view.
+    fn coords_in_view<'m, M: HexMap>(
+        &self,
+        hex_grid: &'m M,
+        ext: bool,
+    ) -> Box<dyn Iterator<Item = (i32, i32)> + 'm> {
+        let Some(view) = self.view else {
+            return if ext {
+                Box::new(hex_grid.axial_coords_ext())
+            } else {
+                Box::new(hex_grid.axial_coords())
+            };
+        };
+
+        // Rows, and on each row, the hexes whose centers are close enough to
+        // the view horizontally (going by `hex_center()`). Rows of flat-top
+        // grids are columns, so for them, mirror the view along the diagonal.
+        let view = match self.orientation {
+            Orientation::PointyTop => view,
+            Orientation::FlatTop => Rectangle::new(view.y, view.x, view.height, view.width),
+        };
+        let rows = hex_grid.rows(ext);
+        let margin = self.view_margin();
+        let (hd, vd) = self.row_spacing();
+        let r0 = (((view.y - margin) / vd).floor() as i32).max(rows.start);
+        let r1 = (((view.y + view.height + margin) / vd).ceil() as i32 + 1).min(rows.end);
+        let x0 = (view.x - margin) / hd;
+        let x1 = (view.x + view.width + margin) / hd;
+
+        Box::new((r0..r1.max(r0)).flat_map(move |r| {
+            let span = hex_grid.row_span(r, ext);
+            let shift = r as f32 / 2.0;
+            let q0 = ((x0 - shift).floor() as i32).max(span.start);
+            let q1 = ((x1 - shift).ceil() as i32 + 1).min(span.end);
+            (q0..q1.max(q0)).map(move |q| hex_grid.row_hex(r, q))
+        }))
+    }
+
+    /// Whether anything drawn for a hex centered at `center` could be in view.
+    fn is_in_view(&self, center: Vector2) -> bool {
+        let Some(view) = self.view else {
+            return true;
+        };
+        let margin = self.view_margin();
+        center.x >= view.x - margin
+            && center.x <= view.x + view.width + margin
+            && center.y >= view.y - margin
+            && center.y <= view.y + view.height + margin
+    }
+
+    /// How far from its center anything drawn for a hex can go: the hex itself,
+    /// its walls and its highlight all stay well within two hex sizes.
+    fn view_margin(&self) -> f32 {
+        2.0 * self.hex_size()
     }
 
-    fn draw_extras<D: RaylibDraw>(&self, d: &mut D, hex_grid: &HexGrid, q: i32, r: i32) {
+    /// Draws the hex at (`q`, `r`), if it is in the map, labeled with its
+    /// `hex_int()` if it has one.
+    fn draw_hex<B: DrawBackend, M: HexMap>(
+        &self,
+        d: &mut B,
+        hex_grid: &M,
+        heatmap: Option<&Heatmap>,
+        q: i32,
+        r: i32,
+    ) {
+        let Some(mut color) = hex_grid.hex_color(q, r) else {
+            return;
+        };
+        let mut label = hex_grid.hex_int(q, r);
+        if let Some(heatmap) = heatmap {
+            let value = heatmap.field.get(q, r).copied().flatten();
+            if let Some(value) = value {
+                color = heat_color(value, heatmap.max);
+            }
+            if heatmap.labels {
+                label = value.map(|value| value as i32);
+            }
+        }
+
         let center = self.hex_center(q, r);
+        let corners = self.hex_corners(center, self.hex_size());
 
-        if let Some(color) = hex_grid.w_wall(q, r) {
-            let start = self.hex_corner_position(center, 3);
-            let end = self.hex_corner_position(center, 4);
-            d.draw_line_ex(start, end, 6.0, color);
+        d.fill_polygon(&corners, color);
+        d.stroke_polygon(&corners, 1.0, Color::DARKGRAY);
+        if let Some(label) = label {
+            d.draw_text(format!("{}", label).as_str(), center, 20, Color::BLACK);
         }
+    }
+
+    fn draw_extras<B: DrawBackend, M: HexMap>(&self, d: &mut B, hex_grid: &M, q: i32, r: i32) {
+        let center = self.hex_center(q, r);
 
-        if let Some(color) = hex_grid.nw_wall(q, r) {
-            let start = self.hex_corner_position(center, 4);
-            let end = self.hex_corner_position(center, 5);
-            d.draw_line_ex(start, end, 6.0, color);
+        // Each hex owns three walls, so drawing them for every hex (including
+        // the extra ones) covers all walls.
+        for dir in hex_grid.owned_directions() {
+            if let Some(color) = hex_grid.wall(q, r, dir) {
+                let (v1, v2) = dir.corners();
+                let start = self.hex_corner_position(center, v1);
+                let end = self.hex_corner_position(center, v2);
+                let kind = hex_grid.wall_kind(q, r, dir).unwrap_or_default();
+                self.draw_wall(d, start, end, kind, color);
+            }
         }
+    }
 
-        if let Some(color) = hex_grid.ne_wall(q, r) {
-            let start = self.hex_corner_position(center, 5);
-            let end = self.hex_corner_position(center, 0);
-            d.draw_line_ex(start, end, 6.0, color);
+    /// Draws a wall from `start` to `end`, in the style of its kind: solid
+    /// walls are a thick line, doors leave a gap in the middle (filled with a
+    /// lock when locked), windows are a faded line with a thin one on top, and
+    /// one-way passages leave a gap with an arrow pointing their way.
+    fn draw_wall<B: DrawBackend>(
+        &self,
+        d: &mut B,
+        start: Vector2,
+        end: Vector2,
+        kind: WallKind,
+        color: Color,
+    ) {
+        let middle = start.lerp(end, 0.5);
+        let draw_jambs = |d: &mut B| {
+            d.draw_line(start, start.lerp(end, 0.25), 6.0, color);
+            d.draw_line(end.lerp(start, 0.25), end, 6.0, color);
+        };
+
+        match kind {
+            WallKind::Solid => d.draw_line(start, end, 6.0, color),
+            WallKind::OpenDoor => draw_jambs(d),
+            WallKind::LockedDoor => {
+                d.draw_line(start, end, 6.0, color);
+                d.fill_circle(middle, self.hex_size() / 6.0, color);
+                d.fill_circle(middle, self.hex_size() / 12.0, Color::BLACK);
+            }
+            WallKind::Window => {
+                d.draw_line(start, end, 6.0, color.fade(0.4));
+                d.draw_line(start, end, 2.0, color);
+            }
+            WallKind::OneWay(dir) => {
+                draw_jambs(d);
+
+                // A triangle across the gap, pointing the way to go.
+                let (dq, dr) = dir.offset();
+                let way = (self.hex_center(dq, dr) - self.hex_center(0, 0)).normalized();
+                let side = (end - start) * 0.2;
+                let tip = middle + way * (self.hex_size() / 4.0);
+                let back = middle - way * (self.hex_size() / 8.0);
+                d.fill_polygon(&[tip, back + side, back - side], color);
+            }
         }
     }
 
@@ -174,33 +510,108 @@ impl<'a> HexGridRenderer {
         self.hex_size
     }
 
-    fn hex_width(&self) -> f32 {
-        self.hex_size * 3.0f32.sqrt()
+    /// The distance between the centers of neighboring hexes in the same row,
+    /// and between rows (see `GridLayout::rows()`). Rows of flat-top grids are
+    /// columns, so these go the other way for them.
+    fn row_spacing(&self) -> (f32, f32) {
+        (self.hex_size * 3.0f32.sqrt(), self.hex_size * 3.0 / 2.0)
     }
 
-    fn hex_height(&self) -> f32 {
-        self.hex_size * 2.0
+    pub(crate) fn hex_center(&self, q: i32, r: i32) -> Vector2 {
+        let (along, across) = self.row_spacing();
+        match self.orientation {
+            Orientation::PointyTop => {
+                Vector2::new(along * (q as f32 + r as f32 / 2.0), across * r as f32)
+            }
+            Orientation::FlatTop => {
+                Vector2::new(across * q as f32, along * (r as f32 + q as f32 / 2.0))
+            }
+        }
     }
 
-    fn horizontal_distance(&self) -> f32 {
-        self.hex_width()
+    pub(crate) fn hex_corner_position(&self, center: Vector2, i: u8) -> Vector2 {
+        let angle = self.corner_angle(i as usize);
+        let size = self.hex_size();
+        center + Vector2::new(size * angle.cos(), size * angle.sin())
     }
 
-    fn vertical_distance(&self) -> f32 {
-        self.hex_size * 3.0 / 2.0
+    /// Like `hex_corner_position()`, but for all corners of a hex of any
+    /// size, as a polygon.
+    fn hex_corners(&self, center: Vector2, radius: f32) -> [Vector2; 6] {
+        std::array::from_fn(|i| {
+            let angle = self.corner_angle(i);
+            center + Vector2::new(radius * angle.cos(), radius * angle.sin())
+        })
     }
 
-    fn hex_center(&self, q: i32, r: i32) -> Vector2 {
-        let x = self.horizontal_distance() * (q as f32 + r as f32 / 2.0);
-        let y = self.vertical_distance() * r as f32;
-        Vector2::new(x, y)
+    /// The angle from the center of a hex to its `i`-th corner, in radians,
+    /// clockwise from the east.
+    fn corner_angle(&self, i: usize) -> f32 {
+        let offset = match self.orientation {
+            Orientation::PointyTop => -30.0,
+            Orientation::FlatTop => 0.0,
+        };
+        (60.0 * i as f32 + offset).to_radians()
     }
+}
 
-    fn hex_corner_position(&self, center: Vector2, i: u8) -> Vector2 {
-        let angle = (60.0 * i as f32 - 30.0).to_radians();
-        let size = self.hex_size();
-        center + Vector2::new(size * angle.cos(), size * angle.sin())
-    }
+/// Converts a position on the screen to the position it shows through
+/// `camera`: the inverse of what drawing in `begin_mode2D()` does. Use it to
+/// find out what is under the mouse, for `hex_coords_at_pos()` and
+/// `wall_at_pos()`.
+pub fn screen_to_world(camera: &Camera2D, p: Vector2) -> Vector2 {
+    let (sin, cos) = (-camera.rotation).to_radians().sin_cos();
+    let v = p - camera.offset;
+    let rotated = Vector2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos);
+    rotated / camera.zoom + camera.target
+}
+
+/// Returns the area visible through `camera` on a screen of the given size
+/// (or, if the camera is rotated, a box around it), for `set_view()`.
+pub fn camera_view(camera: &Camera2D, width: f32, height: f32) -> Rectangle {
+    let corners = [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)]
+        .map(|(x, y)| screen_to_world(camera, Vector2::new(x, y)));
+
+    let min_x = corners.iter().map(|p| p.x).fold(f32::MAX, f32::min);
+    let min_y = corners.iter().map(|p| p.y).fold(f32::MAX, f32::min);
+    let max_x = corners.iter().map(|p| p.x).fold(f32::MIN, f32::max);
+    let max_y = corners.iter().map(|p| p.y).fold(f32::MIN, f32::max);
+    Rectangle::new(min_x, min_y, max_x - min_x, max_y - min_y)
+}
+
+/// A per-hex layer of values to color hexes by, for `draw_heatmap()`.
+struct Heatmap<'a> {
+    field: &'a HexLayer<Option<u32>>,
+
+    /// The highest value in `field`.
+    max: u32,
+
+    /// Whether to draw the values in place of the usual labels.
+    labels: bool,
+}
+
+/// The color of `value` in a heatmap going from 0 to `max`.
+fn heat_color(value: u32, max: u32) -> Color {
+    let t = if max == 0 {
+        0.0
+    } else {
+        value as f32 / max as f32
+    };
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    Color::new(
+        mix(HEAT_LOW.r, HEAT_HIGH.r),
+        mix(HEAT_LOW.g, HEAT_HIGH.g),
+        mix(HEAT_LOW.b, HEAT_HIGH.b),
+        255,
+    )
+}
+
+// The color of highlights, cycling between magenta and cyan.
+fn highlight_color() -> Color {
+    let magenta = Color::MAGENTA.color_to_hsv();
+    let cyan = Color::CYAN.color_to_hsv();
+    let target_hue = magenta.lerp(cyan, get_pulse(10.0)).x;
+    Color::color_from_hsv(target_hue, 1.0, 1.0)
 }
 
 // Value between 0.0 and 1.0, pulsating, with time multiplier s.
@@ -212,3 +623,363 @@ fn get_pulse(s: f64) -> f32 {
     };
     ((t.sin() + 1.0) / 2.0) as f32
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::MapShape;
+
+    #[test]
+    fn test_draw() {
+        let renderer = HexGridRenderer::new(10.0);
+        let mut hex_grid = HexGrid::new(3, 2);
+        hex_grid.set_hex_color(1, 0, Color::RED);
+        hex_grid.set_wall(1, 0, Direction::East, Some(Color::BLACK));
+
+        let mut recording = RecordingBackend::new();
+        renderer.draw(&mut recording, &hex_grid);
+
+        // Each hex is filled, outlined and labeled, and then come the walls.
+        let calls = &recording.calls;
+        assert_eq!(calls.len(), 3 * 6 + 1);
+
+        let center = renderer.hex_center(1, 0);
+        let corners = renderer.hex_corners(center, 10.0).to_vec();
+        assert_eq!(
+            calls[3],
+            DrawCall::FillPolygon {
+                points: corners.clone(),
+                color: Color::RED
+            }
+        );
+        assert_eq!(
+            calls[4],
+            DrawCall::StrokePolygon {
+                points: corners,
+                thickness: 1.0,
+                color: Color::DARKGRAY
+            }
+        );
+        assert!(matches!(&calls[5], DrawCall::Text { text, .. } if text == "1"));
+
+        // The east wall of (1, 0) is drawn as the west wall of (2, 0).
+        let center = renderer.hex_center(2, 0);
+        assert_eq!(
+            calls[18],
+            DrawCall::Line {
+                start: renderer.hex_corner_position(center, 3),
+                end: renderer.hex_corner_position(center, 4),
+                thickness: 6.0,
+                color: Color::BLACK
+            }
+        );
+    }
+
+    #[test]
+    fn test_draw_heatmap() {
+        let renderer = HexGridRenderer::new(10.0);
+        let hex_grid = HexGrid::new(3, 1);
+        let mut field = HexLayer::new(hex_grid.layout(), None);
+        field.set(0, 0, Some(0));
+        field.set(1, 0, Some(4));
+
+        let fills_and_labels = |labels| {
+            let mut recording = RecordingBackend::new();
+            renderer.draw_heatmap(&mut recording, &hex_grid, &field, labels);
+            let mut fills = Vec::new();
+            let mut texts = Vec::new();
+            for call in recording.calls {
+                match call {
+                    DrawCall::FillPolygon { color, .. } => fills.push(color),
+                    DrawCall::Text { text, .. } => texts.push(text),
+                    _ => {}
+                }
+            }
+            (fills, texts)
+        };
+
+        // From one end of the gradient to the other, and hexes without a value
+        // keep their color.
+        let (fills, texts) = fills_and_labels(false);
+        assert_eq!(fills, vec![HEAT_LOW, HEAT_HIGH, Color::MAGENTA]);
+        assert_eq!(texts, vec!["0", "1", "2"]);
+
+        // With labels, the values replace the hex ints.
+        let (fills, texts) = fills_and_labels(true);
+        assert_eq!(fills, vec![HEAT_LOW, HEAT_HIGH, Color::MAGENTA]);
+        assert_eq!(texts, vec!["0", "4"]);
+
+        assert_eq!(heat_color(7, 0), HEAT_LOW);
+        let middle = heat_color(1, 2);
+        assert!(middle != HEAT_LOW && middle != HEAT_HIGH);
+    }
+
+    #[test]
+    fn test_draw_wall_kinds() {
+        let renderer = HexGridRenderer::new(10.0);
+        let mut hex_grid = HexGrid::new(1, 1);
+        hex_grid.set_wall(0, 0, Direction::West, Some(Color::BLACK));
+
+        let wall_calls = |hex_grid: &HexGrid| {
+            let mut recording = RecordingBackend::new();
+            renderer.draw(&mut recording, hex_grid);
+            recording.calls.split_off(3)
+        };
+        let is_line = |call: &DrawCall| matches!(call, DrawCall::Line { .. });
+
+        let set_kind = |hex_grid: &mut HexGrid, kind| {
+            hex_grid.set_wall_kind(0, 0, Direction::West, kind);
+        };
+        assert_eq!(wall_calls(&hex_grid).len(), 1);
+
+        // Doors leave a gap, but their jambs still reach the corners.
+        set_kind(&mut hex_grid, WallKind::OpenDoor);
+        let calls = wall_calls(&hex_grid);
+        assert_eq!(calls.len(), 2);
+        assert!(calls.iter().all(is_line));
+        let center = renderer.hex_center(0, 0);
+        assert!(matches!(calls[0], DrawCall::Line { start, .. }
+            if start == renderer.hex_corner_position(center, 3)));
+
+        set_kind(&mut hex_grid, WallKind::LockedDoor);
+        let calls = wall_calls(&hex_grid);
+        assert_eq!(calls.len(), 3);
+        assert!(matches!(calls[1], DrawCall::Circle { .. }));
+
+        set_kind(&mut hex_grid, WallKind::Window);
+        let calls = wall_calls(&hex_grid);
+        assert_eq!(calls.len(), 2);
+        assert!(matches!(calls[1], DrawCall::Line { thickness, .. } if thickness < 6.0));
+
+        // The arrow points the way to go, here west.
+        set_kind(&mut hex_grid, WallKind::OneWay(Direction::West));
+        let calls = wall_calls(&hex_grid);
+        assert_eq!(calls.len(), 3);
+        let DrawCall::FillPolygon { points, .. } = &calls[2] else {
+            panic!("no arrow: {:?}", calls);
+        };
+        assert!(points[0].x < points[1].x && points[0].x < points[2].x);
+    }
+
+    #[test]
+    fn test_draw_vertex() {
+        let renderer = HexGridRenderer::new(10.0);
+        let mut recording = RecordingBackend::new();
+        renderer.draw_vertex(&mut recording, 1, 1, 5, Color::RED);
+
+        // Same place no matter which hex is used to name the vertex.
+        renderer.draw_vertex(&mut recording, 1, 0, 1, Color::RED);
+        let center = renderer.hex_center(1, 1) + Vector2::new(0.0, -10.0);
+        for call in recording.calls.iter().step_by(2) {
+            match call {
+                DrawCall::Circle { center: c, .. } => assert!(c.distance_to(center) < 1e-4),
+                call => panic!("unexpected {:?}", call),
+            }
+        }
+    }
+
+    #[test]
+    fn test_outline_hex() {
+        let renderer = HexGridRenderer::new(10.0);
+        let mut recording = RecordingBackend::new();
+        renderer.outline_hex(&mut recording, 2, 1, Color::RED);
+        let center = renderer.hex_center(2, 1);
+        match &recording.calls[..] {
+            [DrawCall::StrokePolygon { points, color, .. }] => {
+                assert_eq!(*color, Color::RED);
+                assert_eq!(points.len(), 6);
+                for p in points {
+                    assert!((p.distance_to(center) - 8.5).abs() < 1e-4);
+                }
+            }
+            calls => panic!("unexpected {:?}", calls),
+        }
+    }
+
+    #[test]
+    fn test_draw_path() {
+        let renderer = HexGridRenderer::new(10.0);
+        let hex_grid = HexGrid::new_wrapping(5, 1);
+
+        // Across the seam, the path goes on past the edge instead of jumping
+        // back across the whole grid.
+        let mut recording = RecordingBackend::new();
+        renderer.draw_path(&mut recording, &hex_grid, &[(0, 0), (4, 0)]);
+        assert_eq!(recording.calls.len(), 3);
+        match &recording.calls[0] {
+            DrawCall::Line { start, end, .. } => {
+                assert_eq!(*start, renderer.hex_center(0, 0));
+                assert_eq!(*end, renderer.hex_center(-1, 0));
+            }
+            call => panic!("unexpected {:?}", call),
+        }
+
+        let mut recording = RecordingBackend::new();
+        renderer.draw_path(&mut recording, &hex_grid, &[]);
+        assert!(recording.calls.is_empty());
+    }
+
+    #[test]
+    fn test_draw_culled() {
+        let mut renderer = HexGridRenderer::new(10.0);
+        let hex_grid = HexGrid::new(1000, 1000);
+
+        // Just around the center of (250, 500).
+        let center = renderer.hex_center(250, 500);
+        renderer.set_view(Some(Rectangle::new(
+            center.x - 1.0,
+            center.y - 1.0,
+            2.0,
+            2.0,
+        )));
+
+        let mut recording = RecordingBackend::new();
+        renderer.draw(&mut recording, &hex_grid);
+        let calls = &recording.calls;
+        assert!(calls.len() < 100);
+        let corners = renderer.hex_corners(center, 10.0).to_vec();
+        assert!(calls.contains(&DrawCall::FillPolygon {
+            points: corners,
+            color: Color::MAGENTA
+        }));
+
+        // Everything drawn is near the view.
+        let (min, max) = bounds(calls).unwrap();
+        assert!(center.distance_to(min) < 100.0);
+        assert!(center.distance_to(max) < 100.0);
+
+        // A view covering the whole grid draws everything, just like no view.
+        let hex_grid = HexGrid::new_wrapping(5, 4);
+        let mut everything = RecordingBackend::new();
+        renderer.set_view(None);
+        renderer.draw(&mut everything, &hex_grid);
+        let mut culled = RecordingBackend::new();
+        renderer.set_view(Some(Rectangle::new(-100.0, -100.0, 300.0, 300.0)));
+        renderer.draw(&mut culled, &hex_grid);
+        assert_eq!(culled.calls, everything.calls);
+        assert_eq!(
+            renderer.visible_hexes(&hex_grid).collect::<Vec<_>>(),
+            hex_grid.axial_coords().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            renderer.coords_in_view(&hex_grid, true).collect::<Vec<_>>(),
+            hex_grid.axial_coords_ext().collect::<Vec<_>>()
+        );
+
+        // Same with other shapes, some of them with negative coordinates.
+        let shapes = [
+            MapShape::Hexagon { radius: 3 },
+            MapShape::Rhombus {
+                width: 4,
+                height: 3,
+            },
+            MapShape::Triangle { size: 4 },
+        ];
+        for shape in shapes {
+            let hex_grid = HexGrid::with_shape(shape);
+            renderer.set_view(Some(Rectangle::new(-300.0, -300.0, 600.0, 600.0)));
+            assert_eq!(
+                renderer.coords_in_view(&hex_grid, true).collect::<Vec<_>>(),
+                hex_grid.axial_coords_ext().collect::<Vec<_>>()
+            );
+        }
+
+        // And flat-top, where rows are columns.
+        renderer.set_orientation(Orientation::FlatTop);
+        let shape = MapShape::Rectangle {
+            width: 30,
+            height: 20,
+        };
+        let hex_grid = HexGrid::with_orientation(shape, Orientation::FlatTop);
+        renderer.set_view(Some(Rectangle::new(-300.0, -300.0, 600.0, 600.0)));
+        let visible: Vec<_> = renderer.visible_hexes(&hex_grid).collect();
+        let expected: Vec<_> = hex_grid
+            .axial_coords()
+            .filter(|&(q, r)| renderer.is_in_view(renderer.hex_center(q, r)))
+            .collect();
+        assert!(expected.iter().all(|hex| visible.contains(hex)));
+        assert!(visible.len() < 2 * expected.len());
+        renderer.set_view(Some(Rectangle::new(-300.0, -300.0, 3000.0, 3000.0)));
+        assert_eq!(
+            renderer.coords_in_view(&hex_grid, true).collect::<Vec<_>>(),
+            hex_grid.axial_coords_ext().collect::<Vec<_>>()
+        );
+        renderer.set_orientation(Orientation::PointyTop);
+
+        // Nothing at all out there.
+        renderer.set_view(Some(Rectangle::new(5000.0, -5000.0, 100.0, 100.0)));
+        assert_eq!(renderer.visible_hexes(&hex_grid).count(), 0);
+    }
+
+    #[test]
+    fn test_screen_to_world() {
+        let camera = Camera2D {
+            offset: Vector2::new(100.0, 50.0),
+            target: Vector2::new(10.0, 20.0),
+            rotation: 0.0,
+            zoom: 2.0,
+        };
+        let p = screen_to_world(&camera, Vector2::new(120.0, 40.0));
+        assert_eq!(p, Vector2::new(20.0, 15.0));
+
+        let view = camera_view(&camera, 200.0, 100.0);
+        let view = (view.x, view.y, view.width, view.height);
+        assert_eq!(view, (-40.0, -5.0, 100.0, 50.0));
+
+        // A quarter turn clockwise: moving right on screen goes up in the
+        // world.
+        let camera = Camera2D {
+            rotation: 90.0,
+            zoom: 1.0,
+            ..camera
+        };
+        let p = screen_to_world(&camera, Vector2::new(110.0, 50.0));
+        assert!(p.distance_to(Vector2::new(10.0, 10.0)) < 1e-4);
+    }
+
+    #[test]
+    fn test_picking() {
+        let mut renderer = HexGridRenderer::new(10.0);
+        picking_round_trips(&renderer);
+
+        // Flat-top hexes have an edge on top, and their first corner to the
+        // east.
+        renderer.set_orientation(Orientation::FlatTop);
+        picking_round_trips(&renderer);
+        assert_eq!(
+            renderer.hex_center(2, 0),
+            Vector2::new(30.0, 3f32.sqrt() * 10.0)
+        );
+        let corner = renderer.hex_corner_position(Vector2::zero(), 0);
+        assert!(corner.distance_to(Vector2::new(10.0, 0.0)) < 1e-4);
+        assert_eq!(
+            renderer.hex_coords_at_pos(Vector2::new(0.0, -15.0)),
+            (0, -1)
+        );
+        let (_, _, dir) = renderer.wall_at_pos(Vector2::new(0.0, -8.0));
+        assert_eq!(dir, Direction::NorthWest);
+    }
+
+    fn picking_round_trips(renderer: &HexGridRenderer) {
+        for (q, r) in [(0, 0), (3, 2), (-1, 5)] {
+            let center = renderer.hex_center(q, r);
+            assert_eq!(renderer.hex_coords_at_pos(center), (q, r));
+
+            // Pick a point just inside the middle of each wall.
+            for dir in Direction::ALL {
+                let (v1, v2) = dir.corners();
+                let mid = (renderer.hex_corner_position(center, v1)
+                    + renderer.hex_corner_position(center, v2))
+                    * 0.5;
+                let p = center + (mid - center) * 0.9;
+                assert_eq!(renderer.wall_at_pos(p), (q, r, dir));
+            }
+
+            // And just inside each corner.
+            for corner in 0..6 {
+                let p = center + (renderer.hex_corner_position(center, corner) - center) * 0.9;
+                assert_eq!(renderer.vertex_at_pos(p), (q, r, corner));
+            }
+        }
+    }
+}
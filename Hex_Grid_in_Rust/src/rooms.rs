@@ -0,0 +1,170 @@
+use crate::hex_grid::*;
+use crate::hex_map::HexMap;
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+
+/// A room: a set of hexes connected to each other without crossing any wall.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Room {
+    /// The axial coordinates of the hexes in the room, in the same order
+    /// `HexGrid::axial_coords()` yields them.
+    pub hexes: Vec<(i32, i32)>,
+
+    /// The walls around the room, as `(q, r, dir)`: the wall of the room hex
+    /// at (`q`, `r`) in direction `dir`.
+    pub boundary_walls: Vec<(i32, i32, Direction)>,
+}
+
+impl Room {
+    /// Returns the number of hexes in the room.
+    pub fn area(&self) -> usize {
+        self.hexes.len()
+    }
+}
+
+/// All the rooms in a grid, as computed by `find_rooms()`.
+pub struct Rooms {
+    rooms: Vec<Room>,
+
+    /// The index (into `rooms`) of the room each hex belongs to.
+    room_of_hex: HashMap<(i32, i32), usize>,
+}
+
+impl Rooms {
+    /// Returns all the rooms, sorted by their first hex, in the same order
+    /// `HexGrid::axial_coords()` yields them.
+    pub fn rooms(&self) -> &[Room] {
+        &self.rooms
+    }
+
+    /// Returns the index (into `rooms()`) of the room containing the hex at the
+    /// given axial coordinates, or `None` if the coordinates are not valid.
+    pub fn room_index_at(&self, q: i32, r: i32) -> Option<usize> {
+        self.room_of_hex.get(&(q, r)).copied()
+    }
+
+    /// Returns the room containing the hex at the given axial coordinates, or
+    /// `None` if the coordinates are not valid.
+    pub fn room_at(&self, q: i32, r: i32) -> Option<&Room> {
+        self.room_index_at(q, r).map(|i| &self.rooms[i])
+    }
+}
+
+/// Splits the grid into rooms. Two neighboring hexes are in the same room
+/// unless there is a wall between them. (The edges of the grid also bound the
+/// rooms, of course, but only actual walls are listed in `boundary_walls`.)
+pub fn find_rooms<M: HexMap>(hex_grid: &M) -> Rooms {
+    let mut rooms = Vec::new();
+    let mut room_of_hex = HashMap::new();
+
+    for start in hex_grid.axial_coords() {
+        if room_of_hex.contains_key(&start) {
+            continue;
+        }
+
+        // Plain old breadth-first flood fill.
+        let room_index = rooms.len();
+        let mut hexes = Vec::new();
+        let mut boundary_walls = Vec::new();
+        let mut queue = VecDeque::new();
+        room_of_hex.insert(start, room_index);
+        queue.push_back(start);
+
+        while let Some((q, r)) = queue.pop_front() {
+            hexes.push((q, r));
+
+            for dir in Direction::ALL {
+                if hex_grid.wall(q, r, dir).is_some() {
+                    boundary_walls.push((q, r, dir));
+                } else if let Some(next) = hex_grid.neighbor(q, r, dir) {
+                    if let Entry::Vacant(entry) = room_of_hex.entry(next) {
+                        entry.insert(room_index);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        // The flood fill visits hexes in whatever order; make it predictable.
+        let order = |&(q, r): &(i32, i32)| (r, q);
+        hexes.sort_by_key(order);
+        boundary_walls.sort_by_key(|&(q, r, dir)| (order(&(q, r)), dir as usize));
+
+        rooms.push(Room {
+            hexes,
+            boundary_walls,
+        });
+    }
+
+    Rooms { rooms, room_of_hex }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raylib::prelude::Color;
+
+    #[test]
+    fn test_no_walls_is_one_room() {
+        let hex_grid = HexGrid::new(6, 4);
+        let rooms = find_rooms(&hex_grid);
+
+        assert_eq!(rooms.rooms().len(), 1);
+        let room = &rooms.rooms()[0];
+        assert_eq!(room.area(), 24);
+        assert!(room.boundary_walls.is_empty());
+        assert_eq!(room.hexes, hex_grid.axial_coords().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_walled_hex_is_its_own_room() {
+        let mut hex_grid = HexGrid::new(5, 5);
+        for dir in Direction::ALL {
+            hex_grid.set_wall(1, 2, dir, Some(Color::BLACK));
+        }
+
+        let rooms = find_rooms(&hex_grid);
+        assert_eq!(rooms.rooms().len(), 2);
+
+        let inner = rooms.room_at(1, 2).unwrap();
+        assert_eq!(inner.hexes, vec![(1, 2)]);
+        assert_eq!(
+            inner.boundary_walls,
+            Direction::ALL
+                .iter()
+                .map(|&dir| (1, 2, dir))
+                .collect::<Vec<_>>()
+        );
+
+        let outer = rooms.room_at(0, 0).unwrap();
+        assert_eq!(outer.area(), 24);
+        assert_eq!(outer.boundary_walls.len(), 6);
+        assert_ne!(rooms.room_index_at(1, 2), rooms.room_index_at(0, 0));
+        assert_eq!(rooms.room_index_at(2, 2), rooms.room_index_at(0, 0));
+    }
+
+    #[test]
+    fn test_wall_across_grid_splits_it() {
+        // A vertical zig-zag wall splitting a 4x2 grid in two 2x2 halves.
+        let mut hex_grid = HexGrid::new(4, 2);
+        hex_grid.set_wall(1, 0, Direction::East, Some(Color::BLACK));
+        hex_grid.set_wall(1, 1, Direction::East, Some(Color::BLACK));
+        hex_grid.set_wall(2, 0, Direction::SouthWest, Some(Color::BLACK));
+
+        let rooms = find_rooms(&hex_grid);
+        assert_eq!(rooms.rooms().len(), 2);
+        assert_eq!(rooms.rooms()[0].hexes, vec![(0, 0), (1, 0), (0, 1), (1, 1)]);
+        assert_eq!(rooms.rooms()[1].hexes, vec![(2, 0), (3, 0), (2, 1), (3, 1)]);
+        assert_eq!(rooms.rooms()[0].boundary_walls.len(), 3);
+        assert_eq!(rooms.rooms()[1].boundary_walls.len(), 3);
+    }
+
+    #[test]
+    fn test_room_at_out_of_bounds() {
+        let hex_grid = HexGrid::new(3, 3);
+        let rooms = find_rooms(&hex_grid);
+        assert!(rooms.room_at(-5, 0).is_none());
+        assert!(rooms.room_index_at(0, 3).is_none());
+    }
+}
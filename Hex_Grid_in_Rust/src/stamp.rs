@@ -0,0 +1,246 @@
+use crate::hex_grid::*;
+use crate::hex_map::HexMap;
+use raylib::prelude::Color;
+
+use std::collections::{HashMap, HashSet};
+
+/// A wall of a `Stamp`: its color and kind.
+pub type StampWall = (Color, WallKind);
+
+/// A region lifted out of a map, to be pasted elsewhere (on the same map or
+/// another one): the colors of its hexes, and the walls on every side of them.
+///
+/// Hexes are stored relative to an origin, in axial coordinates, so stamps can
+/// be turned and mirrored around it. Walls are stored as a hex of the stamp and
+/// a side of it (twice, for walls between two hexes of the stamp) rather than
+/// the way maps own them (see `HexMap::owned_directions()`): turning a stamp
+/// then just turns each side along with its hex, and pasting leaves it to the
+/// map to find which hex owns each wall.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stamp {
+    hexes: Vec<((i32, i32), Color)>,
+    walls: HashMap<((i32, i32), Direction), StampWall>,
+}
+
+impl Stamp {
+    /// Copies the given hexes of `map`, with all the walls around them, into a
+    /// stamp with `origin` as its origin. Hexes out of the map are skipped. On
+    /// maps that wrap around, hexes are taken the shortest way from `origin`.
+    pub fn copy<M: HexMap>(map: &M, hexes: &[(i32, i32)], origin: (i32, i32)) -> Self {
+        let mut stamp = Self {
+            hexes: Vec::new(),
+            walls: HashMap::new(),
+        };
+        let mut seen = HashSet::new();
+
+        for &(q, r) in hexes {
+            let Some(hex) = map.normalize(q, r) else {
+                continue;
+            };
+            if !seen.insert(hex) {
+                continue;
+            }
+
+            let (q, r) = map.unwrap_towards(origin, hex);
+            let offset = (q - origin.0, r - origin.1);
+            stamp.hexes.push((offset, map.hex_color(q, r).unwrap()));
+            for dir in Direction::ALL {
+                if let (Some(color), Some(kind)) = (map.wall(q, r, dir), map.wall_kind(q, r, dir)) {
+                    stamp.walls.insert((offset, dir), (color, kind));
+                }
+            }
+        }
+
+        stamp
+    }
+
+    /// Returns the hexes of this stamp when pasted with its origin at `at`,
+    /// with their colors, in the order they were copied.
+    pub fn hexes_at(&self, at: (i32, i32)) -> Vec<((i32, i32), Color)> {
+        self.hexes
+            .iter()
+            .map(|&((dq, dr), color)| ((at.0 + dq, at.1 + dr), color))
+            .collect()
+    }
+
+    /// Returns every side of every hex of this stamp when pasted with its
+    /// origin at `at`, as the coordinates of the hex and the direction of the
+    /// side, with the wall the stamp has there (if any).
+    pub fn walls_at(&self, at: (i32, i32)) -> Vec<(i32, i32, Direction, Option<StampWall>)> {
+        let mut walls = Vec::new();
+        for &(offset, _) in &self.hexes {
+            for dir in Direction::ALL {
+                let wall = self.walls.get(&(offset, dir)).copied();
+                walls.push((at.0 + offset.0, at.1 + offset.1, dir, wall));
+            }
+        }
+        walls
+    }
+
+    /// Pastes this stamp onto `map`, with its origin at `at`: paints its hexes,
+    /// and sets every side of them to the walls of the stamp, removing the
+    /// walls the stamp doesn't have. Whatever falls out of the map is skipped.
+    pub fn paste<M: HexMap>(&self, map: &mut M, at: (i32, i32)) {
+        for ((q, r), color) in self.hexes_at(at) {
+            map.set_hex_color(q, r, color);
+        }
+        for (q, r, dir, wall) in self.walls_at(at) {
+            map.set_wall(q, r, dir, wall.map(|(color, _)| color));
+            if let Some((_, kind)) = wall {
+                map.set_wall_kind(q, r, dir, kind);
+            }
+        }
+    }
+
+    /// Returns this stamp turned around its origin by `steps` times 60 degrees
+    /// clockwise (counter-clockwise, for negative `steps`).
+    pub fn rotated(&self, steps: i32) -> Self {
+        let mut stamp = self.clone();
+        for _ in 0..steps.rem_euclid(6) {
+            stamp = stamp.transformed(|(q, r)| (-r, q + r));
+        }
+        stamp
+    }
+
+    /// Returns this stamp mirrored across the east-west line through its
+    /// origin: north-east and south-east swap, and so do north-west and
+    /// south-west.
+    pub fn mirrored(&self) -> Self {
+        self.transformed(|(q, r)| (q + r, -r))
+    }
+
+    /// Returns this stamp with `transform`, a linear map of axial coordinates
+    /// taking neighbors to neighbors, applied to its hexes, to the sides of its
+    /// walls, and to the way of its one-way passages.
+    fn transformed<F>(&self, transform: F) -> Self
+    where
+        F: Fn((i32, i32)) -> (i32, i32),
+    {
+        let dir = |dir: Direction| {
+            let offset = transform(dir.offset());
+            *Direction::ALL
+                .iter()
+                .find(|d| d.offset() == offset)
+                .unwrap()
+        };
+
+        let hexes = self
+            .hexes
+            .iter()
+            .map(|&(offset, color)| (transform(offset), color))
+            .collect();
+        let walls = self
+            .walls
+            .iter()
+            .map(|(&(offset, side), &(color, kind))| {
+                let kind = match kind {
+                    WallKind::OneWay(way) => WallKind::OneWay(dir(way)),
+                    kind => kind,
+                };
+                ((transform(offset), dir(side)), (color, kind))
+            })
+            .collect();
+
+        Self { hexes, walls }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{MapShape, Orientation};
+
+    #[test]
+    fn test_copy_and_paste() {
+        let mut hex_grid = HexGrid::new(10, 10);
+        hex_grid.set_hex_color(2, 2, Color::RED);
+        hex_grid.set_hex_color(3, 2, Color::BLUE);
+        hex_grid.set_wall(2, 2, Direction::East, Some(Color::BLACK));
+        hex_grid.set_wall(3, 2, Direction::NorthEast, Some(Color::GREEN));
+        hex_grid.set_wall_kind(3, 2, Direction::NorthEast, WallKind::Window);
+
+        let stamp = Stamp::copy(&hex_grid, &[(2, 2), (3, 2), (3, 2), (-5, 0)], (2, 2));
+        assert_eq!(stamp.hexes_at((0, 0)).len(), 2);
+
+        // A wall where the stamp lands goes, since the stamp has none there.
+        hex_grid.set_wall(4, 5, Direction::SouthEast, Some(Color::BLACK));
+        stamp.paste(&mut hex_grid, (4, 5));
+        assert_eq!(hex_grid.hex_color(4, 5), Some(Color::RED));
+        assert_eq!(hex_grid.hex_color(5, 5), Some(Color::BLUE));
+        assert_eq!(hex_grid.wall(5, 5, Direction::West), Some(Color::BLACK));
+        assert_eq!(
+            hex_grid.wall(5, 5, Direction::NorthEast),
+            Some(Color::GREEN)
+        );
+        assert_eq!(
+            hex_grid.wall_kind(5, 5, Direction::NorthEast),
+            Some(WallKind::Window)
+        );
+        assert_eq!(hex_grid.wall(4, 5, Direction::SouthEast), None);
+    }
+
+    #[test]
+    fn test_rotate() {
+        let mut hex_grid = HexGrid::new(10, 10);
+        hex_grid.set_hex_color(3, 3, Color::RED);
+        hex_grid.set_hex_color(4, 3, Color::BLUE);
+        hex_grid.set_wall(4, 3, Direction::East, Some(Color::BLACK));
+        let way = WallKind::OneWay(Direction::East);
+        hex_grid.set_wall_kind(4, 3, Direction::East, way);
+        let stamp = Stamp::copy(&hex_grid, &[(3, 3), (4, 3)], (3, 3));
+
+        // A turn clockwise takes east to south-east, walls and one-way
+        // passages included.
+        let rotated = stamp.rotated(1);
+        let hexes = rotated.hexes_at((0, 0));
+        assert_eq!(hexes, vec![((0, 0), Color::RED), ((0, 1), Color::BLUE)]);
+        let wall = (Color::BLACK, WallKind::OneWay(Direction::SouthEast));
+        assert!(rotated
+            .walls_at((0, 0))
+            .contains(&(0, 1, Direction::SouthEast, Some(wall))));
+
+        assert_eq!(stamp.rotated(-1), stamp.rotated(5));
+        assert_eq!(stamp.rotated(6), stamp);
+        assert_eq!(stamp.rotated(2).rotated(4), stamp);
+    }
+
+    #[test]
+    fn test_mirror() {
+        let mut hex_grid = HexGrid::new(10, 10);
+        hex_grid.set_wall(3, 3, Direction::SouthEast, Some(Color::BLACK));
+        hex_grid.set_wall(3, 3, Direction::NorthWest, Some(Color::RED));
+        hex_grid.set_wall(3, 3, Direction::East, Some(Color::GREEN));
+        let stamp = Stamp::copy(&hex_grid, &[(3, 3), (3, 4)], (3, 3));
+
+        let mirrored = stamp.mirrored();
+        assert_eq!(mirrored.mirrored(), stamp);
+        mirrored.paste(&mut hex_grid, (5, 5));
+        assert_eq!(
+            hex_grid.wall(5, 5, Direction::NorthEast),
+            Some(Color::BLACK)
+        );
+        assert_eq!(hex_grid.wall(5, 5, Direction::SouthWest), Some(Color::RED));
+        assert_eq!(hex_grid.wall(5, 5, Direction::East), Some(Color::GREEN));
+        assert_eq!(hex_grid.wall(5, 5, Direction::SouthEast), None);
+        assert_eq!(hex_grid.hex_color(6, 4), Some(Color::MAGENTA));
+    }
+
+    #[test]
+    fn test_paste_across_orientations() {
+        // Stamps don't care which hex owns which wall, so a stamp copied from
+        // a pointy-top grid pastes the same on a flat-top one.
+        let mut hex_grid = HexGrid::new(10, 10);
+        for dir in Direction::ALL {
+            hex_grid.set_wall(4, 4, dir, Some(Color::BLACK));
+        }
+        let stamp = Stamp::copy(&hex_grid, &[(4, 4)], (4, 4)).rotated(2);
+
+        let shape = MapShape::Hexagon { radius: 3 };
+        let mut flat = HexGrid::with_orientation(shape, Orientation::FlatTop);
+        stamp.paste(&mut flat, (1, -1));
+        for dir in Direction::ALL {
+            assert_eq!(flat.wall(1, -1, dir), Some(Color::BLACK));
+            assert_eq!(flat.wall(-2, 0, dir), None);
+        }
+    }
+}
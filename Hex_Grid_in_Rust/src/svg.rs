@@ -0,0 +1,282 @@
+use crate::backend::*;
+use crate::hex_map::HexMap;
+use crate::render::HexGridRenderer;
+use raylib::prelude::*;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// How much empty space to leave around the grid, in pixels. Enough for the
+/// walls along the edges of the grid to fit.
+const MARGIN: f32 = 5.0;
+
+// Exporting grids as SVG images, without needing a window (or a display) like
+// drawing with raylib does.
+impl HexGridRenderer {
+    /// Saves an SVG image of `hex_grid` to the file at `path`. See
+    /// `write_svg()` for details.
+    pub fn save_svg_to_file<M: HexMap, P: AsRef<Path>>(
+        &self,
+        hex_grid: &M,
+        labels: bool,
+        path: P,
+    ) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_svg(&mut writer, hex_grid, labels)?;
+        writer.flush()
+    }
+
+    /// Writes an SVG image of `hex_grid` to `writer`.
+    ///
+    /// This is exactly the picture `draw_all()` draws (ghost columns included),
+    /// just translated so that the whole grid is in view. The `hex_int()` of
+    /// each hex is written over it if `labels` is true.
+    ///
+    /// Numbers are written with a fixed precision, so the same grid always
+    /// gives exactly the same output.
+    pub fn write_svg<W: Write, M: HexMap>(
+        &self,
+        writer: &mut W,
+        hex_grid: &M,
+        labels: bool,
+    ) -> io::Result<()> {
+        let mut recording = RecordingBackend::new();
+        self.draw_all(&mut recording, hex_grid);
+
+        // The labels are the only text `draw()` draws.
+        let mut calls = recording.calls;
+        if !labels {
+            calls.retain(|call| !matches!(call, DrawCall::Text { .. }));
+        }
+
+        write_svg_calls(writer, &calls)
+    }
+}
+
+/// Writes an SVG image with the given draw calls to `writer`, sized to fit
+/// them all.
+fn write_svg_calls<W: Write>(writer: &mut W, calls: &[DrawCall]) -> io::Result<()> {
+    // The bounding box of everything we draw.
+    let mut min = Vector2::new(f32::MAX, f32::MAX);
+    let mut max = Vector2::new(f32::MIN, f32::MIN);
+    let mut include = |p: Vector2| {
+        min = Vector2::new(min.x.min(p.x), min.y.min(p.y));
+        max = Vector2::new(max.x.max(p.x), max.y.max(p.y));
+    };
+    for call in calls {
+        match call {
+            DrawCall::FillPolygon { points, .. } | DrawCall::StrokePolygon { points, .. } => {
+                points.iter().for_each(|&p| include(p));
+            }
+            DrawCall::Line { start, end, .. } => {
+                include(*start);
+                include(*end);
+            }
+            DrawCall::Circle { center, radius, .. } => {
+                include(*center - Vector2::new(*radius, *radius));
+                include(*center + Vector2::new(*radius, *radius));
+            }
+            DrawCall::Text { position, .. } => include(*position),
+        }
+    }
+    if calls.is_empty() {
+        (min, max) = (Vector2::new(0.0, 0.0), Vector2::new(0.0, 0.0));
+    }
+    let origin = min - Vector2::new(MARGIN, MARGIN);
+    let size = max - min + Vector2::new(2.0 * MARGIN, 2.0 * MARGIN);
+
+    writeln!(
+        writer,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+        svg_number(size.x),
+        svg_number(size.y),
+        svg_number(origin.x),
+        svg_number(origin.y),
+        svg_number(size.x),
+        svg_number(size.y)
+    )?;
+
+    for call in calls {
+        match call {
+            DrawCall::FillPolygon { points, color } => writeln!(
+                writer,
+                r#"<polygon points="{}" {} stroke="none"/>"#,
+                svg_points(points),
+                svg_paint("fill", *color)
+            )?,
+            DrawCall::StrokePolygon {
+                points,
+                thickness,
+                color,
+            } => writeln!(
+                writer,
+                r#"<polygon points="{}" fill="none" {} stroke-width="{}"/>"#,
+                svg_points(points),
+                svg_paint("stroke", *color),
+                svg_number(*thickness)
+            )?,
+            DrawCall::Line {
+                start,
+                end,
+                thickness,
+                color,
+            } => writeln!(
+                writer,
+                r#"<line x1="{}" y1="{}" x2="{}" y2="{}" {} stroke-width="{}"/>"#,
+                svg_number(start.x),
+                svg_number(start.y),
+                svg_number(end.x),
+                svg_number(end.y),
+                svg_paint("stroke", *color),
+                svg_number(*thickness)
+            )?,
+            DrawCall::Circle {
+                center,
+                radius,
+                color,
+            } => writeln!(
+                writer,
+                r#"<circle cx="{}" cy="{}" r="{}" {}/>"#,
+                svg_number(center.x),
+                svg_number(center.y),
+                svg_number(*radius),
+                svg_paint("fill", *color)
+            )?,
+            // Like raylib's `draw_text()`, the position is the top-left corner
+            // of the text.
+            DrawCall::Text {
+                text,
+                position,
+                font_size,
+                color,
+            } => writeln!(
+                writer,
+                r#"<text x="{}" y="{}" font-family="monospace" font-size="{}" dominant-baseline="hanging" {}>{}</text>"#,
+                svg_number(position.x),
+                svg_number(position.y),
+                font_size,
+                svg_paint("fill", *color),
+                svg_escape(text)
+            )?,
+        }
+    }
+
+    writeln!(writer, "</svg>")
+}
+
+fn svg_points(points: &[Vector2]) -> String {
+    let points: Vec<_> = points
+        .iter()
+        .map(|p| format!("{},{}", svg_number(p.x), svg_number(p.y)))
+        .collect();
+    points.join(" ")
+}
+
+fn svg_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Formats a coordinate with a fixed precision. Tiny negative numbers would be
+/// written as "-0.00", so we clean those up.
+fn svg_number(x: f32) -> String {
+    let text = format!("{:.2}", x);
+    if text == "-0.00" {
+        "0.00".to_string()
+    } else {
+        text
+    }
+}
+
+/// Returns the SVG attributes to paint the fill or stroke (as given by `attr`)
+/// with `color`, including its opacity unless it is fully opaque.
+fn svg_paint(attr: &str, color: Color) -> String {
+    let mut paint = format!(
+        r##"{}="#{:02x}{:02x}{:02x}""##,
+        attr, color.r, color.g, color.b
+    );
+    if color.a != 255 {
+        paint += &format!(r#" {}-opacity="{:.2}""#, attr, color.a as f32 / 255.0);
+    }
+    paint
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex_grid::*;
+
+    fn svg(renderer: &HexGridRenderer, hex_grid: &HexGrid, labels: bool) -> String {
+        let mut data = Vec::new();
+        renderer.write_svg(&mut data, hex_grid, labels).unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    #[test]
+    fn test_svg_snapshot() {
+        let renderer = HexGridRenderer::new(10.0);
+        let mut hex_grid = HexGrid::new(1, 1);
+        hex_grid.set_hex_color(0, 0, Color::BLUE);
+        hex_grid.set_wall(0, 0, Direction::East, Some(Color::new(255, 0, 0, 128)));
+
+        let expected = concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="27.32" height="30.00" viewBox="-13.66 -15.00 27.32 30.00">"#,
+            "\n",
+            r##"<polygon points="8.66,-5.00 8.66,5.00 0.00,10.00 -8.66,5.00 -8.66,-5.00 0.00,-10.00" fill="#0079f1" stroke="none"/>"##,
+            "\n",
+            r##"<polygon points="8.66,-5.00 8.66,5.00 0.00,10.00 -8.66,5.00 -8.66,-5.00 0.00,-10.00" fill="none" stroke="#505050" stroke-width="1.00"/>"##,
+            "\n",
+            r##"<text x="0.00" y="0.00" font-family="monospace" font-size="20" dominant-baseline="hanging" fill="#000000">0</text>"##,
+            "\n",
+            r##"<line x1="8.66" y1="5.00" x2="8.66" y2="-5.00" stroke="#ff0000" stroke-opacity="0.50" stroke-width="6.00"/>"##,
+            "\n",
+            "</svg>\n",
+        );
+        assert_eq!(svg(&renderer, &hex_grid, true), expected);
+    }
+
+    #[test]
+    fn test_svg_contents() {
+        let renderer = HexGridRenderer::new(35.0);
+        let mut hex_grid = HexGrid::new(4, 3);
+        hex_grid.set_wall(1, 1, Direction::West, Some(Color::BLACK));
+        hex_grid.set_wall(2, 2, Direction::SouthEast, Some(Color::BLACK));
+
+        let svg_text = svg(&renderer, &hex_grid, false);
+        assert_eq!(svg_text.matches("<polygon").count(), 2 * 12);
+        assert_eq!(svg_text.matches("<line").count(), 2);
+        assert_eq!(svg_text.matches("<text").count(), 0);
+        assert_eq!(svg(&renderer, &hex_grid, true).matches("<text").count(), 12);
+
+        // Exactly the same geometry as `draw()`.
+        let center = renderer.hex_center(1, 1);
+        let corner = renderer.hex_corner_position(center, 3);
+        let point = format!("{},{}", svg_number(corner.x), svg_number(corner.y));
+        assert!(svg_text.contains(&point));
+    }
+
+    #[test]
+    fn test_svg_ghosts() {
+        let mut renderer = HexGridRenderer::new(10.0);
+        renderer.set_ghost_columns(1);
+        let hex_grid = HexGrid::new_wrapping(4, 3);
+
+        // Each ghost hex is drawn (filled and outlined), then tinted.
+        let svg_text = svg(&renderer, &hex_grid, false);
+        assert_eq!(svg_text.matches("<polygon").count(), 2 * 12 + 3 * 6);
+        assert_eq!(svg_text.matches(r#"fill-opacity="0.50""#).count(), 6);
+    }
+
+    #[test]
+    fn test_svg_ignores_view() {
+        let mut renderer = HexGridRenderer::new(10.0);
+        let hex_grid = HexGrid::new(4, 3);
+        let whole = svg(&renderer, &hex_grid, true);
+
+        // The editor always has a view set, but exports show everything.
+        renderer.set_view(Some(Rectangle::new(0.0, 0.0, 1.0, 1.0)));
+        assert_eq!(svg(&renderer, &hex_grid, true), whole);
+    }
+}
@@ -0,0 +1,359 @@
+use crate::hex_grid::*;
+use pcg64s::Rand;
+use raylib::prelude::*;
+
+/// A kind of land `generate_terrain()` paints, and where it goes.
+///
+/// Elevations and moistures are fractions: elevation 0 is the coast and 1 the
+/// highest peak of the map, and moisture goes from 0 on the driest hex to 1 on
+/// the wettest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Biome {
+    /// The color hexes of this biome are painted with.
+    pub color: Color,
+
+    /// The lowest elevation this biome can be at.
+    pub min_elevation: f32,
+
+    /// The range of moistures this biome can be at, both ends included.
+    pub min_moisture: f32,
+    pub max_moisture: f32,
+}
+
+impl Biome {
+    const fn new(color: Color, min_elevation: f32, min_moisture: f32, max_moisture: f32) -> Self {
+        Self {
+            color,
+            min_elevation,
+            min_moisture,
+            max_moisture,
+        }
+    }
+
+    fn contains(&self, elevation: f32, moisture: f32) -> bool {
+        elevation >= self.min_elevation
+            && moisture >= self.min_moisture
+            && moisture <= self.max_moisture
+    }
+}
+
+/// How `generate_terrain()` shapes the land.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TerrainSettings {
+    /// How many layers of noise are added up, each with twice the detail and
+    /// `persistence` times the strength of the previous one. More octaves make
+    /// rougher coasts.
+    pub octaves: u32,
+
+    /// How much weaker each octave is than the previous one.
+    pub persistence: f32,
+
+    /// The size, in hexes, of the largest features (continents, and the like).
+    pub scale: f32,
+
+    /// The elevation of the sea, from 0 (the lowest hex of the map) to 1 (the
+    /// highest). Hexes below it are painted with `ocean`. This is not the
+    /// fraction of the map under water, which depends on how the noise falls.
+    pub sea_level: f32,
+
+    /// The color of hexes under water.
+    pub ocean: Color,
+
+    /// The biomes above water. Each hex is painted with the first biome that
+    /// fits it, or the last one if none does.
+    pub biomes: Vec<Biome>,
+}
+
+impl Default for TerrainSettings {
+    /// The biomes of the editor's palette: grassland, forest, hills, desert,
+    /// mountains, snow and lava, around a blue ocean.
+    fn default() -> Self {
+        Self {
+            octaves: 4,
+            persistence: 0.5,
+            scale: 12.0,
+            sea_level: 0.45,
+            ocean: Color::BLUE,
+            biomes: vec![
+                // Lava, on the driest peaks.
+                Biome::new(Color::ORANGE, 0.8, 0.0, 0.15),
+                // Snow.
+                Biome::new(Color::WHITESMOKE, 0.85, 0.0, 1.0),
+                // Mountains.
+                Biome::new(Color::GRAY, 0.65, 0.0, 1.0),
+                // Hills.
+                Biome::new(Color::BROWN, 0.45, 0.0, 1.0),
+                // Desert.
+                Biome::new(Color::YELLOW, 0.0, 0.0, 0.3),
+                // Forest.
+                Biome::new(Color::DARKGREEN, 0.0, 0.6, 1.0),
+                // Grassland.
+                Biome::new(Color::LIGHTGREEN, 0.0, 0.0, 1.0),
+            ],
+        }
+    }
+}
+
+/// Paints every hex of the grid with a biome, going by an elevation and a
+/// moisture made up of fractal value noise. Walls are left alone.
+///
+/// Elevation and moisture are stretched to cover the whole range from 0 to 1
+/// on each map, so `sea_level` and the biomes work the same whatever the size
+/// of the map. On grids that wrap around horizontally, the noise wraps around,
+/// too, so there is no seam.
+///
+/// All randomness comes from `rng`, so the same seed always gives the same
+/// terrain.
+pub fn generate_terrain(hex_grid: &mut HexGrid, settings: &TerrainSettings, rng: &mut Rand) {
+    assert!(
+        settings.octaves > 0,
+        "there must be at least one octave, got {}",
+        settings.octaves
+    );
+    assert!(
+        !settings.biomes.is_empty(),
+        "there must be at least one biome"
+    );
+
+    // The noise must repeat every time we go around the grid.
+    let period = hex_grid
+        .wraps_horizontally()
+        .then(|| 3.0f64.sqrt() * hex_grid.width() as f64);
+    let elevation_seed = rng.random();
+    let moisture_seed = rng.random();
+
+    let hexes: Vec<_> = hex_grid.axial_coords().collect();
+    let field = |seed| {
+        let values: Vec<_> = hexes
+            .iter()
+            .map(|&hex| {
+                let (x, y) = hex_center(hex);
+                fractal_noise(seed, x, y, settings, period)
+            })
+            .collect();
+        stretch(&values)
+    };
+    let elevations = field(elevation_seed);
+    let moistures = field(moisture_seed);
+
+    let sea_level = settings.sea_level as f64;
+    for (i, &(q, r)) in hexes.iter().enumerate() {
+        let color = if elevations[i] < sea_level {
+            settings.ocean
+        } else {
+            let elevation =
+                ((elevations[i] - sea_level) / (1.0 - sea_level).max(f64::EPSILON)) as f32;
+            let moisture = moistures[i] as f32;
+            let biomes = &settings.biomes;
+            biomes
+                .iter()
+                .find(|biome| biome.contains(elevation, moisture))
+                .unwrap_or(&biomes[biomes.len() - 1])
+                .color
+        };
+        hex_grid.set_hex_color(q, r, color);
+    }
+}
+
+//
+// Internal helpers
+//
+
+/// Adds up the octaves of value noise at (`x`, `y`), in the range 0 to 1.
+///
+/// With a `period`, the noise repeats every `period` along `x`: each octave
+/// fits a whole number of lattice cells in the period (stretching them a bit,
+/// if needed), and wraps its lattice around.
+fn fractal_noise(
+    seed: u64,
+    x: f64,
+    y: f64,
+    settings: &TerrainSettings,
+    period: Option<f64>,
+) -> f64 {
+    let mut frequency = 1.0 / settings.scale.max(f32::EPSILON) as f64;
+    let mut amplitude = 1.0;
+    let mut total = 0.0;
+    let mut max = 0.0;
+
+    for octave in 0..settings.octaves {
+        let seed = seed.wrapping_add((octave as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        let (x_frequency, cells) = match period {
+            Some(period) => {
+                let cells = (period * frequency).round().max(1.0);
+                (cells / period, Some(cells as i64))
+            }
+            None => (frequency, None),
+        };
+        total += amplitude * value_noise(seed, x * x_frequency, y * frequency, cells);
+        max += amplitude;
+        amplitude *= settings.persistence as f64;
+        frequency *= 2.0;
+    }
+
+    total / max
+}
+
+/// Smoothly interpolates between random values at the integer points of the
+/// plane, in the range 0 to 1. With `cells`, the lattice wraps around every
+/// `cells` along `x`.
+fn value_noise(seed: u64, x: f64, y: f64, cells: Option<i64>) -> f64 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (smoothstep(x - x0), smoothstep(y - y0));
+    let (x0, y0) = (x0 as i64, y0 as i64);
+
+    let lattice = |x: i64, y: i64| {
+        let x = match cells {
+            Some(cells) => x.rem_euclid(cells),
+            None => x,
+        };
+        lattice_value(seed, x, y)
+    };
+    let top = lerp(lattice(x0, y0), lattice(x0 + 1, y0), tx);
+    let bottom = lerp(lattice(x0, y0 + 1), lattice(x0 + 1, y0 + 1), tx);
+    lerp(top, bottom, ty)
+}
+
+/// The random value at an integer point of the plane, from 0 to 1: a hash of
+/// the point and the seed (SplitMix64's finalizer).
+fn lattice_value(seed: u64, x: i64, y: i64) -> f64 {
+    let mut h = seed
+        ^ (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^= h >> 31;
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn smoothstep(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+/// Rescales `values` linearly so that they go from 0 to 1.
+fn stretch(values: &[f64]) -> Vec<f64> {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let range = (max - min).max(f64::EPSILON);
+    values.iter().map(|v| (v - min) / range).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex_map::HexMap;
+
+    fn colors(hex_grid: &HexGrid) -> Vec<Color> {
+        hex_grid
+            .axial_coords()
+            .map(|(q, r)| hex_grid.hex_color(q, r).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_only_table_colors() {
+        let settings = TerrainSettings::default();
+        let mut hex_grid = HexGrid::new(30, 20);
+        generate_terrain(&mut hex_grid, &settings, &mut Rand::new(3));
+
+        let colors = colors(&hex_grid);
+        for color in &colors {
+            assert!(
+                *color == settings.ocean || settings.biomes.iter().any(|b| b.color == *color),
+                "unexpected color {:?}",
+                color
+            );
+        }
+
+        // There is both water and land.
+        let ocean = colors.iter().filter(|&&c| c == settings.ocean).count();
+        let fraction = ocean as f32 / colors.len() as f32;
+        assert!(
+            (0.2..0.7).contains(&fraction),
+            "ocean fraction {}",
+            fraction
+        );
+    }
+
+    #[test]
+    fn test_same_seed_same_terrain() {
+        let settings = TerrainSettings::default();
+        let mut a = HexGrid::new(20, 15);
+        let mut b = HexGrid::new(20, 15);
+        let mut c = HexGrid::new(20, 15);
+        generate_terrain(&mut a, &settings, &mut Rand::new(1234));
+        generate_terrain(&mut b, &settings, &mut Rand::new(1234));
+        generate_terrain(&mut c, &settings, &mut Rand::new(4321));
+        assert_eq!(colors(&a), colors(&b));
+        assert_ne!(colors(&a), colors(&c));
+    }
+
+    #[test]
+    fn test_sea_level_and_biome_table() {
+        let mut settings = TerrainSettings {
+            sea_level: 0.0,
+            biomes: vec![Biome::new(Color::RED, 0.0, 0.0, 1.0)],
+            ..Default::default()
+        };
+        let mut hex_grid = HexGrid::with_shape(crate::layers::MapShape::Hexagon { radius: 6 });
+        generate_terrain(&mut hex_grid, &settings, &mut Rand::new(9));
+        assert!(colors(&hex_grid).iter().all(|&c| c == Color::RED));
+
+        settings.sea_level = 1.0;
+        generate_terrain(&mut hex_grid, &settings, &mut Rand::new(9));
+        let ocean = colors(&hex_grid)
+            .iter()
+            .filter(|&&c| c == settings.ocean)
+            .count();
+        assert_eq!(ocean, hex_grid.axial_coords().count() - 1);
+    }
+
+    #[test]
+    fn test_no_seam() {
+        let settings = TerrainSettings::default();
+        let period = 3.0f64.sqrt() * 19.0;
+        for i in 0..50 {
+            let (x, y) = (i as f64 * 0.7, i as f64 * 0.3);
+            let a = fractal_noise(5, x, y, &settings, Some(period));
+            let b = fractal_noise(5, x + period, y, &settings, Some(period));
+            assert!((a - b).abs() < 1e-9);
+        }
+
+        // Across the seam, neighbors are as close as anywhere else.
+        let hex_grid = HexGrid::new_wrapping(19, 11);
+        let noise = |(q, r)| {
+            let (x, y) = hex_center((q, r));
+            fractal_noise(5, x, y, &settings, Some(period))
+        };
+        let (mut max_step, mut seam_steps) = (0.0, Vec::new());
+        for (q, r) in hex_grid.axial_coords() {
+            for dir in Direction::ALL {
+                let (dq, dr) = dir.offset();
+                let neighbor = hex_grid.neighbor(q, r, dir).unwrap_or((q + dq, r + dr));
+                let step = (noise((q, r)) - noise(neighbor)).abs();
+                if neighbor == (q + dq, r + dr) {
+                    max_step = f64::max(max_step, step);
+                } else {
+                    seam_steps.push(step);
+                }
+            }
+        }
+        assert!(!seam_steps.is_empty());
+        assert!(seam_steps.iter().all(|&step| step <= max_step));
+    }
+
+    #[test]
+    fn test_value_noise() {
+        for i in 0..100 {
+            let (x, y) = (i as f64 * 0.37 - 20.0, i as f64 * 0.11 - 5.0);
+            let v = value_noise(11, x, y, None);
+            assert!((0.0..=1.0).contains(&v));
+        }
+        assert_eq!(value_noise(11, 3.0, 4.0, None), lattice_value(11, 3, 4));
+        assert_eq!(value_noise(11, 3.0, 4.0, Some(3)), lattice_value(11, 0, 4));
+    }
+}
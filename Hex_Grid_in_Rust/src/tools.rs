@@ -0,0 +1,310 @@
+use crate::hex_grid::*;
+use crate::hex_map::HexMap;
+use crate::layers::Orientation;
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// The shapes behind the editor's painting tools. Each function works out which
+// hexes (or walls) a tool covers, and leaves what to do with them (painting
+// them, most likely) to the caller.
+//
+// All of them return canonical axial coordinates (see `HexGrid::normalize()`),
+// each hex only once, and skip whatever falls out of the grid. On grids that
+// wrap around horizontally, shapes go the shortest way around.
+
+/// Returns every hex up to `radius` hexes away from `center`, `center`
+/// included. A radius of 0 is just `center`, a radius of 1 adds its neighbors,
+/// and so on.
+pub fn hex_range<M: HexMap>(hex_grid: &M, center: (i32, i32), radius: i32) -> Vec<(i32, i32)> {
+    let mut result = Vec::new();
+    let mut seen = HashSet::new();
+
+    for dr in -radius..=radius {
+        for dq in (-radius).max(-dr - radius)..=radius.min(-dr + radius) {
+            if let Some(hex) = hex_grid.normalize(center.0 + dq, center.1 + dr) {
+                if seen.insert(hex) {
+                    result.push(hex);
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// Returns the hexes along the straight line from `a` to `b`, in order. Each
+/// hex is a neighbor of the previous one.
+///
+/// Lines going exactly between two hexes are nudged a tiny bit, so that they
+/// always pick the same side.
+pub fn hex_line<M: HexMap>(hex_grid: &M, a: (i32, i32), b: (i32, i32)) -> Vec<(i32, i32)> {
+    let b = hex_grid.unwrap_towards(a, b);
+    let n = axial_distance(a, b);
+
+    let mut result = Vec::new();
+    let mut seen = HashSet::new();
+    for i in 0..=n {
+        let t = if n == 0 { 0.0 } else { i as f64 / n as f64 };
+        let q = a.0 as f64 + (b.0 - a.0) as f64 * t + 1e-6;
+        let r = a.1 as f64 + (b.1 - a.1) as f64 * t + 1e-6;
+        let (q, r) = axial_round(q, r);
+        if let Some(hex) = hex_grid.normalize(q, r) {
+            if seen.insert(hex) {
+                result.push(hex);
+            }
+        }
+    }
+
+    result
+}
+
+/// Returns the hexes in the "rectangle" with opposite corners at `a` and `b`:
+/// all the rows between them, and on each row, the same columns as they are
+/// on. (Odd rows are shifted by half a hex, so the sides zigzag.) On flat-top
+/// grids, rows and columns swap roles, like in their layout.
+pub fn hex_rectangle<M: HexMap>(hex_grid: &M, a: (i32, i32), b: (i32, i32)) -> Vec<(i32, i32)> {
+    let b = hex_grid.unwrap_towards(a, b);
+
+    // Flat-top grids are laid out like pointy-top ones with `q` and `r`
+    // swapped (see `Orientation`), so swap them, too.
+    let mirror = |(q, r): (i32, i32)| match hex_grid.orientation() {
+        Orientation::PointyTop => (q, r),
+        Orientation::FlatTop => (r, q),
+    };
+    let (a, b) = (mirror(a), mirror(b));
+
+    // Columns, counted from the start of each row, as in the grid's layout.
+    let column = |(q, r): (i32, i32)| q + r.div_euclid(2);
+    let (x0, x1) = (column(a).min(column(b)), column(a).max(column(b)));
+    let (r0, r1) = (a.1.min(b.1), a.1.max(b.1));
+
+    let mut result = Vec::new();
+    let mut seen = HashSet::new();
+    for r in r0..=r1 {
+        for x in x0..=x1 {
+            let (q, r) = mirror((x - r.div_euclid(2), r));
+            if let Some(hex) = hex_grid.normalize(q, r) {
+                if seen.insert(hex) {
+                    result.push(hex);
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// Returns the hexes reachable from `start` by going through neighbors with
+/// the same color as `start`, nearest first. With `stop_at_walls`, walls block
+/// the way, too.
+///
+/// Returns nothing if `start` is out of the grid.
+pub fn flood_fill<M: HexMap>(
+    hex_grid: &M,
+    start: (i32, i32),
+    stop_at_walls: bool,
+) -> Vec<(i32, i32)> {
+    let Some(start) = hex_grid.normalize(start.0, start.1) else {
+        return Vec::new();
+    };
+    let color = hex_grid.hex_color(start.0, start.1);
+
+    let mut result = Vec::new();
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    while let Some((q, r)) = queue.pop_front() {
+        result.push((q, r));
+        for (dir, hex) in hex_grid.neighbors(q, r) {
+            if stop_at_walls && hex_grid.wall(q, r, dir).is_some() {
+                continue;
+            }
+            if hex_grid.hex_color(hex.0, hex.1) == color && seen.insert(hex) {
+                queue.push_back(hex);
+            }
+        }
+    }
+
+    result
+}
+
+/// Returns the walls along the shortest chain of hex edges from the vertex
+/// `from` to the vertex `to` (see `HexGrid::normalize_vertex()`), in order, as
+/// the coordinates and direction of a hex to pass to `HexGrid::set_wall()`.
+///
+/// Returns `None` if either vertex is out of the grid, and no walls at all if
+/// they are the same vertex.
+pub fn edge_path<M: HexMap>(
+    hex_grid: &M,
+    from: Vertex,
+    to: Vertex,
+) -> Option<Vec<(i32, i32, Direction)>> {
+    let from = hex_grid.normalize_vertex(from.0, from.1, from.2)?;
+    let to = hex_grid.normalize_vertex(to.0, to.1, to.2)?;
+
+    // Every edge is as long as any other, so a breadth-first search finds the
+    // shortest chain.
+    let mut came_from = HashMap::from([(from, None)]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(vertex) = queue.pop_front() {
+        if vertex == to {
+            let mut walls = Vec::new();
+            let mut vertex = to;
+            while let Some((prev, wall)) = came_from[&vertex] {
+                walls.push(wall);
+                vertex = prev;
+            }
+            walls.reverse();
+            return Some(walls);
+        }
+
+        for (next, wall) in hex_grid.vertex_edges(vertex.0, vertex.1, vertex.2) {
+            if let Entry::Vacant(entry) = came_from.entry(next) {
+                entry.insert(Some((vertex, wall)));
+                queue.push_back(next);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::MapShape;
+    use raylib::prelude::*;
+
+    #[test]
+    fn test_hex_range() {
+        let hex_grid = HexGrid::new(20, 20);
+        assert_eq!(hex_range(&hex_grid, (5, 10), 0), vec![(5, 10)]);
+        for radius in 1..4 {
+            let hexes = hex_range(&hex_grid, (5, 10), radius);
+            assert_eq!(hexes.len() as i32, 1 + 3 * radius * (radius + 1));
+            assert!(hexes
+                .iter()
+                .all(|&hex| hex_grid.distance((5, 10), hex) <= radius as u32));
+        }
+
+        // Clipped by the corner of the grid.
+        assert_eq!(hex_range(&hex_grid, (0, 0), 1).len(), 3);
+
+        // Around the seam, and all the way around a tiny grid.
+        let hex_grid = HexGrid::new_wrapping(20, 20);
+        let hexes = hex_range(&hex_grid, (-5, 10), 2);
+        assert_eq!(hexes.len(), 19);
+        assert!(hexes.contains(&(13, 10)));
+        let hex_grid = HexGrid::new_wrapping(3, 3);
+        assert_eq!(hex_range(&hex_grid, (0, 1), 5).len(), 9);
+    }
+
+    #[test]
+    fn test_hex_line() {
+        let hex_grid = HexGrid::new(20, 20);
+        assert_eq!(hex_line(&hex_grid, (3, 3), (3, 3)), vec![(3, 3)]);
+        assert_eq!(
+            hex_line(&hex_grid, (0, 2), (4, 2)),
+            vec![(0, 2), (1, 2), (2, 2), (3, 2), (4, 2)]
+        );
+
+        // Not along an axis: still connected, and as short as can be.
+        let (a, b) = ((2, 1), (9, 12));
+        let line = hex_line(&hex_grid, a, b);
+        assert_eq!(line.len() as u32, hex_grid.distance(a, b) + 1);
+        assert_eq!((line[0], line[line.len() - 1]), (a, b));
+        for pair in line.windows(2) {
+            assert_eq!(hex_grid.distance(pair[0], pair[1]), 1);
+        }
+
+        // The short way around the seam.
+        let hex_grid = HexGrid::new_wrapping(10, 2);
+        assert_eq!(
+            hex_line(&hex_grid, (1, 0), (8, 0)),
+            vec![(1, 0), (0, 0), (9, 0), (8, 0)]
+        );
+    }
+
+    #[test]
+    fn test_hex_rectangle() {
+        let hex_grid = HexGrid::new(10, 10);
+        let hexes = hex_rectangle(&hex_grid, (5, 1), (0, 4));
+        assert_eq!(hexes.len(), 4 * 4);
+        assert_eq!(hexes[0], (2, 1));
+        assert!(hexes.contains(&(0, 4)));
+        assert!(hexes.contains(&(3, 4)));
+        assert!(!hexes.contains(&(4, 4)));
+
+        // Clipped by the edges of the grid.
+        assert_eq!(hex_rectangle(&hex_grid, (8, 0), (20, 1)).len(), 4);
+
+        // Flat-top: columns, each shifted like in the grid.
+        let shape = MapShape::Rectangle {
+            width: 10,
+            height: 10,
+        };
+        let hex_grid = HexGrid::with_orientation(shape, Orientation::FlatTop);
+        let hexes = hex_rectangle(&hex_grid, (1, 5), (4, 0));
+        assert_eq!(hexes.len(), 4 * 4);
+        assert_eq!(hexes[0], (1, 2));
+        assert!(hexes.contains(&(4, 0)));
+        assert!(hexes.contains(&(4, 3)));
+        assert!(!hexes.contains(&(4, 4)));
+    }
+
+    #[test]
+    fn test_flood_fill() {
+        let mut hex_grid = HexGrid::new(5, 1);
+        for (q, r) in hex_grid.axial_coords() {
+            hex_grid.set_hex_color(q, r, Color::BLUE);
+        }
+        hex_grid.set_hex_color(3, 0, Color::RED);
+        hex_grid.set_wall(1, 0, Direction::East, Some(Color::BLACK));
+
+        assert_eq!(
+            flood_fill(&hex_grid, (0, 0), false),
+            vec![(0, 0), (1, 0), (2, 0)]
+        );
+        assert_eq!(flood_fill(&hex_grid, (0, 0), true), vec![(0, 0), (1, 0)]);
+        assert_eq!(flood_fill(&hex_grid, (3, 0), true), vec![(3, 0)]);
+        assert!(flood_fill(&hex_grid, (7, 0), false).is_empty());
+
+        // Across the seam.
+        let mut hex_grid = HexGrid::new_wrapping(5, 1);
+        hex_grid.set_hex_color(2, 0, Color::RED);
+        let mut hexes = flood_fill(&hex_grid, (0, 0), false);
+        hexes.sort();
+        assert_eq!(hexes, vec![(0, 0), (1, 0), (3, 0), (4, 0)]);
+    }
+
+    #[test]
+    fn test_edge_path() {
+        let mut hex_grid = HexGrid::new(10, 10);
+        assert_eq!(edge_path(&hex_grid, (2, 2, 5), (2, 2, 5)), Some(vec![]));
+        assert_eq!(edge_path(&hex_grid, (2, 2, 5), (50, 50, 5)), None);
+
+        // Around a hex, the short way.
+        let walls = edge_path(&hex_grid, (2, 2, 5), (2, 2, 2)).unwrap();
+        assert_eq!(walls.len(), 3);
+        let walls = edge_path(&hex_grid, (2, 2, 4), (2, 2, 1)).unwrap();
+        assert_eq!(walls.len(), 3);
+        let walls = edge_path(&hex_grid, (2, 2, 5), (2, 2, 0)).unwrap();
+        assert_eq!(walls, vec![(2, 2, Direction::NorthEast)]);
+
+        // Straight east, along the tops of a row of hexes: two edges per hex.
+        let walls = edge_path(&hex_grid, (1, 4, 4), (5, 4, 0)).unwrap();
+        assert_eq!(walls.len(), 2 * 5);
+        for (q, r, dir) in walls {
+            hex_grid.set_wall(q, r, dir, Some(Color::BLACK));
+        }
+        for q in 1..=5 {
+            assert!(hex_grid.wall(q, 4, Direction::NorthWest).is_some());
+            assert!(hex_grid.wall(q, 4, Direction::NorthEast).is_some());
+        }
+
+        // Across the seam.
+        let hex_grid = HexGrid::new_wrapping(10, 2);
+        let walls = edge_path(&hex_grid, (0, 0, 4), (9, 0, 4)).unwrap();
+        assert_eq!(walls.len(), 2);
+    }
+}
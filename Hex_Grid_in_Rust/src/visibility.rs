@@ -0,0 +1,273 @@
+use crate::hex_grid::*;
+use crate::hex_map::HexMap;
+
+use std::collections::HashSet;
+
+/// How much we nudge sight lines sideways (in hex sizes). See `can_see()`.
+const NUDGE: f64 = 1e-6;
+
+/// Checks if the hex at `to` can be seen from the hex at `from`.
+///
+/// A hex sees another if the line between their centers doesn't cross any wall
+/// that blocks sight (see `WallKind::blocks_sight()`: windows, open doors and
+/// one-way passages don't). Walls are the same segments `HexGridRenderer`
+/// draws, connecting two corners of a hex.
+///
+/// Lines between hex centers often go exactly through hex corners or along
+/// edges, which makes it ambiguous whether they touch a wall or not. So we
+/// actually check two lines, nudged a tiny bit to each side of the real one,
+/// and the hex is visible if any of them is clear. This lets us see along the
+/// face of a wall and past its very tip, but not through the joint between two
+/// walls.
+///
+/// On grids that wrap around horizontally, we look the shortest way around.
+///
+/// Hexes out of the grid can't see or be seen.
+pub fn can_see<M: HexMap>(hex_grid: &M, from: (i32, i32), to: (i32, i32)) -> bool {
+    if !hex_grid.are_coords_valid(from.0, from.1) || !hex_grid.are_coords_valid(to.0, to.1) {
+        return false;
+    }
+
+    let to = hex_grid.unwrap_towards(from, to);
+
+    if from == to {
+        return true;
+    }
+
+    let a = hex_center(from);
+    let b = hex_center(to);
+
+    // A tiny offset, perpendicular to the line.
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len = (dx * dx + dy * dy).sqrt();
+    let (nx, ny) = (-dy / len * NUDGE, dx / len * NUDGE);
+
+    let walls = walls_near_line(hex_grid, a, b);
+
+    [1.0, -1.0].iter().any(|side| {
+        let a = (a.0 + nx * side, a.1 + ny * side);
+        let b = (b.0 + nx * side, b.1 + ny * side);
+        walls.iter().all(|(w1, w2)| !segments_cross(a, b, *w1, *w2))
+    })
+}
+
+/// Returns the canonical axial coordinates (see `HexGrid::normalize()`) of
+/// every hex visible from `origin` (including `origin` itself) up to `radius`
+/// hexes away.
+pub fn visible_hexes<M: HexMap>(hex_grid: &M, origin: (i32, i32), radius: i32) -> Vec<(i32, i32)> {
+    let mut result = Vec::new();
+    if !hex_grid.are_coords_valid(origin.0, origin.1) {
+        return result;
+    }
+
+    // On small grids that wrap around, the same hex may be within the radius
+    // both ways around.
+    let mut seen = HashSet::new();
+
+    for dr in -radius..=radius {
+        for dq in (-radius).max(-dr - radius)..=radius.min(-dr + radius) {
+            let Some(hex) = hex_grid.normalize(origin.0 + dq, origin.1 + dr) else {
+                continue;
+            };
+            if seen.insert(hex) && can_see(hex_grid, origin, hex) {
+                result.push(hex);
+            }
+        }
+    }
+
+    result
+}
+
+//
+// Internal helpers
+//
+
+/// The position of the `i`-th corner of the hex centered at `center`. Same as
+/// `HexGridRenderer::hex_corner_position()`, just scaled.
+fn hex_corner_position(center: (f64, f64), i: u8) -> (f64, f64) {
+    let angle = (60.0 * i as f64 - 30.0).to_radians();
+    (center.0 + angle.cos(), center.1 + angle.sin())
+}
+
+/// Collects the segments of all walls blocking sight that may cross the line
+/// from `a` to `b`.
+///
+/// Any wall crossing the line must be on the border of some hex the line passes
+/// through. We find those by sampling points along the line, and throw in the
+/// neighbors of each sampled hex for good measure, to make sure we don't miss
+/// hexes the line just clips.
+fn walls_near_line<M: HexMap>(
+    hex_grid: &M,
+    a: (f64, f64),
+    b: (f64, f64),
+) -> Vec<((f64, f64), (f64, f64))> {
+    let sqrt3 = 3.0f64.sqrt();
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let steps = ((dx * dx + dy * dy).sqrt() * 4.0).ceil() as i32;
+
+    let mut hexes = HashSet::new();
+    for step in 0..=steps {
+        let t = step as f64 / steps as f64;
+        let (x, y) = (a.0 + dx * t, a.1 + dy * t);
+        let (q, r) = axial_round(sqrt3 / 3.0 * x - y / 3.0, 2.0 / 3.0 * y);
+        hexes.insert((q, r));
+        for dir in Direction::ALL {
+            let (dq, dr) = dir.offset();
+            hexes.insert((q + dq, r + dr));
+        }
+    }
+
+    let mut walls = Vec::new();
+    for (q, r) in hexes {
+        if !hex_grid.are_coords_valid(q, r) {
+            continue;
+        }
+        let center = hex_center((q, r));
+        for dir in Direction::ALL {
+            if hex_grid.blocks_sight(q, r, dir) {
+                let (v1, v2) = dir.corners();
+                walls.push((
+                    hex_corner_position(center, v1),
+                    hex_corner_position(center, v2),
+                ));
+            }
+        }
+    }
+
+    walls
+}
+
+/// Checks if the segments `a1`-`a2` and `b1`-`b2` properly cross each other.
+/// Merely touching doesn't count.
+fn segments_cross(a1: (f64, f64), a2: (f64, f64), b1: (f64, f64), b2: (f64, f64)) -> bool {
+    let orient = |p: (f64, f64), q: (f64, f64), r: (f64, f64)| {
+        (q.0 - p.0) * (r.1 - p.1) - (q.1 - p.1) * (r.0 - p.0)
+    };
+
+    let o1 = orient(a1, a2, b1);
+    let o2 = orient(a1, a2, b2);
+    let o3 = orient(b1, b2, a1);
+    let o4 = orient(b1, b2, a2);
+
+    o1 * o2 < 0.0 && o3 * o4 < 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raylib::prelude::Color;
+
+    #[test]
+    fn test_open_grid_sees_everything() {
+        let hex_grid = HexGrid::new(7, 7);
+        for to in hex_grid.axial_coords() {
+            assert!(can_see(&hex_grid, (1, 3), to), "{:?}", to);
+        }
+        assert_eq!(visible_hexes(&hex_grid, (0, 0), 100).len(), 49);
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let hex_grid = HexGrid::new(3, 3);
+        assert!(!can_see(&hex_grid, (0, 0), (5, 0)));
+        assert!(!can_see(&hex_grid, (5, 0), (0, 0)));
+        assert!(visible_hexes(&hex_grid, (-3, 0), 2).is_empty());
+    }
+
+    #[test]
+    fn test_visible_hexes_radius() {
+        let hex_grid = HexGrid::new(20, 20);
+        let visible = visible_hexes(&hex_grid, (5, 10), 2);
+        assert_eq!(visible.len(), 19);
+        assert!(visible.iter().all(|&hex| axial_distance((5, 10), hex) <= 2));
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let mut hex_grid = HexGrid::new_wrapping(10, 1);
+        assert_eq!(visible_hexes(&hex_grid, (0, 0), 2).len(), 5);
+        assert_eq!(visible_hexes(&hex_grid, (0, 0), 20).len(), 10);
+
+        // Looking west, across the seam.
+        hex_grid.set_wall(8, 0, Direction::West, Some(Color::BLACK));
+        assert!(can_see(&hex_grid, (0, 0), (8, 0)));
+        assert!(!can_see(&hex_grid, (0, 0), (7, 0)));
+        assert!(can_see(&hex_grid, (1, 0), (-2, 0)));
+        assert!(!can_see(&hex_grid, (1, 0), (-3, 0)));
+    }
+
+    #[test]
+    fn test_wall_blocks_sight() {
+        let mut hex_grid = HexGrid::new(5, 1);
+        hex_grid.set_wall(2, 0, Direction::East, Some(Color::BLACK));
+
+        assert!(can_see(&hex_grid, (0, 0), (2, 0)));
+        assert!(!can_see(&hex_grid, (0, 0), (3, 0)));
+        assert!(!can_see(&hex_grid, (4, 0), (1, 0)));
+        assert!(!can_see(&hex_grid, (2, 0), (3, 0)));
+    }
+
+    #[test]
+    fn test_wall_kinds() {
+        let mut hex_grid = HexGrid::new(5, 1);
+        hex_grid.set_wall(2, 0, Direction::East, Some(Color::BLACK));
+
+        let see_through = [
+            WallKind::Window,
+            WallKind::OpenDoor,
+            WallKind::OneWay(Direction::East),
+        ];
+        for kind in see_through {
+            hex_grid.set_wall_kind(2, 0, Direction::East, kind);
+            assert!(can_see(&hex_grid, (0, 0), (4, 0)), "{:?}", kind);
+            assert!(can_see(&hex_grid, (4, 0), (0, 0)), "{:?}", kind);
+        }
+
+        hex_grid.set_wall_kind(2, 0, Direction::East, WallKind::LockedDoor);
+        assert!(!can_see(&hex_grid, (0, 0), (4, 0)));
+    }
+
+    #[test]
+    fn test_walled_hex() {
+        let mut hex_grid = HexGrid::new(7, 7);
+        let (q, r) = (1, 3);
+        hex_grid.set_wall(q, r, Direction::East, Some(Color::BLACK));
+        hex_grid.set_wall(q, r, Direction::SouthEast, Some(Color::BLACK));
+        hex_grid.set_wall(q, r, Direction::SouthWest, Some(Color::BLACK));
+        hex_grid.set_wall(q, r, Direction::West, Some(Color::BLACK));
+        hex_grid.set_wall(q, r, Direction::NorthWest, Some(Color::BLACK));
+        hex_grid.set_wall(q, r, Direction::NorthEast, Some(Color::BLACK));
+
+        // Sealed from the inside...
+        assert_eq!(visible_hexes(&hex_grid, (q, r), 10), vec![(q, r)]);
+
+        // ...and from the outside.
+        for from in hex_grid.axial_coords() {
+            assert_eq!(can_see(&hex_grid, from, (q, r)), from == (q, r));
+        }
+    }
+
+    #[test]
+    fn test_sight_through_wall_joint_is_blocked() {
+        // The line from (0, 0) to (1, 1) goes exactly along the edge between
+        // (1, 0) and (0, 1), passing through the corner shared by (0, 0),
+        // (1, 0) and (0, 1).
+        let mut hex_grid = HexGrid::new(4, 4);
+        assert!(can_see(&hex_grid, (0, 0), (1, 1)));
+
+        // A wall along the line doesn't block it, as we can see along both
+        // of its faces.
+        hex_grid.set_wall(1, 0, Direction::SouthWest, Some(Color::BLACK));
+        assert!(can_see(&hex_grid, (0, 0), (1, 1)));
+
+        // Nor does a wall just touching the line with its tip.
+        hex_grid.set_wall(1, 0, Direction::SouthWest, None);
+        hex_grid.set_wall(0, 0, Direction::East, Some(Color::BLACK));
+        assert!(can_see(&hex_grid, (0, 0), (1, 1)));
+
+        // But two walls meeting at that corner, from both sides of the line,
+        // do block it.
+        hex_grid.set_wall(0, 0, Direction::SouthEast, Some(Color::BLACK));
+        assert!(!can_see(&hex_grid, (0, 0), (1, 1)));
+    }
+}